@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use rslogo::{loader::Loader, Ast, Canvas, Interpreter, Limits};
+
+/// How programs are run and drawn, shared by every program in a batch
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Width of each image
+    pub width: u32,
+
+    /// Height of each image
+    pub height: u32,
+
+    /// Directories to search for files named by `LOAD`
+    pub include: Vec<PathBuf>,
+
+    /// Limits on the resources each program may use. Each program is limited on its own.
+    pub limits: Limits,
+
+    /// Scale each drawing to fill its image
+    pub fit: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            width: 500,
+            height: 500,
+            include: Vec::new(),
+            limits: Limits::default(),
+            fit: false,
+        }
+    }
+}
+
+/// What happened when one program in a batch was rendered
+#[derive(Debug)]
+pub struct Rendered {
+    /// The program which was run
+    pub source: PathBuf,
+
+    /// Where its image was saved, if it succeeded
+    pub image: PathBuf,
+
+    /// Whether the program loaded, ran and was saved, or the first error it hit
+    pub result: miette::Result<()>,
+}
+
+/// An error found while looking for the programs to render in a batch
+#[derive(thiserror::Error, Debug, miette::Diagnostic)]
+pub enum BatchError {
+    #[error("Failed to read {}", path.display())]
+    ReadDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to create {}", path.display())]
+    CreateDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// Every Logo program directly inside a directory, in order of their names
+pub fn find_programs(dir: &Path) -> Result<Vec<PathBuf>, BatchError> {
+    let read_error = |e: std::io::Error| BatchError::ReadDir {
+        path: dir.to_path_buf(),
+        source: e,
+    };
+    let mut programs: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(read_error)?
+        .map(|x| x.map(|x| x.path()))
+        .collect::<Result<Vec<PathBuf>, std::io::Error>>()
+        .map_err(read_error)?
+        .into_iter()
+        .filter(|x: &PathBuf| x.is_file() && x.extension().is_some_and(|x| x == "lg"))
+        .collect();
+    programs.sort();
+    Ok(programs)
+}
+
+/// Run every program on its own interpreter, in parallel, saving each drawing into `out` with
+/// the same name as its program. The extension of the images, such as `svg` or `png`, picks
+/// their format.
+pub fn render(
+    programs: &[PathBuf],
+    out: &Path,
+    extension: &str,
+    options: &RenderOptions,
+) -> Vec<Rendered> {
+    programs
+        .par_iter()
+        .map(|source: &PathBuf| {
+            let image: PathBuf = out
+                .join(source.file_stem().unwrap_or(source.as_os_str()))
+                .with_extension(extension);
+            let mut loader = Loader::new(options.include.clone());
+            let result: miette::Result<()> = render_file(&mut loader, source, &image, options);
+            Rendered {
+                source: source.clone(),
+                image,
+                result,
+            }
+        })
+        .collect()
+}
+
+/// Load a program with the given loader, run it, and save what it drew. The image is only saved
+/// if the program succeeds. Programs rendered this way can't read input, so `READWORD` and
+/// `READLIST` always find the input empty.
+pub fn render_file(
+    loader: &mut Loader,
+    source: &Path,
+    image: &Path,
+    options: &RenderOptions,
+) -> miette::Result<()> {
+    let ast: Ast = loader.load(source)?;
+    let mut interpreter: Interpreter = Interpreter::new(Canvas::new(options.width, options.height))
+        .with_limits(options.limits.clone())
+        .with_input(std::io::empty());
+    interpreter.run(&ast)?;
+    match options.fit {
+        true => interpreter.canvas().fit().save(image)?,
+        false => interpreter.canvas().save(image)?,
+    }
+    Ok(())
+}
+
+/// A line for each program saying whether it succeeded, along with the diagnostics of those
+/// which failed, followed by how many succeeded and failed
+pub fn summary(rendered: &[Rendered]) -> String {
+    let mut summary: String = String::new();
+    for x in rendered {
+        match &x.result {
+            Ok(()) => {
+                summary += &format!("ok     {} -> {}\n", x.source.display(), x.image.display())
+            }
+            Err(e) => summary += &format!("failed {}\n{e:?}\n", x.source.display()),
+        }
+    }
+    let failed: usize = rendered.iter().filter(|x| x.result.is_err()).count();
+    summary += &format!("{} succeeded, {failed} failed\n", rendered.len() - failed);
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_every_program() {
+        let programs = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        std::fs::write(programs.path().join("line.lg"), "PENDOWN\nFORWARD \"10\n").unwrap();
+        std::fs::write(programs.path().join("broken.lg"), "SETPENCOLOR \"20\n").unwrap();
+        std::fs::write(programs.path().join("notes.txt"), "not a program").unwrap();
+
+        let found = find_programs(programs.path()).unwrap();
+        assert_eq!(found.len(), 2);
+
+        let rendered = render(&found, out.path(), "svg", &RenderOptions::default());
+        assert!(rendered[0].result.is_err());
+        assert!(rendered[1].result.is_ok());
+        assert!(out.path().join("line.svg").exists());
+        assert!(!out.path().join("broken.svg").exists());
+        assert!(summary(&rendered).ends_with("1 succeeded, 1 failed\n"));
+    }
+}
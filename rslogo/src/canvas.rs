@@ -0,0 +1,465 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use unsvg::{Image, COLORS};
+
+use crate::{
+    errors::CanvasError,
+    plotter::{plot, PlotFormat},
+    space::{obj, Segment},
+};
+
+/// A single line drawn by the turtle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Line {
+    /// Where the line starts
+    pub start: (f64, f64),
+
+    /// Where the line ends
+    pub end: (f64, f64),
+
+    /// The direction the line was drawn in, in degrees clockwise from straight up
+    pub heading: f64,
+
+    /// How long the line is
+    pub length: f64,
+
+    /// The index of the line's colour in `unsvg::COLORS`
+    pub colour: usize,
+}
+
+/// Text written by the turtle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Label {
+    /// Where the text starts
+    pub position: (f64, f64),
+
+    /// The direction the text runs in, in degrees clockwise from straight up
+    pub heading: f64,
+
+    pub text: String,
+
+    /// The height of the text, in pixels
+    pub height: f64,
+
+    /// The font family of the text, such as `serif` or `monospace`
+    pub font: String,
+
+    /// The index of the text's colour in `unsvg::COLORS`
+    pub colour: usize,
+}
+
+impl Label {
+    /// The label as an SVG `<text>` element. Text normally runs to the right, which is a heading
+    /// of 90 degrees, so it is rotated by how far the heading is from that.
+    fn to_svg(&self) -> String {
+        let (x, y) = self.position;
+        let colour = COLORS[self.colour.min(COLORS.len() - 1)];
+        format!(
+            "<text x=\"{x}\" y=\"{y}\" font-family=\"{}\" font-size=\"{}\" fill=\"#{:02x}{:02x}{:02x}\" transform=\"rotate({} {x} {y})\">{}</text>",
+            escape_xml(&self.font),
+            self.height,
+            colour.red,
+            colour.green,
+            colour.blue,
+            self.heading - 90.0,
+            escape_xml(&self.text)
+        )
+    }
+}
+
+/// Escape text so it can be written inside an XML element or attribute
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// What happens when the turtle reaches the edge of the canvas, as in UCBLogo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Boundary {
+    /// The turtle may leave the canvas, and anything drawn outside of it is cut off
+    #[default]
+    Window,
+
+    /// The turtle reappears at the opposite edge, carrying on in the same direction
+    Wrap,
+
+    /// The turtle can't leave the canvas, and moving outside of it is an error
+    Fence,
+}
+
+/// The space left around a drawing when it is scaled to fit the canvas, in pixels
+const FIT_MARGIN: f64 = 10.0;
+
+/// Everything the turtle has drawn so far. Lines are kept as a list rather than drawn straight
+/// onto an image, so the drawing can be inspected or rendered to any format afterwards.
+///
+/// Positions are in pixels from the top left corner of the image, so `y` grows downwards, and a
+/// heading of `0` points straight up. The turtle starts in the middle of the canvas.
+///
+/// When the turtle moves in 3D, the lines are where its path appears on the canvas, and the path
+/// itself is kept as segments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    lines: Vec<Line>,
+    labels: Vec<Label>,
+    #[serde(default)]
+    segments: Vec<Segment>,
+}
+
+impl Canvas {
+    /// Create an empty canvas with the given dimensions
+    pub fn new(width: u32, height: u32) -> Self {
+        Canvas {
+            width,
+            height,
+            lines: Vec::new(),
+            labels: Vec::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    /// Get the size of the canvas as a tuple of (width, height)
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Every line drawn so far, in the order they were drawn
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Whether a point is on the canvas, including its edges
+    pub fn contains(&self, (x, y): (f64, f64)) -> bool {
+        (0.0..=self.width as f64).contains(&x) && (0.0..=self.height as f64).contains(&y)
+    }
+
+    /// Move a point off the canvas back onto it, as if the canvas repeated in every direction
+    pub fn wrap_point(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        if self.width == 0 || self.height == 0 {
+            return (x, y);
+        }
+        (
+            x.rem_euclid(self.width as f64),
+            y.rem_euclid(self.height as f64),
+        )
+    }
+
+    /// Cut a line which leaves the canvas into pieces, so each time it crosses an edge, it carries
+    /// on from the opposite edge. The last piece ends where the turtle ends up.
+    pub fn wrap_line(&self, line: &Line) -> Vec<Line> {
+        if self.width == 0 || self.height == 0 {
+            return vec![line.clone()];
+        }
+        let (width, height) = (self.width as f64, self.height as f64);
+        let delta: (f64, f64) = (line.end.0 - line.start.0, line.end.1 - line.start.1);
+
+        // How far along the line, from 0 to 1, to go from the given position to the next edge
+        let to_edge = |position: f64, delta: f64, size: f64| match delta {
+            d if d > 0.0 => (size - position) / d,
+            d if d < 0.0 => -position / d,
+            _ => f64::INFINITY,
+        };
+
+        let mut lines: Vec<Line> = Vec::new();
+        let mut start: (f64, f64) = self.wrap_point(line.start);
+        let mut remaining: f64 = 1.0;
+        while remaining > 0.0 {
+            let to_x: f64 = to_edge(start.0, delta.0, width);
+            let to_y: f64 = to_edge(start.1, delta.1, height);
+            let step: f64 = to_x.min(to_y).min(remaining);
+            let end: (f64, f64) = (start.0 + delta.0 * step, start.1 + delta.1 * step);
+            if step > 0.0 {
+                lines.push(Line {
+                    start,
+                    end,
+                    length: line.length * step,
+                    ..line.clone()
+                });
+            }
+            remaining -= step;
+            if remaining <= 0.0 {
+                break;
+            }
+
+            // Carry on from the opposite side of whichever edges were reached
+            start = end;
+            if to_x <= step {
+                start.0 = if delta.0 > 0.0 { 0.0 } else { width };
+            }
+            if to_y <= step {
+                start.1 = if delta.1 > 0.0 { 0.0 } else { height };
+            }
+        }
+        lines
+    }
+
+    /// Scale and move the drawing so that it fills the canvas, keeping its shape, with a small
+    /// margin around it
+    pub fn fit(&self) -> Canvas {
+        let points = self
+            .lines
+            .iter()
+            .flat_map(|x: &Line| [x.start, x.end])
+            .chain(self.labels.iter().map(|x: &Label| x.position));
+        let Some((min, max)) = points.fold(None, |bounds, (x, y)| match bounds {
+            None => Some(((x, y), (x, y))),
+            Some((min, max)) => Some((
+                (f64::min(min.0, x), f64::min(min.1, y)),
+                (f64::max(max.0, x), f64::max(max.1, y)),
+            )),
+        }) else {
+            return self.clone();
+        };
+
+        let margin: f64 = FIT_MARGIN.min(self.width.min(self.height) as f64 / 4.0);
+        let available: (f64, f64) = (
+            self.width as f64 - 2.0 * margin,
+            self.height as f64 - 2.0 * margin,
+        );
+        let size: (f64, f64) = (max.0 - min.0, max.1 - min.1);
+        let scale: f64 = match (size.0 > 0.0, size.1 > 0.0) {
+            (true, true) => f64::min(available.0 / size.0, available.1 / size.1),
+            (true, false) => available.0 / size.0,
+            (false, true) => available.1 / size.1,
+            // A drawing of a single point can only be moved, not scaled
+            (false, false) => 1.0,
+        };
+
+        // Centre the drawing in the canvas
+        let offset: (f64, f64) = (
+            (self.width as f64 - size.0 * scale) / 2.0 - min.0 * scale,
+            (self.height as f64 - size.1 * scale) / 2.0 - min.1 * scale,
+        );
+        let transform = |(x, y): (f64, f64)| (x * scale + offset.0, y * scale + offset.1);
+
+        Canvas {
+            width: self.width,
+            height: self.height,
+            lines: self
+                .lines
+                .iter()
+                .map(|x: &Line| Line {
+                    start: transform(x.start),
+                    end: transform(x.end),
+                    length: x.length * scale,
+                    ..x.clone()
+                })
+                .collect(),
+            labels: self
+                .labels
+                .iter()
+                .map(|x: &Label| Label {
+                    position: transform(x.position),
+                    height: x.height * scale,
+                    ..x.clone()
+                })
+                .collect(),
+            // The path in 3D is exported as it was drawn, so only its projection is fit
+            segments: self.segments.clone(),
+        }
+    }
+
+    /// Every label written so far, in the order they were written
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    pub fn draw(&mut self, line: Line) {
+        self.lines.push(line);
+    }
+
+    pub fn write(&mut self, label: Label) {
+        self.labels.push(label);
+    }
+
+    /// Every segment drawn in 3D so far, in the order they were drawn
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn draw_3d(&mut self, segment: Segment) {
+        self.segments.push(segment);
+    }
+
+    /// Render every line drawn so far onto an image. Labels can only be drawn in SVGs, so they
+    /// aren't included.
+    pub fn to_image(&self) -> Result<Image, CanvasError> {
+        let mut image: Image = Image::new(self.width, self.height);
+        for line in &self.lines {
+            let colour = COLORS[line.colour.min(COLORS.len() - 1)];
+            image
+                .draw_simple_line(
+                    line.start.0 as f32,
+                    line.start.1 as f32,
+                    // `unsvg` only draws in whole degrees
+                    line.heading.round() as i32,
+                    line.length as f32,
+                    colour,
+                )
+                .map_err(|e| CanvasError::Render(e.to_string()))?;
+        }
+        Ok(image)
+    }
+
+    /// Save the canvas as an SVG or PNG, as G-code or HPGL for a pen plotter, or as the segments
+    /// drawn in 3D in an OBJ file, depending on the extension of the path. Labels are only saved
+    /// in SVGs, as `<text>` elements.
+    pub fn save(&self, path: &Path) -> Result<(), CanvasError> {
+        let result = match path.extension().and_then(|x| x.to_str()) {
+            Some("svg") => self
+                .to_image()?
+                .save_svg(path)
+                .map_err(|e| e.to_string())
+                .and_then(|_| self.write_labels(path).map_err(|e| e.to_string())),
+            Some("png") => self.to_image()?.save_png(path).map_err(|e| e.to_string()),
+            Some("gcode" | "nc") => {
+                std::fs::write(path, plot(self, PlotFormat::GCode)).map_err(|e| e.to_string())
+            }
+            Some("hpgl" | "plt") => {
+                std::fs::write(path, plot(self, PlotFormat::Hpgl)).map_err(|e| e.to_string())
+            }
+            Some("obj") => std::fs::write(path, obj(&self.segments)).map_err(|e| e.to_string()),
+            _ => return Err(CanvasError::UnsupportedFormat(path.to_path_buf())),
+        };
+        result.map_err(|e| CanvasError::Save(path.to_path_buf(), e))
+    }
+
+    /// Add the labels to an SVG saved by `unsvg`, which can't draw text itself
+    fn write_labels(&self, path: &Path) -> std::io::Result<()> {
+        if self.labels.is_empty() {
+            return Ok(());
+        }
+        let svg: String = std::fs::read_to_string(path)?;
+        let end: usize = svg.rfind("</svg>").unwrap_or(svg.len());
+        let labels: String = self
+            .labels
+            .iter()
+            .map(|x: &Label| format!("    {}\n", x.to_svg()))
+            .collect();
+        std::fs::write(path, format!("{}{labels}{}", &svg[..end], &svg[end..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{errors::TurtleError, test_support::draw, InterpreterError};
+
+    #[test]
+    fn labels_are_saved_as_text() {
+        let (result, canvas) = draw(
+            "SETLABELHEIGHT \"20\nSETFONT \"serif\nSETHEADING \"90\nSETPENCOLOR \"4\nLABEL \"a<b\n",
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            canvas.labels(),
+            &[Label {
+                position: (50.0, 50.0),
+                heading: 90.0,
+                text: "a<b".to_string(),
+                height: 20.0,
+                font: "serif".to_string(),
+                colour: 4,
+            }]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("labels.svg");
+        canvas.save(&path).unwrap();
+        let svg = std::fs::read_to_string(path).unwrap();
+        assert!(svg.contains(
+            "<text x=\"50\" y=\"50\" font-family=\"serif\" font-size=\"20\" fill=\"#ff0000\" transform=\"rotate(0 50 50)\">a&lt;b</text>\n</svg>"
+        ));
+    }
+
+    #[test]
+    fn wrapping_carries_on_from_the_opposite_edge() {
+        let (result, canvas) = draw("WRAP\nPENDOWN\nFORWARD \"170\n");
+        assert_eq!(result, Ok(()));
+        let round =
+            |(x, y): (f64, f64)| ((x * 1000.0).round() / 1000.0, (y * 1000.0).round() / 1000.0);
+        let ends: Vec<((f64, f64), (f64, f64))> = canvas
+            .lines()
+            .iter()
+            .map(|x| (round(x.start), round(x.end)))
+            .collect();
+        assert_eq!(
+            ends,
+            vec![
+                ((50.0, 50.0), (50.0, 0.0)),
+                ((50.0, 100.0), (50.0, 0.0)),
+                ((50.0, 100.0), (50.0, 80.0))
+            ]
+        );
+
+        let (_, canvas) = draw("WRAP\nSETX \"250\nPENDOWN\nFORWARD \"0\n");
+        assert_eq!(canvas.lines()[0].start, (50.0, 50.0));
+    }
+
+    #[test]
+    fn fences_keep_the_turtle_on_the_canvas() {
+        let (result, canvas) = draw("FENCE\nPENDOWN\nFORWARD \"20\nFORWARD \"40\n");
+        assert_eq!(
+            result,
+            Err(InterpreterError::Turtle(TurtleError::InvalidCoordinates(
+                50.0, -10.0
+            )))
+        );
+        assert_eq!(canvas.lines().len(), 1);
+
+        let (result, _) = draw("SETX \"-5\nFENCE\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn drawings_can_be_fit_to_the_canvas() {
+        let (_, canvas) = draw("PENDOWN\nFORWARD \"10\nRIGHT \"20\n");
+        let fitted = canvas.fit();
+        let ends: Vec<((f64, f64), (f64, f64))> =
+            fitted.lines().iter().map(|x| (x.start, x.end)).collect();
+        assert_eq!(
+            ends,
+            vec![((10.0, 70.0), (10.0, 30.0)), ((10.0, 30.0), (90.0, 30.0))]
+        );
+        assert_eq!(fitted.lines()[1].length, 80.0);
+    }
+
+    #[test]
+    fn images_round_headings_to_the_nearest_degree() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.draw(Line {
+            start: (0.0, 50.0),
+            end: (100.0, 50.7),
+            heading: 89.6,
+            length: 100.0,
+            colour: 0,
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("line.svg");
+        canvas.save(&path).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("M 0 50 L 100 50"), "{svg}");
+    }
+
+    #[test]
+    fn only_images_are_rendered_when_saving() {
+        // `unsvg` can't draw a line which never ends, but it needn't be drawn to save a plot
+        let mut canvas = Canvas::new(100, 100);
+        canvas.draw(Line {
+            start: (50.0, 50.0),
+            end: (50.0, f64::INFINITY),
+            heading: 180.0,
+            length: f64::INFINITY,
+            colour: 0,
+        });
+        let dir = tempfile::tempdir().unwrap();
+        assert!(canvas.save(&dir.path().join("line.svg")).is_err());
+        assert!(canvas.save(&dir.path().join("line.gcode")).is_ok());
+    }
+}
@@ -0,0 +1,252 @@
+use std::io::{BufRead, Write};
+
+use rslogo::{
+    errors::InterpreterError,
+    observer::{turtle_state, Observer},
+    Command, EvalResult, Location, Program,
+};
+
+const HELP: &str = "\
+commands:
+  step, s              run the next command, stepping into procedures
+  next, n              run the next command, stepping over procedures
+  continue, c          run until the next breakpoint
+  break, b <line>      stop before any command on the given line
+  break, b <name>      stop before any call to the named procedure
+  delete, d            remove every breakpoint
+  print, p <name>      show the value of a variable
+  vars                 show every variable
+  turtle               show the state of the turtle
+  quit, q              stop the program";
+
+/// Where the debugger should stop next
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    /// Any command on the given line
+    Line(usize),
+
+    /// Any call to the named procedure
+    Procedure(String),
+}
+
+/// How the program should run until the debugger next stops
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// Stop before the next command
+    Step,
+
+    /// Stop before the next command which isn't inside a deeper procedure call
+    Next(usize),
+
+    /// Only stop at a breakpoint
+    Continue,
+}
+
+/// What to do after the user types a command
+enum Action {
+    /// Carry on running the program
+    Resume(Mode),
+
+    /// Wait for another command
+    Prompt,
+
+    /// Stop the program
+    Quit,
+}
+
+/// An interactive debugger, which stops before commands to let the user look around. Commands are
+/// read from `input`, one per line, and everything is written to `output`. The debugger stops
+/// before the first command, so breakpoints can be set.
+pub struct Debugger<R, W> {
+    input: R,
+    output: W,
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+}
+
+impl<R: BufRead + Send, W: Write + Send> Debugger<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Debugger {
+            input,
+            output,
+            breakpoints: Vec::new(),
+            mode: Mode::Step,
+        }
+    }
+
+    /// Whether the debugger should stop before the given command
+    fn should_stop(&self, program: &Program, location: &Location, command: &Command) -> bool {
+        let at_breakpoint = || {
+            self.breakpoints.iter().any(|x| match x {
+                Breakpoint::Line(line) => *line == location.line,
+                Breakpoint::Procedure(name) => {
+                    matches!(command.without_location(), Command::ProcedureExec(x, _) if x == name)
+                }
+            })
+        };
+        match self.mode {
+            Mode::Step => true,
+            Mode::Next(depth) => program.depth() <= depth || at_breakpoint(),
+            Mode::Continue => at_breakpoint(),
+        }
+    }
+
+    /// Run a single command typed by the user
+    fn command(&mut self, program: &Program, line: &str) -> std::io::Result<Action> {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("step" | "s"), None) => return Ok(Action::Resume(Mode::Step)),
+            (Some("next" | "n"), None) => return Ok(Action::Resume(Mode::Next(program.depth()))),
+            (Some("continue" | "c"), None) => return Ok(Action::Resume(Mode::Continue)),
+            (Some("break" | "b"), Some(target)) => {
+                let breakpoint: Breakpoint = match target.parse::<usize>() {
+                    Ok(line) => Breakpoint::Line(line),
+                    Err(_) => Breakpoint::Procedure(target.to_string()),
+                };
+                writeln!(self.output, "breakpoint set: {breakpoint:?}")?;
+                self.breakpoints.push(breakpoint);
+            }
+            (Some("delete" | "d"), None) => {
+                self.breakpoints.clear();
+                writeln!(self.output, "every breakpoint removed")?;
+            }
+            (Some("print" | "p"), Some(name)) => match program.variables().get(name) {
+                Some(value) => writeln!(self.output, "{name} = {value}")?,
+                None => writeln!(self.output, "{name} is not defined")?,
+            },
+            (Some("vars"), None) => {
+                let mut variables: Vec<(&String, &EvalResult)> =
+                    program.variables().iter().collect();
+                variables.sort_by_key(|(name, _)| *name);
+                for (name, value) in variables {
+                    writeln!(self.output, "{name} = {value}")?;
+                }
+            }
+            (Some("turtle"), None) => writeln!(self.output, "{}", turtle_state(program))?,
+            (Some("quit" | "q"), None) => return Ok(Action::Quit),
+            _ => writeln!(self.output, "{HELP}")?,
+        }
+        Ok(Action::Prompt)
+    }
+
+    /// Stop the program, and read commands until the user asks for it to carry on
+    fn prompt(&mut self, program: &Program, location: &Location) -> std::io::Result<Action> {
+        writeln!(
+            self.output,
+            "{}:{}: {}",
+            location.file_name(),
+            location.line,
+            location.source_line().trim()
+        )?;
+        loop {
+            write!(self.output, "(rslogo) ")?;
+            self.output.flush()?;
+
+            let mut line: String = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                // Once there is nothing left to read, let the program run to completion
+                self.breakpoints.clear();
+                return Ok(Action::Resume(Mode::Continue));
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match self.command(program, &line)? {
+                Action::Prompt => (),
+                action => return Ok(action),
+            }
+        }
+    }
+}
+
+impl<R: BufRead + Send, W: Write + Send> Observer for Debugger<R, W> {
+    fn before(
+        &mut self,
+        program: &Program,
+        location: &Location,
+        command: &Command,
+    ) -> Result<(), InterpreterError> {
+        if !self.should_stop(program, location, command) {
+            return Ok(());
+        }
+        match self.prompt(program, location) {
+            Ok(Action::Resume(mode)) => {
+                self.mode = mode;
+                Ok(())
+            }
+            Ok(Action::Prompt) => unreachable!("the prompt only returns to resume or quit"),
+            Ok(Action::Quit) | Err(_) => Err(InterpreterError::Stopped),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rslogo::{Canvas, Interpreter};
+    use std::io::{Read, Seek};
+
+    /// Run a program in the debugger with the given commands typed in, returning what it wrote
+    fn debug(source: &str, input: &str) -> (Result<(), InterpreterError>, String) {
+        // The debugger keeps its output, so it writes to a file which is read back afterwards
+        let mut output = tempfile::tempfile().unwrap();
+        let input = std::io::Cursor::new(input.to_string());
+        let debugger = Debugger::new(input, output.try_clone().unwrap());
+        let mut interpreter = Interpreter::new(Canvas::new(100, 100)).with_observer(debugger);
+        let ast = interpreter.parse(source).unwrap();
+        let result = interpreter.run(&ast);
+
+        let mut written: String = String::new();
+        output.rewind().unwrap();
+        output.read_to_string(&mut written).unwrap();
+        (result, written)
+    }
+
+    const PROGRAM: &str = "\
+TO Step
+  MAKE \"y \"5
+END
+MAKE \"x \"1
+Step
+MAKE \"x \"2
+";
+
+    #[test]
+    fn breakpoints_by_line_and_procedure() {
+        let (result, output) = debug(PROGRAM, "b 6\nc\np x\nc\n");
+        assert_eq!(result, Ok(()));
+        assert!(output.contains("input:6: MAKE \"x \"2\n(rslogo) x = 1\n"));
+
+        let (_, output) = debug(PROGRAM, "b Step\nc\nvars\nq\n");
+        assert!(output.contains("input:5: Step\n(rslogo) x = 1\n"));
+    }
+
+    #[test]
+    fn next_steps_over_procedures() {
+        let (_, output) = debug(PROGRAM, "n\nn\nn\nn\nq\n");
+        let stops: Vec<&str> = output
+            .lines()
+            .filter(|x| x.starts_with("input:") || x.starts_with("(rslogo) input:"))
+            .map(|x| x.trim_start_matches("(rslogo) "))
+            .collect();
+        assert_eq!(
+            stops,
+            vec![
+                "input:1: TO Step",
+                "input:4: MAKE \"x \"1",
+                "input:5: Step",
+                "input:6: MAKE \"x \"2",
+            ]
+        );
+
+        let (_, output) = debug(PROGRAM, "n\nn\ns\ns\nq\n");
+        assert!(output.contains("input:2: MAKE \"y \"5"));
+    }
+
+    #[test]
+    fn quitting_stops_the_program() {
+        let (result, _) = debug(PROGRAM, "q\n");
+        assert_eq!(result, Err(InterpreterError::Stopped));
+    }
+}
@@ -1,68 +1,375 @@
-use nom_locate::LocatedSpan;
-use nom_supreme::error::{BaseErrorKind, ErrorTree, GenericErrorTree, StackContext};
+use std::{path::PathBuf, sync::Arc};
 
-/// Convenient type alias
-pub type Span<'a> = LocatedSpan<&'a str>;
+use miette::NamedSource;
+use nom_supreme::error::{BaseErrorKind, ErrorTree, Expectation, GenericErrorTree, StackContext};
+
+use crate::{lexer::Token, parsers::Tokens};
+
+/// The text of a program, along with the name of the file it came from. Every error found in a
+/// file shares the same copy of it.
+pub type SourceFile = Arc<NamedSource<String>>;
+
+/// Create a `SourceFile` with the given name
+pub fn source_file(name: impl AsRef<str>, text: impl Into<String>) -> SourceFile {
+    Arc::new(NamedSource::new(name, text.into()))
+}
+
+/// Every parse error found in a file. The parser recovers after each syntax error, so a single
+/// run can report more than one of them.
+#[derive(thiserror::Error, Debug, miette::Diagnostic)]
+#[error("Failed to parse program ({} error(s))", errors.len())]
+pub struct Diagnostics {
+    #[related]
+    pub(crate) errors: Vec<ParseError>,
+}
+
+/// An error found while loading a program, or one of the files it loads
+#[derive(thiserror::Error, Debug, miette::Diagnostic)]
+#[non_exhaustive]
+pub enum LoadError {
+    #[error("Failed to read {}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not find {name:?} to load")]
+    #[diagnostic(help("searched in {}", searched.iter().map(|x| x.display().to_string()).collect::<Vec<_>>().join(", ")))]
+    NotFound {
+        name: String,
+        searched: Vec<PathBuf>,
+        #[source_code]
+        src: SourceFile,
+        #[label("loaded here")]
+        span: miette::SourceSpan,
+    },
+
+    #[error("{name:?} loads itself ({})", chain.iter().map(|x| x.display().to_string()).collect::<Vec<_>>().join(" -> "))]
+    Cycle {
+        name: String,
+        chain: Vec<PathBuf>,
+        #[source_code]
+        src: SourceFile,
+        #[label("loaded again here")]
+        span: miette::SourceSpan,
+    },
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Parse(#[from] Diagnostics),
+}
+
+/// An error found while replaying an event log
+#[derive(thiserror::Error, Debug, miette::Diagnostic)]
+#[non_exhaustive]
+pub enum ReplayError {
+    #[error("Failed to read the event log")]
+    Io(#[from] std::io::Error),
+
+    #[error("Line {line} of the event log is not a valid event")]
+    Invalid {
+        line: usize,
+        source: serde_json::Error,
+    },
+
+    #[error("The event log doesn't start with the size of the canvas")]
+    MissingCanvas,
+
+    #[error("Line {0} of the event log starts a second canvas")]
+    #[diagnostic(help("each log can only hold one run of a program"))]
+    SecondCanvas(usize),
+}
+
+/// An error found while saving or loading the state of a program
+#[derive(thiserror::Error, Debug, miette::Diagnostic)]
+#[non_exhaustive]
+pub enum StateError {
+    #[error("Failed to read or write {}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{} is not a saved state", path.display())]
+    Invalid {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+/// Programs save and load their own states, so failing to stops the program
+impl From<StateError> for InterpreterError {
+    fn from(e: StateError) -> Self {
+        let cause: String = std::error::Error::source(&e)
+            .map(|x| format!(": {x}"))
+            .unwrap_or_default();
+        InterpreterError::State(format!("{e}{cause}"))
+    }
+}
+
+/// An error found while reading or drawing an L-system
+#[derive(thiserror::Error, Debug, miette::Diagnostic)]
+#[non_exhaustive]
+pub enum LSystemError {
+    #[error("Failed to read {}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid grammar: {0}")]
+    Grammar(String),
+
+    #[error("Expanding the axiom {iterations} times would make {length} symbols")]
+    #[diagnostic(help("use fewer iterations, or raise the limit of {max} symbols"))]
+    TooLong {
+        iterations: usize,
+        length: usize,
+        max: usize,
+    },
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Interpreter(#[from] InterpreterError),
+}
+
+/// Something in a program which can't be written in the language it is being transpiled to
+#[derive(thiserror::Error, Debug, miette::Diagnostic)]
+#[non_exhaustive]
+pub enum TranspileError {
+    #[error("{what} can't be transpiled to {target}")]
+    #[diagnostic(help("run the program with rslogo instead"))]
+    Unsupported {
+        what: String,
+        target: crate::transpile::Target,
+        #[source_code]
+        src: Option<SourceFile>,
+        #[label("used here")]
+        span: Option<miette::SourceSpan>,
+    },
+
+    #[error("Procedure not found: {name}")]
+    UndefinedProcedure {
+        name: String,
+        #[source_code]
+        src: Option<SourceFile>,
+        #[label("called here")]
+        span: Option<miette::SourceSpan>,
+    },
+
+    #[error("{name} is defined more than once")]
+    #[diagnostic(help(
+        "each procedure becomes a single function, so it can only be defined once"
+    ))]
+    Redefined {
+        name: String,
+        #[source_code]
+        src: Option<SourceFile>,
+        #[label("defined again here")]
+        span: Option<miette::SourceSpan>,
+    },
+
+    #[error("{name} takes {expected} input(s), but was given {given}")]
+    Arity {
+        name: String,
+        expected: usize,
+        given: usize,
+        #[source_code]
+        src: Option<SourceFile>,
+        #[label("called here")]
+        span: Option<miette::SourceSpan>,
+    },
+}
 
 #[derive(thiserror::Error, Debug, miette::Diagnostic)]
 #[error("Parse error")]
-pub struct ParseError<'b> {
+pub struct ParseError {
     #[source_code]
-    src: &'b str,
+    pub(crate) src: SourceFile,
 
     #[label("{kind}")]
-    span: miette::SourceSpan,
+    pub(crate) span: miette::SourceSpan,
 
-    kind: BaseErrorKind<&'b str, Box<dyn std::error::Error + Send + Sync + 'static>>,
+    pub(crate) kind: ParseErrorKind,
+
+    #[help]
+    pub(crate) help: Option<String>,
 
     #[related]
-    others: Vec<ParseErrorContext<'b>>,
+    pub(crate) others: Vec<ParseErrorContext>,
+}
+
+impl Diagnostics {
+    /// Every error found, in the order they appear in the file
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+}
+
+impl ParseError {
+    /// Create an error at the given token which was not found by nom, such as a missing delimiter.
+    pub(crate) fn new(src: &SourceFile, token: &Token, kind: ParseErrorKind) -> Self {
+        let help = match kind {
+            ParseErrorKind::ExpectedCommand => Some(
+                "commands start with a keyword such as `FORWARD`, or the name of a procedure"
+                    .to_string(),
+            ),
+            ParseErrorKind::UnclosedBracket => Some("add a `]` to close this block".to_string()),
+            ParseErrorKind::UnexpectedClosingBracket => {
+                Some("remove this `]`, or add the `[` it was meant to close".to_string())
+            }
+            ParseErrorKind::MissingEnd => {
+                Some("add an `END` after the body of this procedure".to_string())
+            }
+            ParseErrorKind::UnexpectedEnd => {
+                Some("remove this `END`, or add the `TO` it was meant to close".to_string())
+            }
+            ParseErrorKind::PrimitiveName(_) => {
+                Some("choose another name for this procedure".to_string())
+            }
+            ParseErrorKind::Syntax(_) | ParseErrorKind::ExpectedOneOf(_) => None,
+        };
+        ParseError {
+            src: src.clone(),
+            span: miette::SourceSpan::new(token.offset.into(), token.text.len()),
+            kind,
+            help,
+            others: Vec::new(),
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.span.offset()
+    }
+}
+
+/// The reason a parse failed
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// A nom parser failed
+    #[error("{0}")]
+    Syntax(BaseErrorKind<&'static str, Box<dyn std::error::Error + Send + Sync + 'static>>),
+
+    #[error("expected a command")]
+    ExpectedCommand,
+
+    #[error("expected one of {}", .0.join(", "))]
+    ExpectedOneOf(Vec<&'static str>),
+
+    #[error("unclosed `[`")]
+    UnclosedBracket,
+
+    #[error("unexpected `]`")]
+    UnexpectedClosingBracket,
+
+    #[error("procedure is missing its `END`")]
+    MissingEnd,
+
+    #[error("`END` without a matching `TO`")]
+    UnexpectedEnd,
+
+    #[error("`{0}` is a primitive, so it can't be the name of a procedure")]
+    PrimitiveName(String),
+}
+
+impl ParseErrorKind {
+    /// A name for the kind of error, for tools which read errors
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParseErrorKind::Syntax(_) => "syntax",
+            ParseErrorKind::ExpectedCommand => "expected_command",
+            ParseErrorKind::ExpectedOneOf(_) => "expected_one_of",
+            ParseErrorKind::UnclosedBracket => "unclosed_bracket",
+            ParseErrorKind::UnexpectedClosingBracket => "unexpected_closing_bracket",
+            ParseErrorKind::MissingEnd => "missing_end",
+            ParseErrorKind::UnexpectedEnd => "unexpected_end",
+            ParseErrorKind::PrimitiveName(_) => "primitive_name",
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug, miette::Diagnostic)]
 #[error("Parse error context")]
-pub struct ParseErrorContext<'b> {
+pub struct ParseErrorContext {
     #[source_code]
-    src: &'b str,
+    pub(crate) src: SourceFile,
     #[label("{context}")]
-    span: miette::SourceSpan,
-    context: StackContext<&'b str>,
+    pub(crate) span: miette::SourceSpan,
+    pub(crate) context: StackContext<&'static str>,
 }
 
-pub fn format_parse_error<'a>(input: &'a str, e: ErrorTree<Span<'a>>) -> ParseError<'a> {
-    match e {
-        GenericErrorTree::Base { location, kind } => {
-            let offset = location.location_offset().into();
-            ParseError {
-                src: input,
-                span: miette::SourceSpan::new(offset, 1_u8.into()),
-                kind,
-                others: Vec::new(),
-            }
+/// Find the span covered by the first of the given tokens. If there are no tokens left, the
+/// error is at the end of the input, so we point at the last character of the program instead.
+fn token_span(input: &str, location: Tokens) -> miette::SourceSpan {
+    match location.first() {
+        Some(token) => miette::SourceSpan::new(token.offset.into(), token.text.len()),
+        None => {
+            let end: usize = input.trim_end().len();
+            let last_char: usize = input[..end].chars().next_back().map_or(0, char::len_utf8);
+            miette::SourceSpan::new((end - last_char).into(), last_char)
         }
+    }
+}
+
+pub(crate) fn format_parse_error(src: &SourceFile, e: ErrorTree<Tokens>) -> ParseError {
+    match e {
+        GenericErrorTree::Base { location, kind } => ParseError {
+            src: src.clone(),
+            span: token_span(src.inner(), location),
+            kind: ParseErrorKind::Syntax(kind),
+            help: None,
+            others: Vec::new(),
+        },
         GenericErrorTree::Stack { base, contexts } => {
-            let mut base = format_parse_error(input, *base);
+            let mut base = format_parse_error(src, *base);
             let mut contexts: Vec<ParseErrorContext> = contexts
                 .into_iter()
-                .map(|(location, context)| {
-                    let offset = location.location_offset().into();
-                    ParseErrorContext {
-                        src: input,
-                        span: miette::SourceSpan::new(offset, 1_u8.into()),
-                        context,
-                    }
+                .map(|(location, context)| ParseErrorContext {
+                    src: src.clone(),
+                    span: token_span(src.inner(), location),
+                    context,
                 })
                 .collect();
             base.others.append(&mut contexts);
             base
         }
         GenericErrorTree::Alt(alt_errors) => {
-            // get the error with the most context
-            // TODO: figure out what to do on ties
+            let mut alt_errors: Vec<ParseError> = alt_errors
+                .into_iter()
+                .map(|e| format_parse_error(src, e))
+                .collect();
+            let furthest: usize = alt_errors.iter().map(|x| x.offset()).max().unwrap_or(0);
+
+            // If every branch failed on the same token without getting anywhere, none of them is
+            // more likely to be what was intended, so list what each of them expected instead.
+            let expected: Vec<Vec<&'static str>> = alt_errors
+                .iter()
+                .filter_map(|x| match &x.kind {
+                    ParseErrorKind::Syntax(BaseErrorKind::Expected(Expectation::Tag(tag))) => {
+                        Some(vec![*tag])
+                    }
+                    // A nested `alt` which failed the same way has already listed them
+                    ParseErrorKind::ExpectedOneOf(tags) => Some(tags.clone()),
+                    _ => None,
+                })
+                .collect();
+            if alt_errors.len() > 1
+                && expected.len() == alt_errors.len()
+                && alt_errors.iter().all(|x| x.offset() == furthest)
+            {
+                let mut first: ParseError = alt_errors.swap_remove(0);
+                first.kind = ParseErrorKind::ExpectedOneOf(expected.concat());
+                first.others.clear();
+                return first;
+            }
+
+            // Otherwise, get the error from the branch which got furthest into the input, as that
+            // is the one which most likely matched the intended syntax. On ties, get the one with
+            // the most context.
             alt_errors
                 .into_iter()
-                .map(|e| format_parse_error(input, e))
-                .max_by_key(|formatted| formatted.others.len())
+                .max_by_key(|formatted| (formatted.offset(), formatted.others.len()))
                 .unwrap()
         }
     }
@@ -70,10 +377,21 @@ pub fn format_parse_error<'a>(input: &'a str, e: ErrorTree<Span<'a>>) -> ParseEr
 
 // TOKEN ERRORS
 #[derive(thiserror::Error, miette::Diagnostic, Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum InterpreterError {
     #[error("Variable not found: {0}")]
     UndefinedVariable(String),
 
+    #[error("Primitive not found: {0}")]
+    UndefinedPrimitive(String),
+
+    #[error("Property not found: {1} in {0}")]
+    #[diagnostic(help("set the property with `PPROP` before reading it"))]
+    UndefinedProperty(String, String),
+
+    #[error("{0} did not output a value")]
+    NoOutput(String),
+
     #[error("Division by zero")]
     DivisionByZero,
 
@@ -85,6 +403,45 @@ pub enum InterpreterError {
 
     #[error("Unsuccessful operation: {0}")]
     UnsuccessfulOperation(String),
+
+    #[error("Exceeded the limit of {0} executed commands")]
+    CommandLimitExceeded(u64),
+
+    #[error("Exceeded the time limit of {0:?}")]
+    TimeLimitExceeded(std::time::Duration),
+
+    #[error("Exceeded the limit of {0} drawn lines")]
+    SegmentLimitExceeded(usize),
+
+    #[error("Exceeded the limit of {0} bytes of variables")]
+    MemoryLimitExceeded(usize),
+
+    #[error("Exceeded the limit of {0} nested procedure calls")]
+    DepthLimitExceeded(usize),
+
+    #[error("Program stopped by the debugger")]
+    Stopped,
+
+    #[error("Failed to read or write: {0}")]
+    Io(String),
+
+    #[error("Could not run [{0}]: {1}")]
+    InvalidList(String, String),
+
+    #[error("{0}")]
+    State(String),
+
+    #[error("The saved state is from a different program")]
+    #[diagnostic(help("resume the state with the program which saved it"))]
+    StateMismatch,
+
+    #[error("{0} only works when the turtle moves in 3D")]
+    #[diagnostic(help("run the program in 3D, such as with the `--3d` flag"))]
+    Needs3d(String),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Turtle(#[from] TurtleError),
 }
 
 impl InterpreterError {
@@ -92,6 +449,14 @@ impl InterpreterError {
         InterpreterError::UndefinedVariable(name.into())
     }
 
+    pub fn undefined_primitive(name: &str) -> Self {
+        InterpreterError::UndefinedPrimitive(name.into())
+    }
+
+    pub fn no_output(name: &str) -> Self {
+        InterpreterError::NoOutput(name.into())
+    }
+
     pub fn division_by_zero() -> Self {
         InterpreterError::DivisionByZero
     }
@@ -107,14 +472,72 @@ impl InterpreterError {
     pub fn unsuccessful_operation(operation: &str) -> Self {
         InterpreterError::UnsuccessfulOperation(operation.into())
     }
+
+    /// A name for the kind of error, for tools which read errors
+    pub fn kind(&self) -> &'static str {
+        match self {
+            InterpreterError::UndefinedVariable(_) => "undefined_variable",
+            InterpreterError::UndefinedPrimitive(_) => "undefined_primitive",
+            InterpreterError::UndefinedProperty(_, _) => "undefined_property",
+            InterpreterError::NoOutput(_) => "no_output",
+            InterpreterError::DivisionByZero => "division_by_zero",
+            InterpreterError::UnsupportedOperation(_) => "unsupported_operation",
+            InterpreterError::InvalidType(_, _) => "invalid_type",
+            InterpreterError::UnsuccessfulOperation(_) => "unsuccessful_operation",
+            InterpreterError::CommandLimitExceeded(_) => "command_limit_exceeded",
+            InterpreterError::TimeLimitExceeded(_) => "time_limit_exceeded",
+            InterpreterError::SegmentLimitExceeded(_) => "segment_limit_exceeded",
+            InterpreterError::MemoryLimitExceeded(_) => "memory_limit_exceeded",
+            InterpreterError::DepthLimitExceeded(_) => "depth_limit_exceeded",
+            InterpreterError::Stopped => "stopped",
+            InterpreterError::Io(_) => "io",
+            InterpreterError::InvalidList(_, _) => "invalid_list",
+            InterpreterError::State(_) => "state",
+            InterpreterError::StateMismatch => "state_mismatch",
+            InterpreterError::Needs3d(_) => "needs_3d",
+            InterpreterError::Turtle(e) => e.kind(),
+        }
+    }
 }
 
-#[derive(thiserror::Error, miette::Diagnostic, Debug, PartialEq)]
+#[derive(thiserror::Error, miette::Diagnostic, Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum TurtleError {
     #[error("Colour out of range: {0}")]
-    ColourOutOfRange(f32), // TODO: Make miette provide a help message informing the correct range.
+    ColourOutOfRange(f64), // TODO: Make miette provide a help message informing the correct range.
     #[error("Angle out of range: {0}")]
-    AngleOutOfRange(f32), // TODO: Make miette provide a help message informing the correct range.
+    AngleOutOfRange(f64), // TODO: Make miette provide a help message informing the correct range.
+    #[error("Label height must be positive: {0}")]
+    LabelHeightOutOfRange(f64),
     #[error("Invalid coordinates: ({0}, {1})")]
-    InvalidCoordinates(f32, f32),
+    #[diagnostic(help("the turtle is fenced in, so it can't leave the canvas"))]
+    InvalidCoordinates(f64, f64),
+}
+
+impl TurtleError {
+    /// A name for the kind of error, for tools which read errors
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TurtleError::ColourOutOfRange(_) => "colour_out_of_range",
+            TurtleError::AngleOutOfRange(_) => "angle_out_of_range",
+            TurtleError::LabelHeightOutOfRange(_) => "label_height_out_of_range",
+            TurtleError::InvalidCoordinates(_, _) => "invalid_coordinates",
+        }
+    }
+}
+
+#[derive(thiserror::Error, miette::Diagnostic, Debug)]
+#[non_exhaustive]
+pub enum CanvasError {
+    #[error("Failed to render image: {0}")]
+    Render(String),
+
+    #[error("Failed to save {}: {1}", .0.display())]
+    Save(PathBuf, String),
+
+    #[error("Unsupported image format: {}", .0.display())]
+    #[diagnostic(help(
+        "save the image as a `.svg` or `.png` file, as a `.gcode` or `.hpgl` file for a plotter, or as an `.obj` file of the lines drawn in 3D"
+    ))]
+    UnsupportedFormat(PathBuf),
 }
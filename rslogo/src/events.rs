@@ -0,0 +1,300 @@
+use std::io::{BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    canvas::{Label, Line},
+    errors::{InterpreterError, ReplayError},
+    observer::Observer,
+    tokens::{Command, Location, Program},
+    turtle::PenState,
+    Canvas,
+};
+
+/// Something the turtle did. Events are written as JSON objects, with their kind in an `event`
+/// field, such as `{"event":"pen","down":true}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Event {
+    /// The size of the canvas, which is always the first event in a log
+    Canvas { width: u32, height: u32 },
+
+    /// The pen was lifted or put down
+    Pen { down: bool },
+
+    /// The pen changed colour
+    Colour { colour: f64 },
+
+    /// The turtle turned to face a new heading
+    Turn { heading: f64 },
+
+    /// A line was drawn. A single move may draw several lines, such as when the turtle wraps
+    /// around the edge of the canvas.
+    Line(Line),
+
+    /// Text was written
+    Label(Label),
+
+    /// The turtle moved, whether or not it drew anything
+    Move { from: (f64, f64), to: (f64, f64) },
+}
+
+/// Where the command which caused an event was written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub file: String,
+
+    /// The line the command starts on, starting from 1
+    pub line: usize,
+
+    /// How many bytes into the file the command starts
+    pub offset: usize,
+
+    /// How many bytes long the command is
+    pub length: usize,
+}
+
+impl From<&Location> for Span {
+    fn from(location: &Location) -> Self {
+        Span {
+            file: location.file_name().to_string(),
+            line: location.line,
+            offset: location.span.offset(),
+            length: location.span.len(),
+        }
+    }
+}
+
+/// A line of an event log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    #[serde(flatten)]
+    pub event: Event,
+
+    /// The command which caused the event. Only the canvas has no span.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+}
+
+/// The parts of a program an event log watches for changes
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot {
+    position: (f64, f64),
+    heading: f64,
+    pen_down: bool,
+    colour: f64,
+    lines: usize,
+    labels: usize,
+}
+
+impl Snapshot {
+    fn of(program: &Program) -> Self {
+        let turtle = program.turtle();
+        Snapshot {
+            position: turtle.get_turtle_coords(),
+            heading: turtle.get_heading(),
+            pen_down: matches!(turtle.get_pen_state(), PenState::Down),
+            colour: turtle.get_pen_colour(),
+            lines: program.canvas().lines().len(),
+            labels: program.canvas().labels().len(),
+        }
+    }
+}
+
+/// Writes everything the turtle does as JSON Lines, along with where the command which did it
+/// was written. Each event belongs to the innermost command which caused it, so drawing inside a
+/// procedure is put down to the command in the procedure rather than the call.
+///
+/// A log has everything needed to draw the program again with [`replay`], and two logs of the
+/// same program can be compared line by line.
+pub struct EventLog<W> {
+    output: W,
+
+    /// The state of the program when it was last looked at, or `None` before the first command
+    last: Option<Snapshot>,
+
+    /// The first error writing the log, which stops the program before its next command
+    failed: Option<std::io::Error>,
+}
+
+impl<W: Write + Send> EventLog<W> {
+    /// Start a log of a program drawing onto a canvas of the given size
+    pub fn new(mut output: W, width: u32, height: u32) -> std::io::Result<Self> {
+        write_record(
+            &mut output,
+            &Record {
+                event: Event::Canvas { width, height },
+                span: None,
+            },
+        )?;
+        Ok(EventLog {
+            output,
+            last: None,
+            failed: None,
+        })
+    }
+
+    /// Write an event for everything which changed since the program was last looked at
+    fn record(&mut self, program: &Program, location: &Location) {
+        let now: Snapshot = Snapshot::of(program);
+        let Some(last) = self.last.replace(now.clone()) else {
+            return;
+        };
+        let mut events: Vec<Event> = Vec::new();
+        if now.pen_down != last.pen_down {
+            events.push(Event::Pen { down: now.pen_down });
+        }
+        if now.colour != last.colour {
+            events.push(Event::Colour { colour: now.colour });
+        }
+        if now.heading != last.heading {
+            events.push(Event::Turn {
+                heading: now.heading,
+            });
+        }
+        let canvas: &Canvas = program.canvas();
+        events.extend(
+            canvas.lines()[last.lines..]
+                .iter()
+                .cloned()
+                .map(Event::Line),
+        );
+        events.extend(
+            canvas.labels()[last.labels..]
+                .iter()
+                .cloned()
+                .map(Event::Label),
+        );
+        if now.position != last.position {
+            events.push(Event::Move {
+                from: last.position,
+                to: now.position,
+            });
+        }
+
+        for event in events {
+            let record = Record {
+                event,
+                span: Some(Span::from(location)),
+            };
+            if let Err(e) = write_record(&mut self.output, &record) {
+                self.failed.get_or_insert(e);
+            }
+        }
+    }
+}
+
+impl<W: Write + Send> Observer for EventLog<W> {
+    fn before(
+        &mut self,
+        program: &Program,
+        location: &Location,
+        _command: &Command,
+    ) -> Result<(), InterpreterError> {
+        if let Some(e) = self.failed.take() {
+            return Err(InterpreterError::Io(e.to_string()));
+        }
+        // Nothing is drawn between commands, so this only sets where the next command starts
+        self.record(program, location);
+        Ok(())
+    }
+
+    fn after(
+        &mut self,
+        program: &Program,
+        location: &Location,
+        _command: &Command,
+        _result: &Result<(), InterpreterError>,
+    ) {
+        self.record(program, location);
+    }
+}
+
+fn write_record(output: &mut impl Write, record: &Record) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *output, record)?;
+    writeln!(output)
+}
+
+/// Draw the lines and labels in an event log onto a canvas the size given by the log. The other
+/// events only describe what the turtle did, so they are ignored.
+pub fn replay(log: impl BufRead) -> Result<Canvas, ReplayError> {
+    let mut canvas: Option<Canvas> = None;
+    for (i, line) in log.lines().enumerate() {
+        let line: String = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(&line).map_err(|e| ReplayError::Invalid {
+            line: i + 1,
+            source: e,
+        })?;
+        match (record.event, canvas.as_mut()) {
+            (Event::Canvas { width, height }, None) => canvas = Some(Canvas::new(width, height)),
+            (Event::Canvas { .. }, Some(_)) => return Err(ReplayError::SecondCanvas(i + 1)),
+            (_, None) => return Err(ReplayError::MissingCanvas),
+            (Event::Line(x), Some(canvas)) => canvas.draw(x),
+            (Event::Label(x), Some(canvas)) => canvas.write(x),
+            (_, Some(_)) => {}
+        }
+    }
+    canvas.ok_or(ReplayError::MissingCanvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{interpreter, run, SharedBuffer};
+
+    #[test]
+    fn replays_what_was_drawn() {
+        let buffer = SharedBuffer::default();
+        let log = EventLog::new(buffer.clone(), 100, 100).unwrap();
+        let mut interpreter = interpreter().with_observer(log);
+        run(
+            &mut interpreter,
+            "TO Step\n  FORWARD \"10\nEND\nPENDOWN\nSETPENCOLOR \"2\nStep\nTURN \"90\nLABEL \"hi\n",
+        )
+        .unwrap();
+
+        let log = buffer.contents();
+        let records: Vec<Record> = log
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect();
+        let events: Vec<&str> = records
+            .iter()
+            .map(|x| match x.event {
+                Event::Canvas { .. } => "canvas",
+                Event::Pen { .. } => "pen",
+                Event::Colour { .. } => "colour",
+                Event::Turn { .. } => "turn",
+                Event::Line(_) => "line",
+                Event::Label(_) => "label",
+                Event::Move { .. } => "move",
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec!["canvas", "pen", "colour", "line", "move", "turn", "label"]
+        );
+        // Drawing inside a procedure belongs to the command which drew it, not the call
+        assert_eq!(records[3].span.as_ref().unwrap().line, 2);
+
+        let canvas = replay(log.as_bytes()).unwrap();
+        assert_eq!(&canvas, interpreter.canvas());
+    }
+
+    #[test]
+    fn logs_must_start_with_the_canvas() {
+        let log = r#"{"event":"pen","down":true}"#;
+        assert!(matches!(
+            replay(log.as_bytes()),
+            Err(ReplayError::MissingCanvas)
+        ));
+        assert!(matches!(
+            replay("{\"event\":\"canvas\",\"width\":1,\"height\":1}\nnot json\n".as_bytes()),
+            Err(ReplayError::Invalid { line: 2, .. })
+        ));
+    }
+}
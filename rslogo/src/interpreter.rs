@@ -0,0 +1,388 @@
+use std::io::{BufRead, Write};
+
+use crate::{
+    canvas::Canvas,
+    errors::{source_file, Diagnostics, InterpreterError},
+    limits::{Limits, DEFAULT_MAX_DEPTH},
+    observer::Observer,
+    parsers::parse,
+    primitives::Primitives,
+    space::View,
+    state::State,
+    tokens::{Ast, EvalResult, Program},
+};
+
+/// Roughly the most stack a single procedure call can take up, which is far more in an
+/// unoptimised build than an optimised one
+const STACK_PER_CALL: usize = 128 * 1024;
+
+/// Stack for everything other than procedure calls, such as parsing a list passed to `RUN`
+const BASE_STACK: usize = 8 * 1024 * 1024;
+
+/// Runs parsed programs, drawing onto a canvas. State such as variables, procedures and the
+/// turtle carries over between runs, so a program can be run in several parts.
+pub struct Interpreter {
+    program: Program,
+}
+
+impl Interpreter {
+    /// Create an interpreter which draws onto the given canvas, with the turtle in the middle
+    pub fn new(canvas: Canvas) -> Self {
+        Interpreter {
+            program: Program::with_canvas(Vec::new(), canvas),
+        }
+    }
+
+    /// Limit the resources programs may use. Commands and time are counted from the start of
+    /// each run.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.program.set_limits(limits);
+        self
+    }
+
+    /// Let `EQ` and `NE` treat numbers which are at most `tolerance` apart as equal
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.program.set_tolerance(tolerance);
+        self
+    }
+
+    /// Let the turtle move in 3D, drawing its path onto the canvas with the given view
+    pub fn with_view(mut self, view: View) -> Self {
+        self.program.set_view(Some(view));
+        self
+    }
+
+    /// Watch each command as it runs, such as with a `Tracer` or `Debugger`
+    pub fn with_observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.program.set_observer(Some(Box::new(observer)));
+        self
+    }
+
+    /// Write the output of `PRINT`, `SHOW` and `TYPE` somewhere other than standard output
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.program.set_output(output);
+        self
+    }
+
+    /// Read the input of `READWORD` and `READLIST` from somewhere other than standard input
+    pub fn with_input(mut self, input: impl BufRead + Send + 'static) -> Self {
+        self.program.set_input(input);
+        self
+    }
+
+    /// Add a command or reporter which programs can use. Primitives take a fixed number of
+    /// arguments, and may output a value; reporters which don't output anything are an error.
+    pub fn register<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&mut Program, &[EvalResult]) -> Result<Option<EvalResult>, InterpreterError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.program.register_primitive(name, arity, function);
+    }
+
+    pub fn primitives(&self) -> &Primitives {
+        self.program.primitives()
+    }
+
+    /// Parse the source of a program which may use any of the registered primitives
+    pub fn parse(&self, source: &str) -> Result<Ast, Diagnostics> {
+        parse(&source_file("input", source), self.primitives()).map(Ast::from)
+    }
+
+    /// Run every command in the program, stopping at the first one which fails
+    pub fn run(&mut self, ast: &Ast) -> Result<(), InterpreterError> {
+        self.program.restart_budget();
+        self.run_from(ast, 0, &[])
+    }
+
+    /// Restore a state saved by `SAVESTATE`, then carry on running the program from the command
+    /// after the one which saved it. The program must be the same as the one which was saved.
+    pub fn resume(&mut self, ast: &Ast, state: State) -> Result<(), InterpreterError> {
+        if state
+            .program
+            .is_some_and(|program: u64| program != ast.fingerprint())
+        {
+            return Err(InterpreterError::StateMismatch);
+        }
+        let (start, within): (usize, Vec<usize>) = match state.nested.is_empty() {
+            true => (state.next, Vec::new()),
+            false => (
+                state
+                    .next
+                    .checked_sub(1)
+                    .ok_or(InterpreterError::StateMismatch)?,
+                state.nested.clone(),
+            ),
+        };
+        self.program.restore(state)?;
+        self.program.restart_budget();
+        self.run_from(ast, start, &within)
+    }
+
+    /// Run the program from the given top-level command on a thread of its own, with a stack big
+    /// enough for the deepest recursion the limits allow. If `within` isn't empty, the run carries
+    /// on from there inside that command.
+    fn run_from(
+        &mut self,
+        ast: &Ast,
+        start: usize,
+        within: &[usize],
+    ) -> Result<(), InterpreterError> {
+        let depth: usize = self.program.limits().max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        let program: &mut Program = &mut self.program;
+        std::thread::scope(|scope| {
+            std::thread::Builder::new()
+                .stack_size(BASE_STACK + depth * STACK_PER_CALL)
+                .spawn_scoped(scope, || program.run(ast, start, within))
+                .expect("the thread running the program should start")
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    /// The turtle, variables, procedures and drawing so far
+    pub fn state(&self) -> State {
+        self.program.state()
+    }
+
+    /// Everything drawn so far
+    pub fn canvas(&self) -> &Canvas {
+        self.program.canvas()
+    }
+
+    pub fn into_canvas(self) -> Canvas {
+        self.program.into_canvas()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{draw, interpreter, printed, run};
+
+    #[test]
+    fn draws_only_with_the_pen_down() {
+        let (result, canvas) = draw("FORWARD \"10\nPENDOWN\nRIGHT \"20\nTURN \"90\nFORWARD \"5\n");
+        assert_eq!(result, Ok(()));
+
+        let ends: Vec<(f64, f64)> = canvas.lines().iter().map(|x| x.end).collect();
+        assert_eq!(ends, vec![(70.0, 40.0), (75.0, 40.0)]);
+    }
+
+    #[test]
+    fn primitives_are_commands_and_reporters() {
+        let mut interpreter = interpreter();
+        interpreter.register("double", 1, |_, args| match args {
+            [EvalResult::Float(x)] => Ok(Some(EvalResult::Float(x * 2.0))),
+            _ => Err(InterpreterError::invalid_type("double", "non-number")),
+        });
+        interpreter.register("Jump", 0, |program, _| {
+            program.turtle_mut().set_coordinates(Some(0.0), Some(0.0))?;
+            Ok(None)
+        });
+
+        run(&mut interpreter, "jump\nSETX DOUBLE DOUBLE \"5\n").unwrap();
+        assert_eq!(
+            interpreter.program.turtle().get_turtle_coords(),
+            (20.0, 0.0)
+        );
+
+        // A command used as a reporter doesn't output anything
+        assert_eq!(
+            run(&mut interpreter, "SETX JUMP"),
+            Err(InterpreterError::no_output("Jump"))
+        );
+    }
+
+    #[test]
+    fn errors_inside_blocks_are_reported() {
+        let (result, _) = draw("IF EQ \"1 \"1 [\n  SETPENCOLOR \"20\n]\n");
+        assert!(matches!(result, Err(InterpreterError::Turtle(_))));
+    }
+
+    #[test]
+    fn prints_and_reads_words() {
+        let input = std::io::Cursor::new("turtle\n3 blind mice\n");
+        let (mut interpreter, printed) = printed(
+            interpreter().with_input(input),
+            "MAKE \"name READWORD\nMAKE \"words READLIST\nTYPE \"Hello,\nPRINT WORD \" :name\n\
+                 PRINT :words\nSHOW :words\nPRINT COUNT :words\nPRINT COUNT :name\n\
+                 PRINT WORDP :words\nPRINT NUMBERP WORD \"4 \"2\nPRINT READWORD\n",
+        );
+        assert_eq!(
+            printed,
+            "Hello,turtle\n3 blind mice\n[3 blind mice]\n3\n6\nFALSE\nTRUE\n\n"
+        );
+
+        assert_eq!(
+            run(&mut interpreter, "PRINT WORD \"a :words"),
+            Err(InterpreterError::invalid_type("word", "list"))
+        );
+    }
+
+    #[test]
+    fn lists_are_run_and_mapped() {
+        let (mut interpreter, printed) = printed(
+            interpreter(),
+            "TO Line \"length\n  FORWARD :length\nEND\nRUN [PENDOWN Line \"10]\n\
+                 APPLY \"Line [5]\nMAKE \"doubled MAP [* ? \"2] [1 2 3]\nSHOW :doubled\n\
+                 SHOW FILTER [LT ? \"5] :doubled\nPRINT REDUCE [+ ?1 ?2] :doubled\n",
+        );
+
+        let ends: Vec<(f64, f64)> = interpreter.canvas().lines().iter().map(|x| x.end).collect();
+        assert_eq!(ends, vec![(50.0, 40.0), (50.0, 35.0)]);
+        assert_eq!(printed, "[2 4 6]\n[2 4]\n12\n");
+
+        assert!(matches!(
+            run(&mut interpreter, "RUN [FORWARD]"),
+            Err(InterpreterError::InvalidList(_, _))
+        ));
+        assert_eq!(
+            run(&mut interpreter, "SHOW MAP [? ?] \"word"),
+            Err(InterpreterError::invalid_type("map", "string"))
+        );
+    }
+
+    #[test]
+    fn keeps_property_lists() {
+        let (mut interpreter, printed) = printed(
+            interpreter(),
+            "PPROP \"square \"size \"10\nPPROP \"square \"colour \"2\nPPROP \"square \"size \"20\n\
+                 PENDOWN\nSETPENCOLOR GPROP \"square \"colour\nFORWARD GPROP \"square \"size\n\
+                 SHOW PLIST \"square\nREMPROP \"square \"size\nREMPROP \"square \"size\n\
+                 SHOW PLIST \"square\nSHOW PLIST \"circle\n",
+        );
+
+        assert_eq!(interpreter.canvas().lines()[0].end, (50.0, 30.0));
+        assert_eq!(printed, "[size 20 colour 2]\n[colour 2]\n[]\n");
+
+        assert_eq!(
+            run(&mut interpreter, "FORWARD GPROP \"square \"size"),
+            Err(InterpreterError::UndefinedProperty(
+                "square".to_string(),
+                "size".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn works_in_double_precision() {
+        let (_, printed) = printed(
+            interpreter().with_tolerance(1e-9),
+            "MAKE \"i \"0\nWHILE LT :i \"1000 [\n  FORWARD \"0.1\n  ADDASSIGN \"i \"1\n]\n\
+                 SHOW EQ YCOR \"-50\nSHOW XCOR\nSHOW QUOTIENT \"7 \"2\nSHOW REMAINDER \"-7 \"2\n\
+                 SHOW INTEGERP QUOTIENT \"7 \"2\nSHOW INTEGERP \"2.5\n",
+        );
+
+        // A tenth can't be written exactly, so the turtle only ends up within the tolerance of
+        // where it should be, but it stays exactly in line
+        assert_eq!(printed, "TRUE\n50\n3\n-1\nTRUE\nFALSE\n");
+    }
+
+    #[test]
+    fn resumes_from_a_saved_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let source = format!(
+            "TO Step \"n\n  FORWARD :n\nEND\nMAKE \"i \"10\nPENDOWN\nStep :i\n\
+             SAVESTATE \"{}\nADDASSIGN \"i \"5\nStep :i\n",
+            path.display()
+        );
+        let mut interpreter = interpreter();
+        let ast = interpreter.parse(&source).unwrap();
+        interpreter.run(&ast).unwrap();
+
+        // A new interpreter only runs the commands after SAVESTATE, but draws the same thing
+        let state = State::load(&path).unwrap();
+        assert_eq!(state.next, 5);
+        let mut resumed = Interpreter::new(Canvas::new(1, 1));
+        resumed.resume(&ast, state).unwrap();
+        assert_eq!(resumed.canvas(), interpreter.canvas());
+        assert_eq!(resumed.state(), interpreter.state());
+
+        // Another program can't carry on from the state
+        let other = interpreter.parse("FORWARD \"10\n").unwrap();
+        assert_eq!(
+            Interpreter::new(Canvas::new(1, 1)).resume(&other, State::load(&path).unwrap()),
+            Err(InterpreterError::StateMismatch)
+        );
+    }
+
+    #[test]
+    fn resumes_inside_loops_and_procedures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let source = format!(
+            "TO Spiral \"n\n  WHILE LT :n \"40 [\n    FORWARD :n\n    RIGHT \"90\n    \
+             IF EQ :n \"20 [ SAVESTATE \"{}\n ]\n    \
+             ADDASSIGN \"n \"5\n  ]\n  MAKE \"last :n\nEND\nPENDOWN\nSpiral \"10\nFORWARD \"5\n",
+            path.display()
+        );
+        let mut interpreter = interpreter();
+        let ast = interpreter.parse(&source).unwrap();
+        interpreter.run(&ast).unwrap();
+
+        // The rest of the loop, the procedure and the program all run after the resume
+        let state = State::load(&path).unwrap();
+        assert_eq!(state.next, 3);
+        assert!(!state.nested.is_empty());
+        let mut resumed = Interpreter::new(Canvas::new(1, 1));
+        resumed.resume(&ast, state).unwrap();
+        assert_eq!(resumed.canvas(), interpreter.canvas());
+        assert_eq!(resumed.state(), interpreter.state());
+    }
+
+    #[test]
+    fn loading_a_state_carries_on_after_the_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let source = format!(
+            "MAKE \"x \"1\nPENDOWN\nFORWARD \"10\nSAVESTATE \"{0}\nMAKE \"x \"2\nFORWARD \"10\n\
+             LOADSTATE \"{0}\nPRINT :x\nFORWARD \"5\n",
+            path.display()
+        );
+        let (interpreter, printed) = printed(interpreter(), &source);
+
+        // The second line was drawn after the state was saved, so loading it undoes the line,
+        // but the commands after LOADSTATE still run
+        assert_eq!(printed, "1\n");
+        let ends: Vec<(f64, f64)> = interpreter.canvas().lines().iter().map(|x| x.end).collect();
+        assert_eq!(ends, vec![(50.0, 40.0), (50.0, 35.0)]);
+    }
+
+    #[test]
+    fn moves_in_3d() {
+        let mut interpreter = interpreter().with_view(View::default());
+        run(
+            &mut interpreter,
+            "PENDOWN\nUP \"90\nFORWARD \"10\nDOWN \"90\nROLLRIGHT \"90\nUP \"90\n\
+                 FORWARD \"10\nROLLLEFT \"90\nTURN \"90\nFORWARD \"10\nSETZ \"5\n\
+                 MAKE \"z ZCOR\n",
+        )
+        .unwrap();
+
+        // Out of the canvas, then to the right on its side, then down the canvas once the turtle
+        // is upright again and turned
+        let ends: Vec<[f64; 3]> = interpreter
+            .canvas()
+            .segments()
+            .iter()
+            .map(|x| x.end.map(f64::round))
+            .collect();
+        assert_eq!(
+            ends,
+            vec![[50.0, 50.0, 10.0], [60.0, 50.0, 10.0], [60.0, 60.0, 10.0]]
+        );
+        // Seen from the front, the first segment is only a point
+        assert_eq!(interpreter.canvas().lines()[1].end.0.round(), 60.0);
+        assert_eq!(
+            interpreter.program.variables().get("z"),
+            Some(&EvalResult::Float(5.0))
+        );
+
+        let (result, _) = draw("UP \"90");
+        assert_eq!(result, Err(InterpreterError::Needs3d("UP".to_string())));
+    }
+}
@@ -0,0 +1,331 @@
+/// Words which have a meaning of their own in Logo. These are matched case-insensitively, so
+/// `forward`, `Forward` and `FORWARD` are all the same keyword.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Keyword {
+    PenUp,
+    PenDown,
+    Wrap,
+    Fence,
+    Window,
+    Forward,
+    Back,
+    Left,
+    Right,
+    SetPenColor,
+    Turn,
+    SetHeading,
+    SetX,
+    SetY,
+    SetZ,
+    Up,
+    Down,
+    RollLeft,
+    RollRight,
+    Label,
+    SetLabelHeight,
+    SetFont,
+    Run,
+    Apply,
+    Print,
+    Show,
+    Type,
+    SaveState,
+    LoadState,
+    Make,
+    AddAssign,
+    PProp,
+    RemProp,
+    If,
+    While,
+    To,
+    End,
+    Load,
+    XCor,
+    YCor,
+    ZCor,
+    Heading,
+    Color,
+    ReadWord,
+    ReadList,
+    Word,
+    WordP,
+    NumberP,
+    IntegerP,
+    Count,
+    Map,
+    Filter,
+    Reduce,
+    GProp,
+    PList,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Quotient,
+    Remainder,
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    And,
+    Or,
+}
+
+impl Keyword {
+    /// Every keyword, along with how it is spelled in a program
+    const ALL: &'static [(&'static str, Keyword)] = &[
+        ("PENUP", Keyword::PenUp),
+        ("PENDOWN", Keyword::PenDown),
+        ("WRAP", Keyword::Wrap),
+        ("FENCE", Keyword::Fence),
+        ("WINDOW", Keyword::Window),
+        ("FORWARD", Keyword::Forward),
+        ("BACK", Keyword::Back),
+        ("LEFT", Keyword::Left),
+        ("RIGHT", Keyword::Right),
+        ("SETPENCOLOR", Keyword::SetPenColor),
+        ("TURN", Keyword::Turn),
+        ("SETHEADING", Keyword::SetHeading),
+        ("SETX", Keyword::SetX),
+        ("SETY", Keyword::SetY),
+        ("SETZ", Keyword::SetZ),
+        ("UP", Keyword::Up),
+        ("DOWN", Keyword::Down),
+        ("ROLLLEFT", Keyword::RollLeft),
+        ("ROLLRIGHT", Keyword::RollRight),
+        ("LABEL", Keyword::Label),
+        ("SETLABELHEIGHT", Keyword::SetLabelHeight),
+        ("SETFONT", Keyword::SetFont),
+        ("RUN", Keyword::Run),
+        ("APPLY", Keyword::Apply),
+        ("PRINT", Keyword::Print),
+        ("SHOW", Keyword::Show),
+        ("TYPE", Keyword::Type),
+        ("SAVESTATE", Keyword::SaveState),
+        ("LOADSTATE", Keyword::LoadState),
+        ("MAKE", Keyword::Make),
+        ("ADDASSIGN", Keyword::AddAssign),
+        ("PPROP", Keyword::PProp),
+        ("REMPROP", Keyword::RemProp),
+        ("IF", Keyword::If),
+        ("WHILE", Keyword::While),
+        ("TO", Keyword::To),
+        ("END", Keyword::End),
+        ("LOAD", Keyword::Load),
+        ("XCOR", Keyword::XCor),
+        ("YCOR", Keyword::YCor),
+        ("ZCOR", Keyword::ZCor),
+        ("HEADING", Keyword::Heading),
+        ("COLOR", Keyword::Color),
+        ("READWORD", Keyword::ReadWord),
+        ("READLIST", Keyword::ReadList),
+        ("WORD", Keyword::Word),
+        ("WORDP", Keyword::WordP),
+        ("NUMBERP", Keyword::NumberP),
+        ("INTEGERP", Keyword::IntegerP),
+        ("COUNT", Keyword::Count),
+        ("MAP", Keyword::Map),
+        ("FILTER", Keyword::Filter),
+        ("REDUCE", Keyword::Reduce),
+        ("GPROP", Keyword::GProp),
+        ("PLIST", Keyword::PList),
+        ("+", Keyword::Add),
+        ("-", Keyword::Subtract),
+        ("*", Keyword::Multiply),
+        ("/", Keyword::Divide),
+        ("QUOTIENT", Keyword::Quotient),
+        ("REMAINDER", Keyword::Remainder),
+        ("EQ", Keyword::Eq),
+        ("NE", Keyword::Ne),
+        ("GT", Keyword::Gt),
+        ("LT", Keyword::Lt),
+        ("AND", Keyword::And),
+        ("OR", Keyword::Or),
+    ];
+
+    /// Find the keyword spelled by the given word, ignoring case
+    pub fn from_word(word: &str) -> Option<Keyword> {
+        Keyword::ALL
+            .iter()
+            .find(|(spelling, _)| spelling.eq_ignore_ascii_case(word))
+            .map(|(_, keyword)| *keyword)
+    }
+
+    /// How this keyword is spelled in a program
+    pub fn as_str(&self) -> &'static str {
+        Keyword::ALL
+            .iter()
+            .find(|(_, keyword)| keyword == self)
+            .map(|(spelling, _)| *spelling)
+            .expect("every keyword is listed in `Keyword::ALL`")
+    }
+}
+
+/// The different kinds of tokens that make up a Logo program
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TokenKind {
+    /// A word with a meaning of its own, such as `FORWARD` or `+`
+    Keyword(Keyword),
+
+    /// Any other bare word, such as the name of a procedure
+    Word(String),
+
+    /// A literal value, denoted by a double quote (`"`) followed by a word
+    Quoted(String),
+
+    /// A variable, denoted by a colon (`:`) followed by its name
+    Variable(String),
+
+    /// A keyword which a procedure in the same program is also named after, spelled exactly as
+    /// in its `TO`. In command position it calls the procedure; in an expression it is still the
+    /// keyword. The lexer never produces these itself; the parser resolves them from keywords.
+    Shadowed { keyword: Keyword, name: String },
+
+    /// A word naming a primitive provided by the host, along with how many arguments it takes.
+    /// The lexer never produces these itself; the parser resolves them from words.
+    Primitive { name: String, arity: usize },
+
+    /// The start of a block, `[`
+    OpenBracket,
+
+    /// The end of a block, `]`
+    CloseBracket,
+}
+
+/// A single token, along with where it was found in the source
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Token {
+    pub kind: TokenKind,
+
+    /// Byte offset of the start of the token
+    pub offset: usize,
+
+    /// The token exactly as it was written
+    pub text: String,
+
+    /// The line the token is on, starting from 1
+    pub line: usize,
+}
+
+/// Brackets are always tokens of their own, so they also end a word
+fn is_word_boundary(c: char) -> bool {
+    c.is_whitespace() || c == '[' || c == ']'
+}
+
+/// Split a program into tokens. Whitespace and comments (from `//` to the end of the line) are
+/// discarded. Every other character belongs to some token, so this never fails; it is up to the
+/// parser to decide whether the tokens make sense.
+pub(crate) fn lex(input: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut line: usize = 1;
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let (kind, end): (TokenKind, usize) = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '[' => (TokenKind::OpenBracket, start + 1),
+            ']' => (TokenKind::CloseBracket, start + 1),
+            _ => {
+                // Consume the rest of the word
+                let mut end: usize = start + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|(_, c)| !is_word_boundary(*c)) {
+                    end = i + c.len_utf8();
+                }
+                let word: &str = &input[start..end];
+
+                if word.starts_with("//") {
+                    // Skip to the end of the line, leaving the newline itself to be counted
+                    while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+                    continue;
+                }
+
+                let kind: TokenKind = match c {
+                    '"' => TokenKind::Quoted(word[1..].to_string()),
+                    ':' => TokenKind::Variable(word[1..].to_string()),
+                    _ => match Keyword::from_word(word) {
+                        Some(keyword) => TokenKind::Keyword(keyword),
+                        None => TokenKind::Word(word.to_string()),
+                    },
+                };
+                (kind, end)
+            }
+        };
+
+        tokens.push(Token {
+            kind,
+            offset: start,
+            text: input[start..end].to_string(),
+            line,
+        });
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        lex(input).into_iter().map(|x| x.kind).collect()
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive() {
+        assert_eq!(
+            kinds("forward Forward FORWARD"),
+            vec![TokenKind::Keyword(Keyword::Forward); 3]
+        );
+    }
+
+    #[test]
+    fn keywords_need_a_word_boundary() {
+        assert_eq!(
+            kinds("TOWER TO"),
+            vec![
+                TokenKind::Word(String::from("TOWER")),
+                TokenKind::Keyword(Keyword::To)
+            ]
+        );
+    }
+
+    #[test]
+    fn brackets_and_tabs_end_words() {
+        assert_eq!(
+            kinds("IF :x\t[Box]"),
+            vec![
+                TokenKind::Keyword(Keyword::If),
+                TokenKind::Variable(String::from("x")),
+                TokenKind::OpenBracket,
+                TokenKind::Word(String::from("Box")),
+                TokenKind::CloseBracket,
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let tokens = lex("// a comment\nPENUP // another one\n  \"10");
+        assert_eq!(
+            tokens,
+            vec![
+                Token {
+                    kind: TokenKind::Keyword(Keyword::PenUp),
+                    offset: 13,
+                    text: String::from("PENUP"),
+                    line: 2,
+                },
+                Token {
+                    kind: TokenKind::Quoted(String::from("10")),
+                    offset: 36,
+                    text: String::from("\"10"),
+                    line: 3,
+                },
+            ]
+        );
+    }
+}
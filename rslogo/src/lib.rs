@@ -0,0 +1,59 @@
+//! An interpreter for a subset of the Logo programming language.
+//!
+//! Programs are parsed into an [`Ast`], then run by an [`Interpreter`], which draws onto a
+//! [`Canvas`]:
+//!
+//! ```
+//! use rslogo::{Canvas, Interpreter};
+//!
+//! let ast = rslogo::parse("PENDOWN\nFORWARD \"10\n").unwrap();
+//! let mut interpreter = Interpreter::new(Canvas::new(100, 100));
+//! interpreter.run(&ast).unwrap();
+//! assert_eq!(interpreter.canvas().lines().len(), 1);
+//! ```
+//!
+//! Programs which `LOAD` other files should be read with a [`loader::Loader`] instead.
+
+pub mod canvas;
+pub mod errors;
+pub mod events;
+mod interpreter;
+mod lexer;
+mod limits;
+pub mod loader;
+pub mod lsystem;
+pub mod messages;
+pub mod observer;
+mod optimiser;
+mod parsers;
+mod plotter;
+mod primitives;
+pub mod profiler;
+pub mod space;
+pub mod state;
+#[cfg(test)]
+mod test_support;
+mod tokens;
+pub mod transpile;
+mod turtle;
+
+pub use canvas::Canvas;
+pub use errors::{Diagnostics, InterpreterError};
+pub use interpreter::Interpreter;
+pub use limits::{Limits, DEFAULT_MAX_DEPTH};
+pub use optimiser::{optimise, optimise_with_tolerance};
+pub use primitives::{Primitive, Primitives};
+pub use tokens::{Ast, Command, EvalResult, Frame, Location, Program};
+
+/// Parse the source of a program. Every syntax error is reported at once, and each error owns a
+/// copy of the source, so it can be rendered long after `source` is gone.
+///
+/// The program can't use any primitives; use [`Interpreter::parse`] for programs which use the
+/// primitives registered with an interpreter.
+pub fn parse(source: &str) -> Result<Ast, Diagnostics> {
+    parsers::parse(
+        &errors::source_file("input", source),
+        &primitives::Primitives::new(),
+    )
+    .map(Ast::from)
+}
@@ -0,0 +1,254 @@
+use std::time::{Duration, Instant};
+
+use crate::{errors::InterpreterError, tokens::EvalResult};
+
+/// How many procedure calls deep a program may go by default. Deeper recursion is almost always
+/// a procedure which never stops calling itself.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// Limits on the resources a program may use, so that untrusted programs can be run safely.
+/// Every limit is optional. By default, only how deeply a program may recurse is limited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// The most commands a program may execute, including those inside loops and procedures
+    pub max_commands: Option<u64>,
+
+    /// The longest a program may run for
+    pub max_time: Option<Duration>,
+
+    /// The most lines and labels a program may draw. In 3D, every segment counts as a line, even
+    /// those the camera can't see.
+    pub max_segments: Option<usize>,
+
+    /// The most memory, in bytes, the program's variables may take up
+    pub max_memory: Option<usize>,
+
+    /// The most procedure calls, `RUN`s and templates a program may be inside of at once. Each
+    /// of these takes up room on the stack, so without a limit, deep enough recursion crashes
+    /// the whole process.
+    pub max_depth: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_commands: None,
+            max_time: None,
+            max_segments: None,
+            max_memory: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+        }
+    }
+}
+
+/// How much of each limit a program has used so far
+#[derive(Debug, Clone)]
+pub(crate) struct Budget {
+    commands: u64,
+    started: Instant,
+    memory: usize,
+    depth: usize,
+}
+
+impl Budget {
+    pub fn new() -> Self {
+        Budget {
+            commands: 0,
+            started: Instant::now(),
+            memory: 0,
+            depth: 0,
+        }
+    }
+
+    /// Start counting commands and time again, such as at the start of a new run. Memory is kept,
+    /// as the variables are still around.
+    pub fn restart(&mut self) {
+        self.commands = 0;
+        self.started = Instant::now();
+    }
+
+    /// Count a single step of the program, such as a command or loop iteration
+    pub fn step(&mut self, limits: &Limits) -> Result<(), InterpreterError> {
+        self.commands += 1;
+        if let Some(max) = limits.max_commands {
+            if self.commands > max {
+                return Err(InterpreterError::CommandLimitExceeded(max));
+            }
+        }
+        if let Some(max) = limits.max_time {
+            if self.started.elapsed() > max {
+                return Err(InterpreterError::TimeLimitExceeded(max));
+            }
+        }
+        Ok(())
+    }
+
+    /// Go one call deeper, such as into a procedure. Every successful call must be matched by a
+    /// call to `leave`.
+    pub fn enter(&mut self, limits: &Limits) -> Result<(), InterpreterError> {
+        if let Some(max) = limits.max_depth {
+            if self.depth >= max {
+                return Err(InterpreterError::DepthLimitExceeded(max));
+            }
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Come back out of a call entered with `enter`
+    pub fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Account for a variable being replaced, given the sizes of its old and new values
+    pub fn reallocate(
+        &mut self,
+        limits: &Limits,
+        old: usize,
+        new: usize,
+    ) -> Result<(), InterpreterError> {
+        let memory: usize = self.memory - old + new;
+        if let Some(max) = limits.max_memory {
+            if memory > max {
+                return Err(InterpreterError::MemoryLimitExceeded(max));
+            }
+        }
+        self.memory = memory;
+        Ok(())
+    }
+}
+
+/// Roughly how much memory a variable takes up, in bytes
+pub(crate) fn variable_size(name: &str, value: &EvalResult) -> usize {
+    let contents: usize = match value {
+        EvalResult::Bool(_) | EvalResult::Float(_) => 0,
+        EvalResult::String(x) => x.len(),
+        EvalResult::List(x) => x.iter().map(|x| variable_size("", x)).sum(),
+    };
+    name.len() + std::mem::size_of::<EvalResult>() + contents
+}
+
+/// Roughly how much memory a property in a property list takes up, in bytes. The name of the
+/// list counts towards each of its properties.
+pub(crate) fn property_size(list: &str, property: &str, value: &EvalResult) -> usize {
+    list.len() + variable_size(property, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        space::{Projection, View},
+        test_support::{interpreter, run as run_in},
+    };
+
+    fn run(source: &str, limits: Limits) -> Result<(), InterpreterError> {
+        run_in(&mut interpreter().with_limits(limits), source)
+    }
+
+    #[test]
+    fn infinite_loops_are_stopped() {
+        let limits = Limits {
+            max_commands: Some(1000),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run("WHILE \"TRUE [ ]", limits),
+            Err(InterpreterError::CommandLimitExceeded(1000))
+        );
+
+        let limits = Limits {
+            max_time: Some(Duration::from_millis(10)),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run("WHILE \"TRUE [ TURN \"0 ]", limits),
+            Err(InterpreterError::TimeLimitExceeded(Duration::from_millis(
+                10
+            )))
+        );
+    }
+
+    #[test]
+    fn drawing_is_limited() {
+        let limits = Limits {
+            max_segments: Some(3),
+            ..Limits::default()
+        };
+        let square = "PENDOWN\nFORWARD \"1\nRIGHT \"1\nBACK \"1\nLEFT \"1\n";
+        assert_eq!(
+            run(square, limits.clone()),
+            Err(InterpreterError::SegmentLimitExceeded(3))
+        );
+
+        // In 3D, a segment counts even when it's behind the camera, so there is no line for it
+        let view = View {
+            projection: Projection::Perspective { distance: 100.0 },
+            ..View::default()
+        };
+        let mut interpreter = interpreter().with_view(view).with_limits(limits);
+        assert_eq!(
+            run_in(
+                &mut interpreter,
+                "PENDOWN\nUP \"90\nFORWARD \"200\nBACK \"200\nLABEL \"a\nLABEL \"b\n"
+            ),
+            Err(InterpreterError::SegmentLimitExceeded(3))
+        );
+        let canvas = interpreter.canvas();
+        assert_eq!((canvas.segments().len(), canvas.lines().len()), (2, 0));
+        assert_eq!(canvas.labels().len(), 1);
+    }
+
+    #[test]
+    fn memory_is_limited() {
+        let limits = Limits {
+            max_memory: Some(1000),
+            ..Limits::default()
+        };
+
+        // Replacing a variable frees its old value, so this only ever uses one variable's worth
+        let same = "MAKE \"x \"1\n".repeat(100);
+        assert_eq!(run(&same, limits.clone()), Ok(()));
+
+        let many = (0..100)
+            .map(|i| format!("MAKE \"x{i} \"1\n"))
+            .collect::<String>();
+        assert_eq!(
+            run(&many, limits),
+            Err(InterpreterError::MemoryLimitExceeded(1000))
+        );
+    }
+
+    #[test]
+    fn recursion_is_limited() {
+        let forever = "TO f\n  f\nEND\nf\n";
+        assert_eq!(
+            run(forever, Limits::default()),
+            Err(InterpreterError::DepthLimitExceeded(DEFAULT_MAX_DEPTH))
+        );
+
+        // Lists and templates count towards the same limit
+        let limits = Limits {
+            max_depth: Some(10),
+            ..Limits::default()
+        };
+        let forever = "TO g\n  RUN [APPLY \"h []]\nEND\nTO h\n  g\nEND\ng\n";
+        assert_eq!(
+            run(forever, limits.clone()),
+            Err(InterpreterError::DepthLimitExceeded(10))
+        );
+        let one = Limits {
+            max_depth: Some(1),
+            ..Limits::default()
+        };
+        assert_eq!(run("SHOW MAP [* ? \"2] [1]", one.clone()), Ok(()));
+        assert_eq!(
+            run("RUN [SHOW MAP [* ? \"2] [1]]", one),
+            Err(InterpreterError::DepthLimitExceeded(1))
+        );
+
+        // Coming back out of a call makes room for the next one
+        let sequential = "TO f\nEND\n".to_string() + &"f\n".repeat(20);
+        assert_eq!(run(&sequential, limits), Ok(()));
+    }
+}
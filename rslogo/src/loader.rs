@@ -0,0 +1,263 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::{source_file, LoadError, SourceFile},
+    parsers::parse,
+    primitives::Primitives,
+    tokens::{Ast, Command},
+};
+
+/// Reads a program from disk, along with every file it loads with `LOAD "name`.
+///
+/// Loaded files are libraries of procedures: their procedure definitions take the place of the
+/// `LOAD` that named them, and anything else in them is ignored. Each file is only parsed once, no
+/// matter how many times it is loaded.
+pub struct Loader {
+    /// Directories to look in for loaded files, after the directory of the file loading them
+    search_path: Vec<PathBuf>,
+
+    /// Every file loaded so far, by canonical path
+    loaded: HashSet<PathBuf>,
+
+    /// The chain of files currently being loaded, used to detect cycles
+    stack: Vec<PathBuf>,
+
+    /// Primitives which every file may use
+    primitives: Primitives,
+}
+
+impl Loader {
+    /// Create a loader which searches the given directories for loaded files
+    pub fn new(search_path: Vec<PathBuf>) -> Self {
+        Loader {
+            search_path,
+            loaded: HashSet::new(),
+            stack: Vec::new(),
+            primitives: Primitives::new(),
+        }
+    }
+
+    /// Let every loaded file use the given primitives, such as those registered with an
+    /// `Interpreter`
+    pub fn with_primitives(mut self, primitives: &Primitives) -> Self {
+        self.primitives = primitives.clone();
+        self
+    }
+
+    /// Parse the file at the given path, replacing each `LOAD` in it with the procedures of the
+    /// file it names.
+    pub fn load(&mut self, path: &Path) -> Result<Ast, LoadError> {
+        self.load_commands(path).map(Ast::from)
+    }
+
+    fn load_commands(&mut self, path: &Path) -> Result<Vec<Command>, LoadError> {
+        let io_error = |source: std::io::Error| LoadError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        let canonical: PathBuf = path.canonicalize().map_err(io_error)?;
+        // The file counts as loaded even if it fails to parse, so it can be watched for a fix
+        self.loaded.insert(canonical.clone());
+        let text: String = std::fs::read_to_string(path).map_err(io_error)?;
+        let src: SourceFile = source_file(path.display().to_string(), text);
+        let commands: Vec<Command> = parse(&src, &self.primitives)?;
+
+        self.stack.push(canonical);
+        let dir: &Path = path.parent().unwrap_or(Path::new("."));
+        let result = self.resolve(&src, dir, commands);
+        self.stack.pop();
+        result
+    }
+
+    /// Every file read so far, including the first one, by canonical path. This includes files
+    /// which failed to parse.
+    pub fn dependencies(&self) -> Vec<PathBuf> {
+        let mut dependencies: Vec<PathBuf> = self.loaded.iter().cloned().collect();
+        dependencies.sort();
+        dependencies
+    }
+
+    /// Replace each `LOAD` in a file with the procedures defined by the file it names
+    fn resolve(
+        &mut self,
+        src: &SourceFile,
+        dir: &Path,
+        commands: Vec<Command>,
+    ) -> Result<Vec<Command>, LoadError> {
+        let mut resolved: Vec<Command> = Vec::new();
+        for command in commands {
+            let Command::Load(name, span) = command.without_location() else {
+                resolved.push(command);
+                continue;
+            };
+            let (name, span): (String, miette::SourceSpan) = (name.clone(), *span);
+
+            let path: PathBuf = self
+                .find(&name, dir)
+                .map_err(|searched| LoadError::NotFound {
+                    name: name.clone(),
+                    searched,
+                    src: src.clone(),
+                    span,
+                })?;
+            let canonical: PathBuf = path.canonicalize().map_err(|source| LoadError::Io {
+                path: path.clone(),
+                source,
+            })?;
+
+            if self.stack.contains(&canonical) {
+                let mut chain: Vec<PathBuf> = self.stack.clone();
+                chain.push(canonical);
+                return Err(LoadError::Cycle {
+                    name,
+                    chain,
+                    src: src.clone(),
+                    span,
+                });
+            }
+            if self.loaded.contains(&canonical) {
+                continue;
+            }
+
+            resolved.extend(
+                self.load_commands(&path)?
+                    .into_iter()
+                    .filter(|x| matches!(x.without_location(), Command::ProcedureDefine(_, _, _))),
+            );
+        }
+        Ok(resolved)
+    }
+
+    /// Look for a loaded file next to the file loading it, then in each directory of the search
+    /// path. If it can't be found, returns every directory that was searched.
+    fn find(&self, name: &str, dir: &Path) -> Result<PathBuf, Vec<PathBuf>> {
+        let dirs: Vec<PathBuf> = std::iter::once(dir.to_path_buf())
+            .chain(self.search_path.iter().cloned())
+            .collect();
+        dirs.iter()
+            .map(|x| x.join(name))
+            .find(|x| x.is_file())
+            .ok_or(dirs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::{EvalResult, Expression};
+    use tempfile::TempDir;
+
+    fn write(dir: &TempDir, name: &str, text: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn procedure_names(commands: &[Command]) -> Vec<&str> {
+        commands
+            .iter()
+            .filter_map(|x| match x.without_location() {
+                Command::ProcedureDefine(Expression::Value(EvalResult::String(name)), _, _) => {
+                    Some(name.as_str())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loads_procedures_in_place() {
+        let dir = TempDir::new().unwrap();
+        write(
+            &dir,
+            "shapes.lg",
+            "TO Box\nFORWARD \"10\nEND\nFORWARD \"20\n",
+        );
+        let main = write(&dir, "main.lg", "LOAD \"shapes.lg\nBox\n");
+
+        let commands = Loader::new(Vec::new()).load(&main).unwrap();
+        assert_eq!(procedure_names(commands.commands()), vec!["Box"]);
+        // The top-level FORWARD in the library is not run
+        assert_eq!(commands.commands().len(), 2);
+    }
+
+    #[test]
+    fn files_are_loaded_once() {
+        let dir = TempDir::new().unwrap();
+        write(&dir, "shapes.lg", "TO Box\nEND\n");
+        write(&dir, "more.lg", "LOAD \"shapes.lg\nTO Boxes\nBox\nEND\n");
+        let main = write(&dir, "main.lg", "LOAD \"shapes.lg\nLOAD \"more.lg\n");
+
+        let commands = Loader::new(Vec::new()).load(&main).unwrap();
+        assert_eq!(procedure_names(commands.commands()), vec!["Box", "Boxes"]);
+    }
+
+    #[test]
+    fn search_path_is_used() {
+        let dir = TempDir::new().unwrap();
+        let lib = TempDir::new().unwrap();
+        write(&lib, "shapes.lg", "TO Box\nEND\n");
+        let main = write(&dir, "main.lg", "LOAD \"shapes.lg\n");
+
+        assert!(matches!(
+            Loader::new(Vec::new()).load(&main),
+            Err(LoadError::NotFound { .. })
+        ));
+        let commands = Loader::new(vec![lib.path().to_path_buf()])
+            .load(&main)
+            .unwrap();
+        assert_eq!(procedure_names(commands.commands()), vec!["Box"]);
+    }
+
+    #[test]
+    fn cycles_are_detected() {
+        let dir = TempDir::new().unwrap();
+        write(&dir, "a.lg", "LOAD \"b.lg\n");
+        write(&dir, "b.lg", "LOAD \"a.lg\n");
+        let main = dir.path().join("a.lg");
+
+        match Loader::new(Vec::new()).load(&main) {
+            Err(LoadError::Cycle { name, chain, .. }) => {
+                assert_eq!(name, "a.lg");
+                assert_eq!(chain.len(), 3);
+            }
+            _ => panic!("expected a cycle"),
+        }
+    }
+
+    #[test]
+    fn parse_errors_name_the_loaded_file() {
+        let dir = TempDir::new().unwrap();
+        write(&dir, "broken.lg", "TO Box\n");
+        let main = write(&dir, "main.lg", "LOAD \"broken.lg\n");
+
+        match Loader::new(Vec::new()).load(&main) {
+            Err(LoadError::Parse(diagnostics)) => {
+                let error = &diagnostics.errors[0];
+                let src = miette::Diagnostic::source_code(error).unwrap();
+                let contents = src.read_span(&(0, 1).into(), 0, 0).unwrap();
+                assert!(contents.name().unwrap().ends_with("broken.lg"));
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn dependencies_include_broken_files() {
+        let dir = TempDir::new().unwrap();
+        write(&dir, "broken.lg", "TO Box\n");
+        let main = write(&dir, "main.lg", "LOAD \"broken.lg\n");
+
+        let mut loader = Loader::new(Vec::new());
+        assert!(loader.load(&main).is_err());
+        let names: Vec<String> = loader
+            .dependencies()
+            .iter()
+            .map(|x| x.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["broken.lg", "main.lg"]);
+    }
+}
@@ -0,0 +1,259 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    canvas::Canvas,
+    errors::LSystemError,
+    tokens::{Command, EvalResult, Expression, Program},
+};
+
+/// What the turtle does when it reads a symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Action {
+    /// Move forward, drawing a line
+    Forward,
+
+    /// Move forward without drawing
+    Move,
+
+    /// Turn left by the angle
+    Left,
+
+    /// Turn right by the angle
+    Right,
+
+    /// Remember where the turtle is and which way it is facing
+    Push,
+
+    /// Go back to where the turtle was when it was last remembered, without drawing
+    Pop,
+
+    /// Do nothing, for symbols which only take part in rewriting
+    Ignore,
+}
+
+/// A grammar which rewrites a string of symbols, then draws it with the turtle, such as:
+///
+/// ```toml
+/// axiom = "F"
+/// angle = 60
+///
+/// [rules]
+/// F = "F+F--F+F"
+/// ```
+///
+/// Symbols without an action use the usual ones: `F` and `G` move forward drawing a line, `f`
+/// moves forward without drawing, `+` and `-` turn right and left, and `[` and `]` remember and
+/// go back to where the turtle was. Any other symbol does nothing when drawn.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LSystem {
+    /// The symbols to start rewriting from
+    pub axiom: String,
+
+    /// How far the turtle turns, in degrees
+    pub angle: f64,
+
+    /// How far the turtle moves, in pixels
+    #[serde(default = "default_length")]
+    pub length: f64,
+
+    /// What each symbol is replaced by on each iteration. Symbols without a rule stay as they are.
+    #[serde(default)]
+    pub rules: HashMap<char, String>,
+
+    /// What the turtle does for each symbol, overriding the usual actions
+    #[serde(default)]
+    pub actions: HashMap<char, Action>,
+}
+
+fn default_length() -> f64 {
+    10.0
+}
+
+impl LSystem {
+    /// Read a grammar written in TOML
+    pub fn from_toml(source: &str) -> Result<Self, LSystemError> {
+        toml::from_str(source).map_err(|e| LSystemError::Grammar(e.message().to_string()))
+    }
+
+    /// Read a grammar from a TOML file
+    pub fn load(path: &Path) -> Result<Self, LSystemError> {
+        let source: String = std::fs::read_to_string(path).map_err(|e| LSystemError::Io {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+        LSystem::from_toml(&source)
+    }
+
+    /// Rewrite the axiom the given number of times. Each iteration can multiply the length of the
+    /// string, so expanding stops as soon as it would make more than `max` symbols.
+    pub fn expand(&self, iterations: usize, max: usize) -> Result<Vec<char>, LSystemError> {
+        let mut symbols: Vec<char> = self.axiom.chars().collect();
+        for iteration in 1..=iterations {
+            // Work out how long the next iteration will be before building it
+            let length: usize = symbols
+                .iter()
+                .map(|x: &char| self.rules.get(x).map_or(1, |x| x.chars().count()))
+                .fold(0, usize::saturating_add);
+            if length > max {
+                return Err(LSystemError::TooLong {
+                    iterations: iteration,
+                    length,
+                    max,
+                });
+            }
+
+            symbols = symbols
+                .iter()
+                .flat_map(|x: &char| match self.rules.get(x) {
+                    Some(replacement) => replacement.chars().collect::<Vec<char>>(),
+                    None => vec![*x],
+                })
+                .collect();
+        }
+        Ok(symbols)
+    }
+
+    /// What the turtle does when it reads a symbol
+    pub fn action(&self, symbol: char) -> Action {
+        if let Some(action) = self.actions.get(&symbol) {
+            return *action;
+        }
+        match symbol {
+            'F' | 'G' => Action::Forward,
+            'f' => Action::Move,
+            '+' => Action::Right,
+            '-' => Action::Left,
+            '[' => Action::Push,
+            ']' => Action::Pop,
+            _ => Action::Ignore,
+        }
+    }
+
+    /// Expand the grammar, then draw it onto the canvas, starting from the middle and facing up
+    pub fn draw(
+        &self,
+        iterations: usize,
+        max: usize,
+        canvas: Canvas,
+    ) -> Result<Canvas, LSystemError> {
+        let symbols: Vec<char> = self.expand(iterations, max)?;
+
+        let mut program: Program = Program::with_canvas(Vec::new(), canvas);
+        let run = |program: &mut Program, commands: &[Command]| {
+            commands
+                .iter()
+                .try_for_each(|x: &Command| x.execute(program))
+        };
+        let float = |x: f64| Expression::Value(EvalResult::Float(x));
+        // `TURN` only takes angles from 0 to 360, so turning left is turning right the other way
+        let angle: f64 = self.angle.rem_euclid(360.0);
+
+        run(&mut program, &[Command::PenDown])?;
+        let mut stack: Vec<((f64, f64), f64)> = Vec::new();
+        for symbol in symbols {
+            match self.action(symbol) {
+                Action::Forward => run(&mut program, &[Command::Forward(float(self.length))])?,
+                Action::Move => run(
+                    &mut program,
+                    &[
+                        Command::PenUp,
+                        Command::Forward(float(self.length)),
+                        Command::PenDown,
+                    ],
+                )?,
+                Action::Right => run(&mut program, &[Command::Turn(float(angle))])?,
+                Action::Left => run(&mut program, &[Command::Turn(float(360.0 - angle))])?,
+                Action::Push => {
+                    let turtle = program.turtle();
+                    stack.push((turtle.get_turtle_coords(), turtle.get_heading()));
+                }
+                // Popping more than was pushed leaves the turtle where it is
+                Action::Pop => {
+                    if let Some(((x, y), heading)) = stack.pop() {
+                        run(
+                            &mut program,
+                            &[
+                                Command::PenUp,
+                                Command::SetX(float(x)),
+                                Command::SetY(float(y)),
+                                Command::SetHeading(float(heading.rem_euclid(360.0))),
+                                Command::PenDown,
+                            ],
+                        )?;
+                    }
+                }
+                Action::Ignore => (),
+            }
+        }
+        Ok(program.into_canvas())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KOCH: &str = r#"
+axiom = "F"
+angle = 90
+length = 5
+
+[rules]
+F = "F+F-F-F+F"
+"#;
+
+    #[test]
+    fn grammars_are_expanded() {
+        let koch = LSystem::from_toml(KOCH).unwrap();
+        assert_eq!(koch.expand(0, 100).unwrap(), vec!['F']);
+        assert_eq!(
+            koch.expand(1, 100).unwrap().into_iter().collect::<String>(),
+            "F+F-F-F+F"
+        );
+        assert_eq!(koch.expand(2, 100).unwrap().len(), 49);
+
+        assert!(matches!(
+            koch.expand(10, 1000),
+            Err(LSystemError::TooLong {
+                iterations: 4,
+                length: 1249,
+                max: 1000
+            })
+        ));
+    }
+
+    #[test]
+    fn grammars_are_drawn() {
+        let koch = LSystem::from_toml(KOCH).unwrap();
+        let canvas = koch.draw(1, 100, Canvas::new(100, 100)).unwrap();
+        let ends: Vec<(f64, f64)> = canvas.lines().iter().map(|x| x.end).collect();
+        assert_eq!(
+            ends,
+            vec![
+                (50.0, 45.0),
+                (55.0, 45.0),
+                (55.0, 40.0),
+                (50.0, 40.0),
+                (50.0, 35.0)
+            ]
+        );
+
+        let branches = LSystem::from_toml(
+            "axiom = \"F[+F]F\"\nangle = 90\n[actions]\nF = \"forward\"\nX = \"move\"\n",
+        )
+        .unwrap();
+        let canvas = branches.draw(0, 100, Canvas::new(100, 100)).unwrap();
+        assert_eq!(canvas.lines().len(), 3);
+        assert_eq!(canvas.lines()[2].start, (50.0, 40.0));
+
+        assert!(matches!(
+            LSystem::from_toml("axiom = \"F\"\nangle = 90\n[rules]\nFF = \"F\"\n"),
+            Err(LSystemError::Grammar(_))
+        ));
+    }
+}
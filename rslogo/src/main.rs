@@ -1,20 +1,133 @@
-mod errors;
-mod parsers;
-mod tokens;
-mod turtle;
+mod batch;
+mod debugger;
+mod serve;
+mod watch;
 
-use std::path::PathBuf;
+use std::{net::TcpListener, path::PathBuf, sync::Arc, time::Duration};
 
-use clap::Parser;
-// use unsvg::Image;
+use clap::{Parser, Subcommand, ValueEnum};
+use miette::{Context, IntoDiagnostic, Result};
+use rslogo::{
+    events::{self, EventLog},
+    loader::Loader,
+    lsystem::LSystem,
+    messages::{FailedCommand, Message},
+    observer::{Observer, Tracer},
+    optimise_with_tolerance,
+    profiler::Profiler,
+    space::{self, Projection, View},
+    state::State,
+    transpile::{self, Target},
+    Ast, Canvas, Interpreter, Limits, DEFAULT_MAX_DEPTH,
+};
 
-use miette::{Context, GraphicalReportHandler, IntoDiagnostic, Result};
-use tokens::{Command, Program};
+use crate::{
+    batch::{BatchError, RenderOptions, Rendered},
+    debugger::Debugger,
+    serve::{Preview, ServeError},
+    watch::Watch,
+};
 
-/// A simple program to parse four arguments using clap.
+/// Run a Logo program, saving what it draws as an image
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    #[command(flatten)]
+    run: Option<Args>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Step through a program interactively, with breakpoints
+    Debug(DebugArgs),
+
+    /// Expand an L-system grammar written in TOML, and draw it
+    Lsystem(LSystemArgs),
+
+    /// Run every Logo program in a directory in parallel, saving an image for each
+    Batch(BatchArgs),
+
+    /// Draw a program, then draw it again whenever it or a file it loads is saved. Each drawing
+    /// stops after 5 seconds unless `--max-time` is given.
+    Watch(WatchArgs),
+
+    /// Serve a page on localhost showing a program's drawing, which reloads whenever it is saved.
+    /// Each drawing stops after 5 seconds unless `--max-time` is given.
+    Serve(ServeArgs),
+
+    /// Draw the lines and labels recorded in an event log, without the program which made it
+    Replay(ReplayArgs),
+
+    /// Write a program as a standalone Python or JavaScript script which draws the same thing
+    Transpile(TranspileArgs),
+}
+
+/// How long a program may run for in watch and serve mode, in seconds, unless `--max-time` is
+/// given
+const LIVE_MAX_TIME: f64 = 5.0;
+
+/// Limits on the resources a program may use
+#[derive(clap::Args)]
+struct LimitArgs {
+    /// Stop after executing this many commands
+    #[arg(long)]
+    max_commands: Option<u64>,
+
+    /// Stop after running for this many seconds
+    #[arg(long)]
+    max_time: Option<f64>,
+
+    /// Stop after drawing this many lines
+    #[arg(long)]
+    max_segments: Option<usize>,
+
+    /// Stop if variables take up more than this many bytes
+    #[arg(long)]
+    max_memory: Option<usize>,
+
+    /// Stop if procedure calls nest more than this many deep
+    #[arg(long, default_value_t = DEFAULT_MAX_DEPTH)]
+    max_depth: usize,
+}
+
+impl LimitArgs {
+    fn limits(&self) -> Result<Limits> {
+        Ok(Limits {
+            max_commands: self.max_commands,
+            max_time: self
+                .max_time
+                .map(Duration::try_from_secs_f64)
+                .transpose()
+                .into_diagnostic()
+                .wrap_err("Invalid time limit.")?,
+            max_segments: self.max_segments,
+            max_memory: self.max_memory,
+            max_depth: Some(self.max_depth),
+        })
+    }
+
+    /// Limits for drawing a program each time it is saved. A half-written program may loop
+    /// forever, which would stop every later save from being drawn, so time is always limited.
+    fn live_limits(&self) -> Result<Limits> {
+        let mut limits: Limits = self.limits()?;
+        limits
+            .max_time
+            .get_or_insert(Duration::from_secs_f64(LIVE_MAX_TIME));
+        Ok(limits)
+    }
+}
+
+/// A simple program to parse four arguments using clap.
+#[derive(clap::Args)]
+// clap leaves the group of a struct which flattens another one empty, so `Cli` can only tell
+// these arguments were given once one of them is added to the group by hand
+#[group(id = "run")]
 struct Args {
     /// Path to a file
+    #[arg(group = "run")]
     file_path: std::path::PathBuf,
 
     /// Path to an svg or png image
@@ -25,72 +138,651 @@ struct Args {
 
     /// Width
     width: u32,
+
+    /// Directories to search for files named by `LOAD`
+    #[arg(short = 'I', long = "include")]
+    include: Vec<PathBuf>,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+
+    /// Scale the drawing to fill the image, rather than drawing it where the turtle went
+    #[arg(long)]
+    fit: bool,
+
+    /// Let `EQ` and `NE` treat numbers which are at most this far apart as equal
+    #[arg(long, default_value_t = 0.0, value_name = "TOLERANCE")]
+    eq_tolerance: f64,
+
+    /// Read the input of `READWORD` and `READLIST` from a file, rather than from stdin
+    #[arg(long, value_name = "PATH")]
+    input: Option<PathBuf>,
+
+    /// Fold constants, remove dead code and hoist loop invariants before running the program
+    #[arg(short = 'O', long)]
+    optimise: bool,
+
+    /// Print each command as it runs, along with the state of the turtle afterwards
+    #[arg(long)]
+    trace: bool,
+
+    /// Print how many times each procedure and loop ran, and how long it took
+    #[arg(long)]
+    profile: bool,
+
+    /// Write the call stacks seen while profiling to a file, in the folded format read by
+    /// flamegraph tools
+    #[arg(long, value_name = "PATH")]
+    profile_stacks: Option<PathBuf>,
+
+    /// Write everything the turtle does to a file as JSON Lines, which `replay` can draw again
+    #[arg(long, value_name = "PATH")]
+    events: Option<PathBuf>,
+
+    /// Restore a state saved by `SAVESTATE`, and carry on from the command after it. The program
+    /// must be the same, and run with the same flags, as when it was saved.
+    #[arg(long, value_name = "PATH")]
+    resume: Option<PathBuf>,
+
+    /// Let the turtle move in 3D, with `UP`, `DOWN`, `ROLLLEFT`, `ROLLRIGHT` and `SETZ`
+    #[arg(long = "3d")]
+    three_d: bool,
+
+    /// How the turtle's path in 3D is drawn onto the image
+    #[arg(long, value_enum, default_value_t = ProjectionArg::Orthographic, requires = "three_d")]
+    projection: ProjectionArg,
+
+    /// How far in front of the middle of the image the camera is, in pixels, for a perspective
+    /// projection
+    #[arg(long, default_value_t = 1000.0, requires = "three_d")]
+    camera_distance: f64,
+
+    /// Turn the drawing this many degrees about the vertical axis before it is projected
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        requires = "three_d"
+    )]
+    view_yaw: f64,
+
+    /// Tilt the drawing this many degrees about the horizontal axis before it is projected
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        requires = "three_d"
+    )]
+    view_pitch: f64,
+
+    /// Also save the turtle's path in 3D as an OBJ file of polylines
+    #[arg(long, value_name = "PATH", requires = "three_d")]
+    obj: Option<PathBuf>,
+
+    /// How to report errors. `json` writes each error to stdout as a line of JSON, with where it
+    /// is in the file and the kind of error it is.
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    message_format: MessageFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MessageFormat {
+    /// Errors are drawn with the source they point at
+    Human,
+
+    /// Errors are written as JSON Lines, for tools to read
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ProjectionArg {
+    /// Things look the same size however far away they are
+    Orthographic,
+
+    /// Things further away look smaller
+    Perspective,
+}
+
+impl Args {
+    /// How the turtle's path is drawn, if it moves in 3D
+    fn view(&self) -> Option<View> {
+        self.three_d.then_some(View {
+            projection: match self.projection {
+                ProjectionArg::Orthographic => Projection::Orthographic,
+                ProjectionArg::Perspective => Projection::Perspective {
+                    distance: self.camera_distance,
+                },
+            },
+            yaw: self.view_yaw,
+            pitch: self.view_pitch,
+        })
+    }
+}
+
+#[derive(clap::Args)]
+struct DebugArgs {
+    /// Path to a file
+    file_path: std::path::PathBuf,
+
+    /// Directories to search for files named by `LOAD`
+    #[arg(short = 'I', long = "include")]
+    include: Vec<PathBuf>,
+
+    /// Height of the canvas the turtle starts in the middle of
+    #[arg(long, default_value_t = 500)]
+    height: u32,
+
+    /// Width of the canvas the turtle starts in the middle of
+    #[arg(long, default_value_t = 500)]
+    width: u32,
+}
+
+#[derive(clap::Args)]
+struct LSystemArgs {
+    /// Path to a TOML file with the grammar
+    grammar_path: PathBuf,
+
+    /// Path to an svg or png image
+    image_path: PathBuf,
+
+    /// How many times to rewrite the axiom
+    #[arg(short = 'n', long, default_value_t = 4)]
+    iterations: usize,
+
+    /// Stop if expanding the grammar makes more than this many symbols
+    #[arg(long, default_value_t = 1_000_000)]
+    max_symbols: usize,
+
+    /// Height of the image
+    #[arg(long, default_value_t = 500)]
+    height: u32,
+
+    /// Width of the image
+    #[arg(long, default_value_t = 500)]
+    width: u32,
+
+    /// Scale the drawing to fill the image, rather than drawing it where the turtle went
+    #[arg(long)]
+    fit: bool,
+}
+
+#[derive(clap::Args)]
+struct BatchArgs {
+    /// Directory of `.lg` files to run
+    source_dir: PathBuf,
+
+    /// Directory to save the images in, named after their programs
+    #[arg(long)]
+    out: PathBuf,
+
+    /// Format of the images, such as svg or png
+    #[arg(long, default_value = "svg")]
+    format: String,
+
+    /// Directories to search for files named by `LOAD`
+    #[arg(short = 'I', long = "include")]
+    include: Vec<PathBuf>,
+
+    /// Height of each image
+    #[arg(long, default_value_t = 500)]
+    height: u32,
+
+    /// Width of each image
+    #[arg(long, default_value_t = 500)]
+    width: u32,
+
+    /// Scale each drawing to fill its image, rather than drawing it where the turtle went
+    #[arg(long)]
+    fit: bool,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+}
+
+#[derive(clap::Args)]
+struct WatchArgs {
+    /// Path to a file
+    file_path: PathBuf,
+
+    /// Path to an svg or png image
+    image_path: PathBuf,
+
+    /// Directories to search for files named by `LOAD`
+    #[arg(short = 'I', long = "include")]
+    include: Vec<PathBuf>,
+
+    /// Height of the image
+    #[arg(long, default_value_t = 500)]
+    height: u32,
+
+    /// Width of the image
+    #[arg(long, default_value_t = 500)]
+    width: u32,
+
+    /// Scale the drawing to fill the image, rather than drawing it where the turtle went
+    #[arg(long)]
+    fit: bool,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+}
+
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// Path to a file
+    file_path: PathBuf,
+
+    /// The port to serve the page on
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+
+    /// Where to save the drawing, which defaults to an SVG next to the file
+    #[arg(long, value_name = "PATH")]
+    image: Option<PathBuf>,
+
+    /// Directories to search for files named by `LOAD`
+    #[arg(short = 'I', long = "include")]
+    include: Vec<PathBuf>,
+
+    /// Height of the image
+    #[arg(long, default_value_t = 500)]
+    height: u32,
+
+    /// Width of the image
+    #[arg(long, default_value_t = 500)]
+    width: u32,
+
+    /// Scale the drawing to fill the image, rather than drawing it where the turtle went
+    #[arg(long)]
+    fit: bool,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+}
+
+#[derive(clap::Args)]
+struct ReplayArgs {
+    /// Path to an event log written with `--events`
+    log_path: PathBuf,
+
+    /// Path to an svg or png image
+    image_path: PathBuf,
+
+    /// Scale the drawing to fill the image, rather than drawing it where the turtle went
+    #[arg(long)]
+    fit: bool,
+}
+
+#[derive(clap::Args)]
+struct TranspileArgs {
+    /// Language to write the script in
+    #[arg(long = "to")]
+    target: TargetArg,
+
+    /// Path to a file
+    file_path: PathBuf,
+
+    /// Path to write the script to, rather than the standard output
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Directories to search for files named by `LOAD`
+    #[arg(short = 'I', long = "include")]
+    include: Vec<PathBuf>,
+
+    /// Height of the canvas the script draws on
+    #[arg(long, default_value_t = 500)]
+    height: u32,
+
+    /// Width of the canvas the script draws on
+    #[arg(long, default_value_t = 500)]
+    width: u32,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TargetArg {
+    /// A Python 3 script, drawing with turtle graphics
+    Python,
+
+    /// A script for a web page, drawing on a canvas
+    #[value(name = "js")]
+    JavaScript,
 }
 
 fn main() -> Result<()> {
     miette::set_panic_hook();
-    let args: Args = Args::parse();
+    match Cli::parse() {
+        Cli {
+            command: Some(Commands::Debug(args)),
+            ..
+        } => debug(args),
+        Cli {
+            command: Some(Commands::Lsystem(args)),
+            ..
+        } => lsystem(args),
+        Cli {
+            command: Some(Commands::Batch(args)),
+            ..
+        } => batch(args),
+        Cli {
+            command: Some(Commands::Watch(args)),
+            ..
+        } => watch(args),
+        Cli {
+            command: Some(Commands::Serve(args)),
+            ..
+        } => serve(args),
+        Cli {
+            command: Some(Commands::Replay(args)),
+            ..
+        } => replay(args),
+        Cli {
+            command: Some(Commands::Transpile(args)),
+            ..
+        } => transpile(args),
+        Cli {
+            run: Some(args), ..
+        } => run(args),
+        Cli { .. } => unreachable!("clap requires either a subcommand or the arguments to run"),
+    }
+}
 
-    // Access the parsed arguments
-    let file_path = args.file_path;
+/// Step through a program, reading debugger commands from stdin
+fn debug(args: DebugArgs) -> Result<()> {
+    let ast: Ast = Loader::new(args.include).load(&args.file_path)?;
 
-    let file = match std::fs::read_to_string(file_path)
-        // Let miette handle the diagnostics for any file opening failure
-        .into_diagnostic()
-        // Add some context to the error
-        .wrap_err("Failed to open file.")
+    let debugger = Debugger::new(std::io::BufReader::new(std::io::stdin()), std::io::stdout());
+    let mut interpreter: Interpreter =
+        Interpreter::new(Canvas::new(args.width, args.height)).with_observer(debugger);
+    interpreter.run(&ast).wrap_err("Failed to execute program.")
+}
+
+/// Draw an L-system, without writing a Logo program for it
+fn lsystem(args: LSystemArgs) -> Result<()> {
+    let grammar: LSystem = LSystem::load(&args.grammar_path)?;
+    let canvas: Canvas = grammar
+        .draw(
+            args.iterations,
+            args.max_symbols,
+            Canvas::new(args.width, args.height),
+        )
+        .wrap_err("Failed to draw L-system.")?;
+    match args.fit {
+        true => canvas.fit().save(&args.image_path)?,
+        false => canvas.save(&args.image_path)?,
+    }
+    Ok(())
+}
+
+/// Render every program in a directory, then report which of them failed
+fn batch(args: BatchArgs) -> Result<()> {
+    let programs: Vec<PathBuf> = batch::find_programs(&args.source_dir)?;
+    std::fs::create_dir_all(&args.out).map_err(|e| BatchError::CreateDir {
+        path: args.out.clone(),
+        source: e,
+    })?;
+    let options = RenderOptions {
+        width: args.width,
+        height: args.height,
+        include: args.include,
+        limits: args.limits.limits()?,
+        fit: args.fit,
+    };
+
+    let rendered: Vec<Rendered> = batch::render(&programs, &args.out, &args.format, &options);
+    print!("{}", batch::summary(&rendered));
+    let failed: usize = rendered.iter().filter(|x| x.result.is_err()).count();
+    match failed {
+        0 => Ok(()),
+        _ => Err(miette::miette!(
+            "{failed} of {} programs failed.",
+            rendered.len()
+        )),
+    }
+}
+
+/// Draw a program every time it changes, until interrupted. Errors are reported without
+/// stopping, so they can be fixed while watching.
+fn watch(args: WatchArgs) -> Result<()> {
+    let options = RenderOptions {
+        width: args.width,
+        height: args.height,
+        include: args.include,
+        limits: args.limits.live_limits()?,
+        fit: args.fit,
+    };
+    let mut watch: Watch = Watch::new(&args.file_path, &args.image_path, options)?;
+    loop {
+        match watch.render()? {
+            Ok(()) => eprintln!("Drew {}", args.image_path.display()),
+            Err(e) => eprintln!(
+                "{e:?}\nKeeping the last drawing in {}",
+                args.image_path.display()
+            ),
+        }
+        eprintln!("Watching {} file(s) for changes...", watch.files().len());
+        watch.wait()?;
+    }
+}
+
+/// Serve a page showing a program's drawing, drawing it again every time it changes, until
+/// interrupted
+fn serve(args: ServeArgs) -> Result<()> {
+    let image: PathBuf = args
+        .image
+        .unwrap_or_else(|| args.file_path.with_extension("svg"));
+    let options = RenderOptions {
+        width: args.width,
+        height: args.height,
+        include: args.include,
+        limits: args.limits.live_limits()?,
+        fit: args.fit,
+    };
+    // Only this machine can see the page
+    let listener: TcpListener =
+        TcpListener::bind(("127.0.0.1", args.port)).map_err(|e| ServeError::Bind {
+            port: args.port,
+            source: e,
+        })?;
+    let preview: Arc<Preview> = Arc::new(Preview::new(&image));
+    let mut watch: Watch = Watch::new(&args.file_path, &image, options)?;
     {
-        Ok(res) => res,
-        Err(error) => return Err(error),
+        let preview: Arc<Preview> = preview.clone();
+        std::thread::spawn(move || serve::serve(listener, preview));
+    }
+    eprintln!(
+        "Serving {} at http://localhost:{}/",
+        args.file_path.display(),
+        args.port
+    );
+    loop {
+        let result: miette::Result<()> = watch.render()?;
+        match &result {
+            Ok(()) => eprintln!("Drew {}", image.display()),
+            Err(e) => eprintln!("{e:?}\nKeeping the last drawing in {}", image.display()),
+        }
+        preview.update(&result);
+        watch.wait()?;
+    }
+}
+
+/// Draw an event log onto an image the size of the canvas it was recorded on
+fn replay(args: ReplayArgs) -> Result<()> {
+    let log = std::fs::File::open(&args.log_path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to open {}.", args.log_path.display()))?;
+    let canvas: Canvas = events::replay(std::io::BufReader::new(log))?;
+    match args.fit {
+        true => canvas.fit().save(&args.image_path)?,
+        false => canvas.save(&args.image_path)?,
+    }
+    Ok(())
+}
+
+/// Write a program in another language, without running it
+fn transpile(args: TranspileArgs) -> Result<()> {
+    let ast: Ast = Loader::new(args.include).load(&args.file_path)?;
+    let target: Target = match args.target {
+        TargetArg::Python => Target::Python,
+        TargetArg::JavaScript => Target::JavaScript,
     };
+    let script: String = transpile::transpile(&ast, target, args.width, args.height)?;
+    match &args.output {
+        Some(path) => std::fs::write(path, script)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write {}.", path.display())),
+        None => {
+            print!("{script}");
+            Ok(())
+        }
+    }
+}
 
-    let _image_path: PathBuf = args.image_path;
-    let _height: u32 = args.height;
-    let _width: u32 = args.width;
-
-    let commands: Vec<Command> =
-        match crate::parsers::parse(Box::leak(file.to_string().into_boxed_str())) {
-            Ok(res) => res,
-            Err(e) => {
-                println!("{:?}", e);
-                let mut s = String::new();
-                GraphicalReportHandler::new()
-                    .render_report(&mut s, &e)
-                    .unwrap();
-                println!("{s}");
-                return Err(e).into_diagnostic();
+fn run(args: Args) -> Result<()> {
+    // Only JSON messages say which command failed, so it isn't tracked otherwise
+    let failed: Option<FailedCommand> =
+        (args.message_format == MessageFormat::Json).then(FailedCommand::new);
+    let result: Result<()> = execute(args, failed.clone());
+    match (failed, result) {
+        (Some(failed), Err(report)) => {
+            for message in Message::from_report(&report, failed.location().as_ref()) {
+                println!("{}", serde_json::to_string(&message).into_diagnostic()?);
             }
-        };
+            std::process::exit(1);
+        }
+        (_, result) => result,
+    }
+}
+
+/// Run a program and save its drawing, remembering where it failed if it does
+fn execute(args: Args, failed: Option<FailedCommand>) -> Result<()> {
+    let view: Option<View> = args.view();
+    // Every syntax error in a file is reported at once, rather than only the first one
+    let mut ast: Ast = Loader::new(args.include).load(&args.file_path)?;
+    if args.optimise {
+        ast = optimise_with_tolerance(&ast, args.eq_tolerance);
+    }
+
+    let mut interpreter: Interpreter = Interpreter::new(Canvas::new(args.width, args.height))
+        .with_limits(args.limits.limits()?)
+        .with_tolerance(args.eq_tolerance);
+    if let Some(path) = &args.input {
+        let input = std::fs::File::open(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to open input {}.", path.display()))?;
+        interpreter = interpreter.with_input(std::io::BufReader::new(input));
+    }
+    if let Some(view) = view {
+        interpreter = interpreter.with_view(view);
+    }
+    let profiler: Option<Profiler> =
+        (args.profile || args.profile_stacks.is_some()).then(Profiler::new);
+    let mut observers: Vec<Box<dyn Observer>> = Vec::new();
+    if args.trace {
+        observers.push(Box::new(Tracer::new(std::io::stderr())));
+    }
+    if let Some(profiler) = &profiler {
+        observers.push(Box::new(profiler.clone()));
+    }
+    if let Some(path) = &args.events {
+        let log: EventLog<_> = std::fs::File::create(path)
+            .map(std::io::BufWriter::new)
+            .and_then(|x| EventLog::new(x, args.width, args.height))
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write events to {}.", path.display()))?;
+        observers.push(Box::new(log));
+    }
+    if let Some(failed) = failed {
+        observers.push(Box::new(failed));
+    }
+    if !observers.is_empty() {
+        interpreter = interpreter.with_observer(observers);
+    }
+    let result = match &args.resume {
+        Some(path) => interpreter.resume(&ast, State::load(path)?),
+        None => interpreter.run(&ast),
+    };
+
+    // A profile is most useful when a program is too slow, so report it even if the program was
+    // stopped by a limit
+    if let Some(profiler) = profiler {
+        let profile = profiler.profile();
+        eprint!("{}", profile.report());
+        if let Some(path) = &args.profile_stacks {
+            std::fs::write(path, profile.folded())
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to write call stacks to {}.", path.display()))?;
+        }
+    }
+    result.wrap_err("Failed to execute program.")?;
 
-    let program: Program = Program::new(commands);
-
-    println!("{:?}", file);
-    println!("{:?}", program.commands);
-
-    // There should be no remainder string from the parser. This should be ensured by nom's all_consuming
-
-    // let image = Image::new(width, height);
-
-    // match image_path.extension().map(|s| s.to_str()).flatten() {
-    //     Some("svg") => {
-    //         let res = image.save_svg(&image_path);
-    //         if let Err(e) = res {
-    //             eprintln!("Error saving svg: {e}");
-    //             return Err(());
-    //         }
-    //     }
-    //     Some("png") => {
-    //         let res = image.save_png(&image_path);
-    //         if let Err(e) = res {
-    //             eprintln!("Error saving png: {e}");
-    //             return Err(());
-    //         }
-    //     }
-    //     _ => {
-    //         eprintln!("File extension not supported");
-    //         return Err(());
-    //     }
-    // }
+    match args.fit {
+        true => interpreter.canvas().fit().save(&args.image_path)?,
+        false => interpreter.canvas().save(&args.image_path)?,
+    }
+    if let Some(path) = &args.obj {
+        std::fs::write(path, space::obj(interpreter.canvas().segments()))
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to save {}.", path.display()))?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_are_read_with_or_without_a_subcommand() {
+        let cli =
+            Cli::try_parse_from(["rslogo", "a.lg", "a.svg", "10", "20", "--max-segments", "3"])
+                .unwrap();
+        assert!(cli.command.is_none());
+        let args = cli.run.unwrap();
+        assert_eq!((args.height, args.width), (10, 20));
+        assert_eq!(args.limits.limits().unwrap().max_segments, Some(3));
+
+        let cli = Cli::try_parse_from([
+            "rslogo",
+            "batch",
+            "programs",
+            "--out",
+            "images",
+            "--max-segments",
+            "3",
+        ])
+        .unwrap();
+        assert!(cli.run.is_none());
+        let Some(Commands::Batch(args)) = cli.command else {
+            panic!("batch should be the subcommand");
+        };
+        assert_eq!(args.limits.limits().unwrap().max_segments, Some(3));
+    }
+
+    #[test]
+    fn live_drawings_are_always_limited() {
+        let Some(Commands::Watch(args)) = Cli::try_parse_from(["rslogo", "watch", "a.lg", "a.svg"])
+            .unwrap()
+            .command
+        else {
+            panic!("watch should be the subcommand");
+        };
+        assert_eq!(
+            args.limits.live_limits().unwrap().max_time,
+            Some(Duration::from_secs_f64(LIVE_MAX_TIME))
+        );
+
+        let Some(Commands::Serve(args)) =
+            Cli::try_parse_from(["rslogo", "serve", "a.lg", "--max-time", "1"])
+                .unwrap()
+                .command
+        else {
+            panic!("serve should be the subcommand");
+        };
+        assert_eq!(
+            args.limits.live_limits().unwrap().max_time,
+            Some(Duration::from_secs(1))
+        );
+    }
+}
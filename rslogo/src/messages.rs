@@ -0,0 +1,224 @@
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use crate::{
+    errors::{Diagnostics, InterpreterError, LoadError, ParseError, ParseErrorContext, SourceFile},
+    observer::Observer,
+    tokens::{Command, Location, Program},
+};
+
+/// Where a message points in a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Span {
+    /// How many bytes into the file the span starts
+    pub offset: usize,
+
+    /// How many bytes long the span is
+    pub length: usize,
+
+    /// The line the span starts on, starting from 1
+    pub line: usize,
+
+    /// The character the span starts at in its line, starting from 1
+    pub column: usize,
+}
+
+/// An error written for tools to read, such as with `--message-format json`, rather than for
+/// people
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Message {
+    /// What sort of error it is, such as `unclosed_bracket` or `undefined_variable`
+    pub kind: String,
+
+    pub message: String,
+
+    /// The file the error was found in, if it was found in one
+    pub file: Option<String>,
+
+    pub span: Option<Span>,
+
+    pub help: Option<String>,
+
+    /// What the parser was doing when it found the error, from the innermost out
+    pub context: Vec<Message>,
+}
+
+impl Message {
+    fn new(kind: &str, message: String) -> Self {
+        Message {
+            kind: kind.to_string(),
+            message,
+            file: None,
+            span: None,
+            help: None,
+            context: Vec::new(),
+        }
+    }
+
+    /// Point the message at a span of a file
+    fn at(mut self, src: Option<&SourceFile>, span: miette::SourceSpan) -> Self {
+        let Some(src) = src else {
+            return self;
+        };
+        let text: &str = src.inner();
+        let before: &str = text.get(..span.offset()).unwrap_or(text);
+        let line_start: &str = before.rsplit('\n').next().unwrap_or("");
+        self.file = Some(src.name().to_string());
+        self.span = Some(Span {
+            offset: span.offset(),
+            length: span.len(),
+            line: before.matches('\n').count() + 1,
+            column: line_start.chars().count() + 1,
+        });
+        self
+    }
+
+    pub fn from_parse_error(error: &ParseError) -> Self {
+        Message {
+            help: error.help.clone(),
+            context: error.others.iter().map(Message::from_context).collect(),
+            ..Message::new(error.kind.name(), error.kind.to_string())
+                .at(Some(&error.src), error.span)
+        }
+    }
+
+    fn from_context(context: &ParseErrorContext) -> Self {
+        Message::new("context", context.context.to_string()).at(Some(&context.src), context.span)
+    }
+
+    /// A message for an error which stopped a program, pointing at the command which failed if
+    /// it is known
+    pub fn from_interpreter_error(error: &InterpreterError, location: Option<&Location>) -> Self {
+        let help: Option<String> = miette::Diagnostic::help(error).map(|x| x.to_string());
+        let message = Message {
+            help,
+            ..Message::new(error.kind(), error.to_string())
+        };
+        match location {
+            Some(location) => message.at(location.src.as_ref(), location.span),
+            None => message,
+        }
+    }
+
+    /// Every message for an error reported by the command line, with the error which caused it
+    /// if the report wraps one
+    pub fn from_report(report: &miette::Report, location: Option<&Location>) -> Vec<Self> {
+        if let Some(diagnostics) = report.downcast_ref::<Diagnostics>() {
+            return diagnostics
+                .errors()
+                .iter()
+                .map(Message::from_parse_error)
+                .collect();
+        }
+        if let Some(error) = report.downcast_ref::<InterpreterError>() {
+            return vec![Message::from_interpreter_error(error, location)];
+        }
+        let message = Message {
+            help: report.help().map(|x| x.to_string()),
+            ..Message::new(
+                "error",
+                report
+                    .chain()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(": "),
+            )
+        };
+        match report.downcast_ref::<LoadError>() {
+            Some(LoadError::Parse(diagnostics)) => diagnostics
+                .errors()
+                .iter()
+                .map(Message::from_parse_error)
+                .collect(),
+            Some(LoadError::NotFound { src, span, .. }) => vec![Message {
+                kind: "not_found".to_string(),
+                ..message.at(Some(src), *span)
+            }],
+            Some(LoadError::Cycle { src, span, .. }) => vec![Message {
+                kind: "load_cycle".to_string(),
+                ..message.at(Some(src), *span)
+            }],
+            Some(LoadError::Io { .. }) | None => vec![message],
+        }
+    }
+}
+
+/// Remembers the innermost command which failed, so an error which stopped the program can say
+/// where it happened. Clones share what they remember.
+#[derive(Debug, Clone, Default)]
+pub struct FailedCommand {
+    location: Arc<Mutex<Option<Location>>>,
+}
+
+impl FailedCommand {
+    pub fn new() -> Self {
+        FailedCommand::default()
+    }
+
+    /// Where the first command which failed was written, if any did
+    pub fn location(&self) -> Option<Location> {
+        self.location.lock().unwrap().clone()
+    }
+}
+
+impl Observer for FailedCommand {
+    fn after(
+        &mut self,
+        _program: &Program,
+        location: &Location,
+        _command: &Command,
+        result: &Result<(), InterpreterError>,
+    ) {
+        // Commands are told they failed from the innermost out, so only the first one counts
+        let mut failed = self.location.lock().unwrap();
+        if result.is_err() && failed.is_none() {
+            *failed = Some(location.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse,
+        test_support::{interpreter, run},
+    };
+
+    #[test]
+    fn describes_parse_errors() {
+        let Err(diagnostics) = parse("PENDOWN\nIF EQ \"1 \"1 [\n  FORWARD \"1\n") else {
+            panic!("the program should not parse");
+        };
+        let message = Message::from_parse_error(&diagnostics.errors()[0]);
+        assert_eq!(message.kind, "unclosed_bracket");
+        assert_eq!(message.file.as_deref(), Some("input"));
+        assert_eq!(
+            message.span,
+            Some(Span {
+                offset: 20,
+                length: 1,
+                line: 2,
+                column: 13,
+            })
+        );
+        assert!(message.help.is_some());
+    }
+
+    #[test]
+    fn points_at_the_command_which_failed() {
+        let failed = FailedCommand::new();
+        let error = run(
+            &mut interpreter().with_observer(failed.clone()),
+            "TO Step\n  FORWARD :missing\nEND\nPENDOWN\nStep\n",
+        )
+        .unwrap_err();
+
+        let message = Message::from_interpreter_error(&error, failed.location().as_ref());
+        assert_eq!(message.kind, "undefined_variable");
+        assert_eq!(message.message, "Variable not found: missing");
+        let span = message.span.unwrap();
+        assert_eq!((span.line, span.column), (2, 3));
+    }
+}
@@ -0,0 +1,207 @@
+use std::io::Write;
+
+use crate::{errors::InterpreterError, turtle::PenState, Command, Frame, Location, Program};
+
+/// Watches a program as it runs. The observer is told about every command the parser found,
+/// including those inside blocks and procedures, along with where it was written.
+pub trait Observer: Send {
+    /// Called before a command runs. Returning an error stops the program.
+    fn before(
+        &mut self,
+        _program: &Program,
+        _location: &Location,
+        _command: &Command,
+    ) -> Result<(), InterpreterError> {
+        Ok(())
+    }
+
+    /// Called after a command runs, whether or not it succeeded
+    fn after(
+        &mut self,
+        _program: &Program,
+        _location: &Location,
+        _command: &Command,
+        _result: &Result<(), InterpreterError>,
+    ) {
+    }
+
+    /// Called when the program enters a procedure call or loop, however it was started. The frame
+    /// is already on the program's call stack.
+    fn enter(&mut self, _program: &Program, _frame: &Frame) {}
+
+    /// Called when the program leaves the frame it entered most recently, while the frame is
+    /// still on the program's call stack
+    fn leave(&mut self, _program: &Program, _frame: &Frame) {}
+}
+
+/// Every observer watches the program, in order. Later observers are only told about a command
+/// if every earlier one lets it run.
+impl Observer for Vec<Box<dyn Observer>> {
+    fn before(
+        &mut self,
+        program: &Program,
+        location: &Location,
+        command: &Command,
+    ) -> Result<(), InterpreterError> {
+        self.iter_mut()
+            .try_for_each(|x| x.before(program, location, command))
+    }
+
+    fn after(
+        &mut self,
+        program: &Program,
+        location: &Location,
+        command: &Command,
+        result: &Result<(), InterpreterError>,
+    ) {
+        for observer in self.iter_mut() {
+            observer.after(program, location, command, result);
+        }
+    }
+
+    fn enter(&mut self, program: &Program, frame: &Frame) {
+        for observer in self.iter_mut() {
+            observer.enter(program, frame);
+        }
+    }
+
+    fn leave(&mut self, program: &Program, frame: &Frame) {
+        for observer in self.iter_mut() {
+            observer.leave(program, frame);
+        }
+    }
+}
+
+/// An observer which may be turned off, such as by a command line flag
+impl<T: Observer> Observer for Option<T> {
+    fn before(
+        &mut self,
+        program: &Program,
+        location: &Location,
+        command: &Command,
+    ) -> Result<(), InterpreterError> {
+        match self {
+            Some(observer) => observer.before(program, location, command),
+            None => Ok(()),
+        }
+    }
+
+    fn after(
+        &mut self,
+        program: &Program,
+        location: &Location,
+        command: &Command,
+        result: &Result<(), InterpreterError>,
+    ) {
+        if let Some(observer) = self {
+            observer.after(program, location, command, result);
+        }
+    }
+
+    fn enter(&mut self, program: &Program, frame: &Frame) {
+        if let Some(observer) = self {
+            observer.enter(program, frame);
+        }
+    }
+
+    fn leave(&mut self, program: &Program, frame: &Frame) {
+        if let Some(observer) = self {
+            observer.leave(program, frame);
+        }
+    }
+}
+
+/// Describe the state of the turtle in a single line
+pub fn turtle_state(program: &Program) -> String {
+    let turtle = program.turtle();
+    let (x, y) = turtle.get_turtle_coords();
+    let pen: &str = match turtle.get_pen_state() {
+        PenState::Up => "up",
+        PenState::Down => "down",
+    };
+    format!(
+        "x={x:.2} y={y:.2} heading={:.2} pen={pen} colour={}",
+        turtle.get_heading(),
+        turtle.get_pen_colour()
+    )
+}
+
+/// Writes each command after it runs, along with the state of the turtle afterwards. Commands
+/// inside procedures are indented by how deep the procedure call is.
+pub struct Tracer<W> {
+    output: W,
+}
+
+impl<W: Write + Send> Tracer<W> {
+    pub fn new(output: W) -> Self {
+        Tracer { output }
+    }
+}
+
+impl<W: Write + Send> Observer for Tracer<W> {
+    fn after(
+        &mut self,
+        program: &Program,
+        location: &Location,
+        _command: &Command,
+        result: &Result<(), InterpreterError>,
+    ) {
+        let state: String = match result {
+            Ok(()) => turtle_state(program),
+            Err(e) => format!("error: {e}"),
+        };
+        // Tracing is best-effort, so a closed output shouldn't stop the program
+        let _ = writeln!(
+            self.output,
+            "{}{}:{}: {}  [{state}]",
+            "  ".repeat(program.depth()),
+            location.file_name(),
+            location.line,
+            location.source_line().trim(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{interpreter, run, SharedBuffer};
+
+    #[test]
+    fn traces_every_command() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = interpreter().with_observer(Tracer::new(buffer.clone()));
+        run(
+            &mut interpreter,
+            "TO Step\n  FORWARD \"10\nEND\nPENDOWN\nStep\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            buffer.contents().lines().collect::<Vec<_>>(),
+            vec![
+                "input:1: TO Step  [x=50.00 y=50.00 heading=0.00 pen=up colour=0]",
+                "input:4: PENDOWN  [x=50.00 y=50.00 heading=0.00 pen=down colour=0]",
+                "  input:2: FORWARD \"10  [x=50.00 y=40.00 heading=0.00 pen=down colour=0]",
+                "input:5: Step  [x=50.00 y=40.00 heading=0.00 pen=down colour=0]",
+            ]
+        );
+    }
+
+    #[test]
+    fn every_observer_in_a_list_watches() {
+        let (first, second) = (SharedBuffer::default(), SharedBuffer::default());
+        let observers: Vec<Box<dyn Observer>> = vec![
+            Box::new(Tracer::new(first.clone())),
+            Box::new(Tracer::new(second.clone())),
+        ];
+        run(&mut interpreter().with_observer(observers), "PENDOWN\n").unwrap();
+
+        for buffer in [first, second] {
+            assert_eq!(
+                buffer.contents(),
+                "input:1: PENDOWN  [x=50.00 y=50.00 heading=0.00 pen=down colour=0]\n"
+            );
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::HashSet;
+
+use crate::tokens::{Ast, Command, EvalResult, Expression, Location, Program};
+
+/// The constructor of an expression with two operands, such as `Expression::Add`
+type Operator = fn(Box<Expression>, Box<Expression>) -> Expression;
+
+/// The constructor of an expression with one operand, such as `Expression::Count`
+type UnaryOperator = fn(Box<Expression>) -> Expression;
+
+/// Optimise a program without changing what it draws:
+///
+/// - Arithmetic, comparisons and logic on constants are worked out ahead of time.
+/// - `IF` and `WHILE` blocks whose condition is always false are removed, and `IF` blocks whose
+///   condition is always true are replaced by their body.
+/// - Expressions in a `WHILE` loop which are the same on every iteration are worked out once,
+///   before the loop starts. They are kept apart from the program's variables, so the program
+///   never sees them.
+///
+/// Expressions which would fail are left alone, so the error is still reported when the program
+/// runs. A hoisted expression which fails is reported before the loop starts, rather than partway
+/// through its first iteration.
+pub fn optimise(ast: &Ast) -> Ast {
+    optimise_with_tolerance(ast, 0.0)
+}
+
+/// Optimise a program which will be run with the given tolerance for `EQ` and `NE`, so numbers
+/// they compare are only worked out to be equal if they would be when the program runs
+pub fn optimise_with_tolerance(ast: &Ast, tolerance: f64) -> Ast {
+    let mut optimiser = Optimiser {
+        hoisted: 0,
+        tolerance,
+    };
+    Ast::from(optimiser.commands(ast.commands()))
+}
+
+struct Optimiser {
+    /// How many expressions have been hoisted out of loops so far, used to number them
+    hoisted: usize,
+
+    /// How far apart numbers may be for `EQ` to say they are equal
+    tolerance: f64,
+}
+
+impl Optimiser {
+    fn commands(&mut self, commands: &[Command]) -> Vec<Command> {
+        commands
+            .iter()
+            .flat_map(|x: &Command| self.command(x, None))
+            .collect()
+    }
+
+    /// Optimise a single command, which may become any number of commands
+    fn command(&mut self, command: &Command, location: Option<&Location>) -> Vec<Command> {
+        match command {
+            Command::Located(location, command) => self.command(command, Some(location)),
+            Command::If(condition, body) => match fold(condition, self.tolerance) {
+                Expression::Value(EvalResult::Bool(false)) => Vec::new(),
+                Expression::Value(EvalResult::Bool(true)) => self.commands(body),
+                condition => vec![locate(
+                    location,
+                    Command::If(condition, self.commands(body)),
+                )],
+            },
+            Command::While(condition, body) => match fold(condition, self.tolerance) {
+                Expression::Value(EvalResult::Bool(false)) => Vec::new(),
+                condition => {
+                    let body: Vec<Command> = self.commands(body);
+                    self.hoist(location, condition, body)
+                }
+            },
+            Command::ProcedureDefine(name, parameters, body) => vec![locate(
+                location,
+                Command::ProcedureDefine(name.clone(), parameters.clone(), self.commands(body)),
+            )],
+            command => vec![locate(
+                location,
+                map_expressions(command, &mut |x: &Expression| fold(x, self.tolerance)),
+            )],
+        }
+    }
+
+    /// Move expressions which are the same on every iteration of a loop into values worked out
+    /// before it starts. The loop is put inside an `IF` with the same condition, so the
+    /// expressions are only worked out if the loop runs at least once. The condition is then
+    /// worked out twice before the first iteration, so this is only done when that can't be told
+    /// apart from working it out once.
+    fn hoist(
+        &mut self,
+        location: Option<&Location>,
+        condition: Expression,
+        body: Vec<Command>,
+    ) -> Vec<Command> {
+        let mut assigned: HashSet<String> = HashSet::new();
+        if calls_primitive(&condition)
+            || reads_input(&condition)
+            || !assigned_variables(&body, &mut assigned)
+        {
+            return vec![locate(location, Command::While(condition, body))];
+        }
+
+        let mut hoisted: Vec<(usize, Expression)> = Vec::new();
+        let mut replace = |x: &Expression| self.replace_invariant(x, &assigned, &mut hoisted);
+        let new_condition: Expression = replace(&condition);
+        // Only commands which run on every iteration are looked at, not those inside blocks
+        let new_body: Vec<Command> = body
+            .iter()
+            .map(|x: &Command| map_expressions(x, &mut replace))
+            .collect();
+        if hoisted.is_empty() {
+            return vec![locate(location, Command::While(condition, body))];
+        }
+
+        let mut commands: Vec<Command> = hoisted
+            .into_iter()
+            .map(|(number, expression)| Command::Hoist(number, expression))
+            .collect();
+        commands.push(locate(location, Command::While(new_condition, new_body)));
+        vec![locate(location, Command::If(condition, commands))]
+    }
+
+    /// Replace the largest loop-invariant parts of an expression with hoisted values
+    fn replace_invariant(
+        &mut self,
+        expression: &Expression,
+        assigned: &HashSet<String>,
+        hoisted: &mut Vec<(usize, Expression)>,
+    ) -> Expression {
+        if !is_invariant(expression, assigned) {
+            if let Some((operator, lhs, rhs)) = operands(expression) {
+                return operator(
+                    Box::new(self.replace_invariant(lhs, assigned, hoisted)),
+                    Box::new(self.replace_invariant(rhs, assigned, hoisted)),
+                );
+            }
+            if let Some((operator, x)) = operand(expression) {
+                return operator(Box::new(self.replace_invariant(x, assigned, hoisted)));
+            }
+            return expression.clone();
+        }
+        if operands(expression).is_none() && operand(expression).is_none() {
+            // Values and single variables are already as cheap as they get
+            return expression.clone();
+        }
+
+        let number: usize = match hoisted.iter().find(|(_, x)| x == expression) {
+            Some((number, _)) => *number,
+            None => {
+                let number: usize = self.hoisted;
+                self.hoisted += 1;
+                hoisted.push((number, expression.clone()));
+                number
+            }
+        };
+        Expression::Hoisted(number)
+    }
+}
+
+/// Give a command back the location it was written at, if it had one
+fn locate(location: Option<&Location>, command: Command) -> Command {
+    match location {
+        Some(location) => Command::Located(location.clone(), Box::new(command)),
+        None => command,
+    }
+}
+
+/// The operator and operands of an expression with two operands
+fn operands(expression: &Expression) -> Option<(Operator, &Expression, &Expression)> {
+    let (operator, lhs, rhs) = match expression {
+        Expression::Add(lhs, rhs) => (Expression::Add as Operator, lhs, rhs),
+        Expression::Subtract(lhs, rhs) => (Expression::Subtract as Operator, lhs, rhs),
+        Expression::Multiply(lhs, rhs) => (Expression::Multiply as Operator, lhs, rhs),
+        Expression::Divide(lhs, rhs) => (Expression::Divide as Operator, lhs, rhs),
+        Expression::Quotient(lhs, rhs) => (Expression::Quotient as Operator, lhs, rhs),
+        Expression::Remainder(lhs, rhs) => (Expression::Remainder as Operator, lhs, rhs),
+        Expression::Equals(lhs, rhs) => (Expression::Equals as Operator, lhs, rhs),
+        Expression::NotEquals(lhs, rhs) => (Expression::NotEquals as Operator, lhs, rhs),
+        Expression::GreaterThan(lhs, rhs) => (Expression::GreaterThan as Operator, lhs, rhs),
+        Expression::LessThan(lhs, rhs) => (Expression::LessThan as Operator, lhs, rhs),
+        Expression::And(lhs, rhs) => (Expression::And as Operator, lhs, rhs),
+        Expression::Or(lhs, rhs) => (Expression::Or as Operator, lhs, rhs),
+        Expression::Word(lhs, rhs) => (Expression::Word as Operator, lhs, rhs),
+        _ => return None,
+    };
+    Some((operator, lhs, rhs))
+}
+
+/// The operator and operand of an expression with one operand
+fn operand(expression: &Expression) -> Option<(UnaryOperator, &Expression)> {
+    let (operator, x) = match expression {
+        Expression::WordP(x) => (Expression::WordP as UnaryOperator, x),
+        Expression::NumberP(x) => (Expression::NumberP as UnaryOperator, x),
+        Expression::IntegerP(x) => (Expression::IntegerP as UnaryOperator, x),
+        Expression::Count(x) => (Expression::Count as UnaryOperator, x),
+        _ => return None,
+    };
+    Some((operator, x))
+}
+
+/// Work out every part of an expression which only depends on constants
+fn fold(expression: &Expression, tolerance: f64) -> Expression {
+    let fold_part = |x: &Expression| fold(x, tolerance);
+    match expression {
+        Expression::GetVariable(name) => Expression::GetVariable(Box::new(fold_part(name))),
+        // Property lists are only known when the program runs, so only their names are folded
+        Expression::GetProperty(list, property) => {
+            Expression::GetProperty(Box::new(fold_part(list)), Box::new(fold_part(property)))
+        }
+        Expression::PropertyList(list) => Expression::PropertyList(Box::new(fold_part(list))),
+        Expression::Primitive(name, arguments) => {
+            Expression::Primitive(name.clone(), arguments.iter().map(fold_part).collect())
+        }
+        expression => {
+            let (folded, constant): (Expression, bool) =
+                match (operands(expression), operand(expression)) {
+                    (Some((operator, lhs, rhs)), _) => {
+                        let (lhs, rhs) = (fold_part(lhs), fold_part(rhs));
+                        let constant: bool = is_constant(&lhs) && is_constant(&rhs);
+                        (operator(Box::new(lhs), Box::new(rhs)), constant)
+                    }
+                    (None, Some((operator, x))) => {
+                        let x: Expression = fold_part(x);
+                        let constant: bool = is_constant(&x);
+                        (operator(Box::new(x)), constant)
+                    }
+                    (None, None) => return expression.clone(),
+                };
+            if !constant {
+                return folded;
+            }
+            // Every operand is a constant, so evaluating it never looks at the program, apart
+            // from the tolerance for comparing numbers
+            let mut program: Program = Program::new(Vec::new());
+            program.set_tolerance(tolerance);
+            match folded.eval(&mut program) {
+                Ok(value) => Expression::Value(value),
+                Err(_) => folded,
+            }
+        }
+    }
+}
+
+fn is_constant(expression: &Expression) -> bool {
+    matches!(expression, Expression::Value(_) | Expression::Variable(_))
+}
+
+/// Whether an expression has the same value on every iteration of a loop which only assigns the
+/// given variables
+fn is_invariant(expression: &Expression, assigned: &HashSet<String>) -> bool {
+    match expression {
+        Expression::Value(_) | Expression::Variable(_) => true,
+        Expression::GetVariable(name) => match name.as_ref() {
+            Expression::Variable(EvalResult::String(name))
+            | Expression::Value(EvalResult::String(name)) => !assigned.contains(name),
+            _ => false,
+        },
+        Expression::XCor
+        | Expression::YCor
+        | Expression::ZCor
+        | Expression::Heading
+        | Expression::Colour
+        | Expression::Primitive(_, _)
+        | Expression::ReadWord
+        | Expression::ReadList
+        | Expression::Map(_, _)
+        | Expression::Filter(_, _)
+        | Expression::Reduce(_, _)
+        | Expression::GetProperty(_, _)
+        | Expression::PropertyList(_) => false,
+        expression => match (operands(expression), operand(expression)) {
+            (Some((_, lhs, rhs)), _) => is_invariant(lhs, assigned) && is_invariant(rhs, assigned),
+            (None, Some((_, x))) => is_invariant(x, assigned),
+            (None, None) => false,
+        },
+    }
+}
+
+/// Collect every variable the commands may assign. Returns false if that can't be known, such as
+/// when a procedure or primitive is called, since they can assign any variable.
+fn assigned_variables(commands: &[Command], assigned: &mut HashSet<String>) -> bool {
+    commands
+        .iter()
+        .all(|command: &Command| match command.without_location() {
+            Command::ProcedureExec(_, _)
+            | Command::Primitive(_, _)
+            | Command::Run(_)
+            | Command::Apply(_, _)
+            | Command::LoadState(_) => false,
+            Command::MakeVariable(name, value) | Command::Increment(name, value) => match name {
+                Expression::Value(EvalResult::String(name)) if !calls_primitive(value) => {
+                    assigned.insert(name.clone());
+                    true
+                }
+                _ => false,
+            },
+            Command::If(condition, body) | Command::While(condition, body) => {
+                !calls_primitive(condition) && assigned_variables(body, assigned)
+            }
+            command => {
+                let mut calls: bool = false;
+                map_expressions(command, &mut |x: &Expression| {
+                    calls |= calls_primitive(x);
+                    x.clone()
+                });
+                !calls
+            }
+        })
+}
+
+/// Whether an expression runs a primitive, which may do anything to the program. Templates may
+/// run any primitive when they are filled in.
+fn calls_primitive(expression: &Expression) -> bool {
+    match expression {
+        Expression::Primitive(_, _)
+        | Expression::Map(_, _)
+        | Expression::Filter(_, _)
+        | Expression::Reduce(_, _) => true,
+        Expression::GetVariable(name) | Expression::PropertyList(name) => calls_primitive(name),
+        Expression::GetProperty(list, property) => {
+            calls_primitive(list) || calls_primitive(property)
+        }
+        expression => {
+            operands(expression)
+                .is_some_and(|(_, lhs, rhs)| calls_primitive(lhs) || calls_primitive(rhs))
+                || operand(expression).is_some_and(|(_, x)| calls_primitive(x))
+        }
+    }
+}
+
+/// Whether an expression reads a line of input, so working it out twice reads two lines
+fn reads_input(expression: &Expression) -> bool {
+    match expression {
+        Expression::ReadWord | Expression::ReadList => true,
+        Expression::GetVariable(name) | Expression::PropertyList(name) => reads_input(name),
+        Expression::GetProperty(list, property) => reads_input(list) || reads_input(property),
+        expression => {
+            operands(expression).is_some_and(|(_, lhs, rhs)| reads_input(lhs) || reads_input(rhs))
+                || operand(expression).is_some_and(|(_, x)| reads_input(x))
+        }
+    }
+}
+
+/// Rebuild a command with each of its expressions replaced. The commands inside blocks are left
+/// as they are, but the conditions of `IF` and `WHILE` are replaced.
+fn map_expressions(command: &Command, f: &mut impl FnMut(&Expression) -> Expression) -> Command {
+    match command {
+        Command::Forward(x) => Command::Forward(f(x)),
+        Command::Back(x) => Command::Back(f(x)),
+        Command::Left(x) => Command::Left(f(x)),
+        Command::Right(x) => Command::Right(f(x)),
+        Command::SetPenColor(x) => Command::SetPenColor(f(x)),
+        Command::Turn(x) => Command::Turn(f(x)),
+        Command::SetHeading(x) => Command::SetHeading(f(x)),
+        Command::SetX(x) => Command::SetX(f(x)),
+        Command::SetY(x) => Command::SetY(f(x)),
+        Command::SetZ(x) => Command::SetZ(f(x)),
+        Command::Up(x) => Command::Up(f(x)),
+        Command::Down(x) => Command::Down(f(x)),
+        Command::RollLeft(x) => Command::RollLeft(f(x)),
+        Command::RollRight(x) => Command::RollRight(f(x)),
+        Command::Label(x) => Command::Label(f(x)),
+        Command::SetLabelHeight(x) => Command::SetLabelHeight(f(x)),
+        Command::SetFont(x) => Command::SetFont(f(x)),
+        Command::Run(x) => Command::Run(f(x)),
+        Command::Apply(name, arguments) => Command::Apply(f(name), f(arguments)),
+        Command::Print(x) => Command::Print(f(x)),
+        Command::Show(x) => Command::Show(f(x)),
+        Command::Type(x) => Command::Type(f(x)),
+        Command::SaveState(x) => Command::SaveState(f(x)),
+        Command::LoadState(x) => Command::LoadState(f(x)),
+        Command::MakeVariable(name, value) => Command::MakeVariable(f(name), f(value)),
+        Command::Increment(name, value) => Command::Increment(f(name), f(value)),
+        Command::PutProperty(list, property, value) => {
+            Command::PutProperty(f(list), f(property), f(value))
+        }
+        Command::RemoveProperty(list, property) => Command::RemoveProperty(f(list), f(property)),
+        Command::Hoist(number, value) => Command::Hoist(*number, f(value)),
+        Command::If(condition, body) => Command::If(f(condition), body.clone()),
+        Command::While(condition, body) => Command::While(f(condition), body.clone()),
+        Command::ProcedureExec(name, arguments) => {
+            Command::ProcedureExec(name.clone(), arguments.iter().map(f).collect())
+        }
+        Command::Primitive(name, arguments) => {
+            Command::Primitive(name.clone(), arguments.iter().map(f).collect())
+        }
+        Command::Located(location, command) => {
+            Command::Located(location.clone(), Box::new(map_expressions(command, f)))
+        }
+        Command::PenUp
+        | Command::PenDown
+        | Command::SetBoundary(_)
+        | Command::ProcedureDefine(_, _, _)
+        | Command::ProcedureBase(_, _)
+        | Command::Load(_, _) => command.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{loader::Loader, parse, parsers::without_locations, Canvas, Interpreter};
+
+    fn optimise_str(source: &str) -> Vec<Command> {
+        optimise(&parse(source).unwrap())
+            .commands()
+            .iter()
+            .map(|x: &Command| x.without_location().clone())
+            .collect()
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        assert_eq!(
+            optimise_str("FORWARD + \"1 * \"2 \"3"),
+            vec![Command::Forward(Expression::Value(EvalResult::Float(7.0)))]
+        );
+        assert_eq!(
+            optimise_str("MAKE \"x AND EQ \"1 \"1 \"TRUE"),
+            vec![Command::MakeVariable(
+                Expression::Value(EvalResult::String("x".to_string())),
+                Expression::Value(EvalResult::Bool(true))
+            )]
+        );
+        assert_eq!(
+            optimise_str("PRINT COUNT WORD \"ab \"cd"),
+            vec![Command::Print(Expression::Value(EvalResult::Float(4.0)))]
+        );
+
+        // Property lists are only known when the program runs
+        assert_eq!(
+            optimise_str("SHOW PLIST WORD \"sq \"uare"),
+            vec![Command::Show(Expression::PropertyList(Box::new(
+                Expression::Value(EvalResult::String("square".to_string()))
+            )))]
+        );
+
+        // Reading input is left for when the program runs
+        assert_eq!(
+            optimise_str("PRINT COUNT READWORD"),
+            vec![Command::Print(Expression::Count(Box::new(
+                Expression::ReadWord
+            )))]
+        );
+
+        // Errors are left for the interpreter to report
+        assert_eq!(
+            optimise_str("FORWARD / \"1 \"0"),
+            vec![Command::Forward(Expression::Divide(
+                Box::new(Expression::Value(EvalResult::Float(1.0))),
+                Box::new(Expression::Value(EvalResult::Float(0.0)))
+            ))]
+        );
+    }
+
+    #[test]
+    fn dead_blocks_are_removed() {
+        assert_eq!(optimise_str("IF EQ \"1 \"2 [ FORWARD \"1 ]"), vec![]);
+        assert_eq!(optimise_str("WHILE \"FALSE [ FORWARD \"1 ]"), vec![]);
+        assert_eq!(
+            optimise_str("IF \"TRUE [ FORWARD \"1 ]"),
+            vec![Command::Forward(Expression::Value(EvalResult::Float(1.0)))]
+        );
+
+        // Numbers close enough together are equal when the program runs with a tolerance
+        let ast = parse("IF EQ + \"0.1 \"0.2 \"0.3 [ FORWARD \"1 ]").unwrap();
+        assert_eq!(optimise(&ast).commands(), &[]);
+        assert_eq!(
+            optimise_with_tolerance(&ast, 1e-9)
+                .commands()
+                .iter()
+                .map(|x: &Command| x.without_location().clone())
+                .collect::<Vec<_>>(),
+            vec![Command::Forward(Expression::Value(EvalResult::Float(1.0)))]
+        );
+    }
+
+    #[test]
+    fn invariants_are_hoisted() {
+        let commands = optimise_str("WHILE LT :i * :n \"2 [ ADDASSIGN \"i + :n \"1 ]");
+        let variable = |name: &str| {
+            Box::new(Expression::GetVariable(Box::new(Expression::Variable(
+                EvalResult::String(name.to_string()),
+            ))))
+        };
+        let Command::If(_, block) = &commands[0] else {
+            panic!("expected the loop to be rotated, got {commands:?}");
+        };
+        assert_eq!(
+            without_locations(block.clone())[2],
+            Command::While(
+                Expression::LessThan(variable("i"), Box::new(Expression::Hoisted(0))),
+                vec![Command::Increment(
+                    Expression::Value(EvalResult::String("i".to_string())),
+                    Expression::Hoisted(1)
+                )]
+            )
+        );
+
+        // The condition is worked out one extra time, so it mustn't read input
+        let commands = optimise_str("WHILE AND EQ READWORD \"1 GT * :n \"2 \"3 [ PRINT \"x ]");
+        assert!(matches!(commands.as_slice(), [Command::While(_, _)]));
+
+        // Procedures may assign any variable, so nothing is hoisted out of loops which call them
+        let commands = optimise_str("WHILE LT :i * :n \"2 [ Step ]");
+        assert!(matches!(commands.as_slice(), [Command::While(_, _)]));
+    }
+
+    #[test]
+    fn hoisted_values_are_not_variables() {
+        let ast = parse("MAKE \"i \"0\nMAKE \"n \"2\nWHILE LT :i * :n \"2 [ ADDASSIGN \"i \"1 ]")
+            .unwrap();
+        let mut plain = Interpreter::new(Canvas::new(100, 100));
+        let mut optimised = Interpreter::new(Canvas::new(100, 100));
+        plain.run(&ast).unwrap();
+        optimised.run(&optimise(&ast)).unwrap();
+        assert_eq!(plain.state().variables, optimised.state().variables);
+    }
+
+    #[test]
+    fn examples_draw_the_same() {
+        let examples = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("logo_examples");
+        for entry in std::fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            let Ok(ast) = Loader::new(Vec::new()).load(&path) else {
+                continue;
+            };
+
+            let mut plain = Interpreter::new(Canvas::new(500, 500));
+            let mut optimised = Interpreter::new(Canvas::new(500, 500));
+            assert_eq!(
+                plain.run(&ast),
+                optimised.run(&optimise(&ast)),
+                "{}",
+                path.display()
+            );
+            assert_eq!(plain.canvas(), optimised.canvas(), "{}", path.display());
+        }
+    }
+}
@@ -1,35 +1,93 @@
+use std::collections::HashSet;
+
 use nom::{
     branch::alt,
-    bytes::complete::{take_till, take_until, take_while},
-    character::{
-        complete::{alphanumeric0, alphanumeric1, char, multispace0},
-        is_alphanumeric,
-    },
-    combinator::opt,
-    multi::many0,
-    number::complete::float,
-    sequence::{delimited, preceded, separated_pair, tuple},
+    combinator::{all_consuming, peek},
+    multi::{count, many0},
+    sequence::{delimited, pair, preceded, tuple},
     IResult, Parser,
 };
-use nom_supreme::{error::ErrorTree, tag::complete::tag, ParserExt};
+use nom_supreme::{
+    error::{BaseErrorKind, ErrorTree, Expectation},
+    ParserExt,
+};
 
 use crate::{
-    errors::{format_parse_error, ParseError, Span},
-    tokens::{Command, EvalResult, Expression},
+    canvas::Boundary,
+    errors::{format_parse_error, Diagnostics, ParseError, ParseErrorKind, SourceFile},
+    lexer::{lex, Keyword, Token, TokenKind},
+    primitives::Primitives,
+    tokens::{Command, EvalResult, Expression, Location},
 };
 
+/// The parsers consume the tokens produced by the lexer, rather than raw text
+pub(crate) type Tokens<'a> = &'a [Token];
+
+/// Convenient type alias for the result of a parser
+type TokenResult<'a, O> = IResult<Tokens<'a>, O, ErrorTree<Tokens<'a>>>;
+
+/// Create a parser which consumes a single token, if `f` accepts it. `expected` describes the
+/// tokens that are accepted, for use in error messages.
+fn token<'a, O>(
+    expected: &'static str,
+    f: impl Fn(&'a TokenKind) -> Option<O>,
+) -> impl FnMut(Tokens<'a>) -> TokenResult<'a, O> {
+    written_token(expected, move |token: &'a Token| f(&token.kind))
+}
+
+/// Like `token`, but `f` is given the whole token, including how it was written
+fn written_token<'a, O>(
+    expected: &'static str,
+    f: impl Fn(&'a Token) -> Option<O>,
+) -> impl FnMut(Tokens<'a>) -> TokenResult<'a, O> {
+    move |input: Tokens<'a>| match input.split_first() {
+        Some((first, rest)) => match f(first) {
+            Some(res) => Ok((rest, res)),
+            None => Err(nom::Err::Error(ErrorTree::Base {
+                location: input,
+                kind: BaseErrorKind::Expected(Expectation::Tag(expected)),
+            })),
+        },
+        None => Err(nom::Err::Error(ErrorTree::Base {
+            location: input,
+            kind: BaseErrorKind::Expected(Expectation::Tag(expected)),
+        })),
+    }
+}
+
+/// Create a parser which consumes the keyword with the given spelling. A keyword shadowed by a
+/// procedure is still accepted, as in an expression it can only mean the keyword.
+fn keyword<'a>(expected: &'static str) -> impl FnMut(Tokens<'a>) -> TokenResult<'a, Keyword> {
+    token(expected, move |kind: &TokenKind| match kind {
+        TokenKind::Keyword(keyword) | TokenKind::Shadowed { keyword, .. }
+            if keyword.as_str() == expected =>
+        {
+            Some(*keyword)
+        }
+        _ => None,
+    })
+}
+
 /// Macro to reduce boilerplate for arithmetic parsing
 macro_rules! parse_operation_expression {
     ($fn_name:ident, $op:expr, $constructor:path) => {
-        fn $fn_name(input: Span) -> IResult<Span, Expression, ErrorTree<Span>> {
-            preceded(
-                tag($op),
-                separated_pair(parse_expression, multispace0, parse_expression)
-                    .preceded_by(multispace0),
-            )
-            .map(|(lhs, rhs)| $constructor(Box::new(lhs), Box::new(rhs)))
-            .context(concat!("when parsing ", stringify!($op), " expression"))
-            .parse(input)
+        fn $fn_name(input: Tokens) -> TokenResult<Expression> {
+            preceded(keyword($op), pair(parse_expression, parse_expression))
+                .map(|(lhs, rhs)| $constructor(Box::new(lhs), Box::new(rhs)))
+                .context(concat!("when parsing ", stringify!($op), " expression"))
+                .parse(input)
+        }
+    };
+}
+
+/// Macro to reduce boilerplate for expressions with a single operand
+macro_rules! parse_unary_expression {
+    ($fn_name:ident, $op:expr, $constructor:path) => {
+        fn $fn_name(input: Tokens) -> TokenResult<Expression> {
+            preceded(keyword($op), parse_expression)
+                .map(|x| $constructor(Box::new(x)))
+                .context(concat!("when parsing ", stringify!($op), " expression"))
+                .parse(input)
         }
     };
 }
@@ -37,8 +95,8 @@ macro_rules! parse_operation_expression {
 /// Macro to reduce boilerplate for argument-less queries
 macro_rules! parse_query_expression {
     ($fn_name:ident, $op:expr, $context:expr, $constructor:path) => {
-        fn $fn_name(input: Span) -> IResult<Span, Expression, ErrorTree<Span>> {
-            tag($op)
+        fn $fn_name(input: Tokens) -> TokenResult<Expression> {
+            keyword($op)
                 .map(|_| $constructor)
                 .context($context)
                 .parse(input)
@@ -49,7 +107,7 @@ macro_rules! parse_query_expression {
 /// Macro to reduce boilerplate for parsing a verb
 macro_rules! command_parser {
     ($tag:expr, $constructor:path) => {
-        tag($tag)
+        keyword($tag)
             .context(concat!("parsing as ", stringify!($tag)))
             .map(|_| $constructor as fn(Expression) -> Command)
     };
@@ -57,7 +115,7 @@ macro_rules! command_parser {
 
 macro_rules! variable_command_parser {
     ($tag:expr, $constructor:path) => {
-        tag($tag)
+        keyword($tag)
             .context(concat!("parsing as ", stringify!($tag)))
             .map(|_| $constructor as fn(Expression, Expression) -> Command)
     };
@@ -65,7 +123,7 @@ macro_rules! variable_command_parser {
 /// Macro to reduce boilerplate for parsing a verb
 macro_rules! control_flow_parser {
     ($tag:expr, $constructor:expr) => {
-        tag($tag)
+        keyword($tag)
             .context(concat!("parsing as ", stringify!($tag)))
             .map(|_| $constructor as fn(Expression, Vec<Command>) -> Command)
     };
@@ -75,65 +133,44 @@ macro_rules! control_flow_parser {
 /// A literal value must be preceeded by a double quote (`"`).
 ///
 /// # Example
+/// ```ignore
+/// assert_eq!(parse_value_expression(&lex("\"TRUE")), Expression::Value(EvalResult::Bool(true)));
+/// assert_eq!(parse_value_expression(&lex("\"FALSE")), Expression::Value(EvalResult::Bool(false)));
+/// assert_eq!(parse_value_expression(&lex("\"2.54")), Expression::Value(EvalResult::Float(2.54)))
 /// ```
-/// assert_eq!(parse_value_expression(Span::new("\"TRUE")), Expression::Value(EvalResult::Bool(true)));
-/// assert_eq!(parse_value_expression(Span::new("\"FALSE")), Expression::Value(EvalResult::Bool(false)));
-/// assert_eq!(parse_value_expression(Span::new("\"2.54")), Expression::Value(EvalResult::Float(2.54)))
-/// ```
-fn parse_value_expression(input: Span) -> IResult<Span, Expression, ErrorTree<Span>> {
+fn parse_value_expression(input: Tokens) -> TokenResult<Expression> {
     /*
-     * A value literal can be one of the following: a float (f32), or a boolean. However, since Logo represents
-     * booleans as the strings "TRUE" and "FALSE", we cannot use nom's built-in bool parser. Instead, we will parse
-     * them as strings, using tag.
+     * A value literal can be one of the following: a float (f64), or a boolean. However, since Logo represents
+     * booleans as the words "TRUE" and "FALSE", we check for those before falling back to a plain word.
      */
-    alt((
-        // parse number
-        float
-            // Instead of a string, we want to return the corresponding enum instance
-            .map(|res: f32| Expression::Value(EvalResult::Float(res)))
-            .context("parsing literal value as float"),
-        // Parse 'true' boolean
-        tag("TRUE")
-            // The parsed value does not matter here. Rather, if the parser succeeds at all, we return an instance of the enum, disregarding the parsed string.
-            .map(|_| Expression::Value(EvalResult::Bool(true)))
-            .context("parsing literal value as boolean 'true'"),
-        // Parse 'false' bolean
-        tag("FALSE")
-            // The parsed value does not matter here. Rather, if the parser succeeds at all, we return an instance of the enum, disregarding the parsed string.
-            .map(|_| Expression::Value(EvalResult::Bool(false)))
-            .context("parsing literal value as boolean 'true'"),
-        // Parse variable name
-        take_till(|c: char| -> bool { c == ' ' || c == '"' || c == '\n' })
-            .map(|name: Span| {
-                Expression::Value(EvalResult::String(name.into_fragment().to_owned()))
-            })
-            .context("parsing variable name"),
-    ))
-    // The specifications for the Logo language requires that a value be preceeded by a singular double-quote
-    .preceded_by(tag("\""))
+    token("a literal value", |kind: &TokenKind| match kind {
+        TokenKind::Quoted(word) => Some(word),
+        _ => None,
+    })
+    .map(|word: &String| Expression::Value(EvalResult::from_word(word)))
     .context("parsing literal value")
     .parse(input)
 }
 
 /// Parse the given input as a variable. This will return an instance of `Expression::GetVariable`
-/// A variable must be preceeded by a double quote (`:`).
+/// A variable must be preceeded by a colon (`:`).
 ///
 /// # Example
+/// ```ignore
+/// assert_eq!(
+///     parse_getvariable_expression(&lex(":SIZE")),
+///     Expression::GetVariable(Box::new(Expression::Variable(EvalResult::String("SIZE".into()))))
+/// );
 /// ```
-/// assert_eq!(parse_value_expression(Span::new("\"TRUE")), Expression::Value(EvalResult::Bool(true)));
-/// assert_eq!(parse_value_expression(Span::new("\"FALSE")), Expression::Value(EvalResult::Bool(false)));
-/// assert_eq!(parse_value_expression(Span::new("\"2.54")), Expression::Value(EvalResult::Float(2.54)))
-/// ```
-fn parse_getvariable_expression(input: Span) -> IResult<Span, Expression, ErrorTree<Span>> {
-    delimited(
-        tag(":"),
-        take_till(|c: char| -> bool { c == ' ' || c == '"' || c == '\n' }),
-        multispace0,
-    )
-    // We want to return a token instead of the actual float
-    .map(|res: Span| -> Expression {
+fn parse_getvariable_expression(input: Tokens) -> TokenResult<Expression> {
+    token("a variable", |kind: &TokenKind| match kind {
+        TokenKind::Variable(name) if !name.is_empty() => Some(name),
+        _ => None,
+    })
+    // We want to return a token instead of the actual name
+    .map(|name: &String| -> Expression {
         Expression::GetVariable(Box::new(Expression::Variable(EvalResult::String(
-            res.into_fragment().into(),
+            name.to_owned(),
         ))))
     })
     // Additional context for error messages
@@ -153,6 +190,12 @@ parse_query_expression!(
     "parsing y-coordinate query",
     Expression::YCor
 );
+parse_query_expression!(
+    parse_zcor_expression,
+    "ZCOR",
+    "parsing z-coordinate query",
+    Expression::ZCor
+);
 parse_query_expression!(
     parse_heading_expression,
     "HEADING",
@@ -166,32 +209,146 @@ parse_query_expression!(
     Expression::Colour
 );
 
-fn parse_comment(input: Span) -> IResult<Span, Command, ErrorTree<Span>> {
-    preceded(tag("//"), take_until("\n"))
-        .map(|_| Command::Comment)
-        .context("parsing comment")
-        .parse(input)
-}
+parse_query_expression!(
+    parse_readword_expression,
+    "READWORD",
+    "parsing word input",
+    Expression::ReadWord
+);
+parse_query_expression!(
+    parse_readlist_expression,
+    "READLIST",
+    "parsing list input",
+    Expression::ReadList
+);
 
 parse_operation_expression!(parse_addition_expression, "+", Expression::Add);
 parse_operation_expression!(parse_subtraction_expression, "-", Expression::Subtract);
 parse_operation_expression!(parse_multiplication_expression, "*", Expression::Multiply);
 parse_operation_expression!(parse_division_expression, "/", Expression::Divide);
+parse_operation_expression!(parse_quotient_expression, "QUOTIENT", Expression::Quotient);
+parse_operation_expression!(
+    parse_remainder_expression,
+    "REMAINDER",
+    Expression::Remainder
+);
 parse_operation_expression!(parse_equality_expression, "EQ", Expression::Equals);
 parse_operation_expression!(parse_inequality_expression, "NE", Expression::NotEquals);
 parse_operation_expression!(parse_greater_than_expression, "GT", Expression::GreaterThan);
 parse_operation_expression!(parse_less_than_expression, "LT", Expression::LessThan);
 parse_operation_expression!(parse_and_expression, "AND", Expression::And);
 parse_operation_expression!(parse_or_expression, "OR", Expression::Or);
+parse_operation_expression!(parse_word_expression, "WORD", Expression::Word);
+
+parse_unary_expression!(parse_wordp_expression, "WORDP", Expression::WordP);
+parse_unary_expression!(parse_numberp_expression, "NUMBERP", Expression::NumberP);
+parse_unary_expression!(parse_integerp_expression, "INTEGERP", Expression::IntegerP);
+parse_unary_expression!(parse_count_expression, "COUNT", Expression::Count);
+
+parse_operation_expression!(parse_map_expression, "MAP", Expression::Map);
+parse_operation_expression!(parse_filter_expression, "FILTER", Expression::Filter);
+parse_operation_expression!(parse_reduce_expression, "REDUCE", Expression::Reduce);
+
+parse_operation_expression!(parse_gprop_expression, "GPROP", Expression::GetProperty);
+parse_unary_expression!(parse_plist_expression, "PLIST", Expression::PropertyList);
+
+/// Parse a single word inside a list. Words in a list aren't run, so they are kept as they were
+/// written, apart from numbers and booleans.
+fn parse_list_word(input: Tokens) -> TokenResult<EvalResult> {
+    written_token("a word", |token: &Token| match token.kind {
+        TokenKind::OpenBracket | TokenKind::CloseBracket => None,
+        _ => Some(EvalResult::from_word(&token.text)),
+    })
+    .parse(input)
+}
+
+/// Parse a list of words, such as `[1 2 3]` or `[FORWARD "10]`, which may contain other lists.
+/// This will return an instance of `Expression::Value`.
+fn parse_list(input: Tokens) -> TokenResult<EvalResult> {
+    delimited(
+        token("[", |kind: &TokenKind| match kind {
+            TokenKind::OpenBracket => Some(()),
+            _ => None,
+        }),
+        many0(alt((parse_list_word, parse_list))),
+        token("]", |kind: &TokenKind| match kind {
+            TokenKind::CloseBracket => Some(()),
+            _ => None,
+        }),
+    )
+    .map(EvalResult::List)
+    .context("parsing list")
+    .parse(input)
+}
 
-fn parse_expression(input: Span) -> IResult<Span, Expression, ErrorTree<Span>> {
+/// Parse a list, or one of the expressions which run a template over a list
+fn parse_list_expression(input: Tokens) -> TokenResult<Expression> {
+    alt((
+        parse_list.map(Expression::Value),
+        parse_map_expression,
+        parse_filter_expression,
+        parse_reduce_expression,
+    ))
+    .context("parsing list expression")
+    .parse(input)
+}
+
+/// Parse one of the expressions which work out a number from two others
+fn parse_arithmetic_expression(input: Tokens) -> TokenResult<Expression> {
     alt((
-        parse_value_expression,
-        parse_getvariable_expression,
         parse_addition_expression,
         parse_subtraction_expression,
         parse_multiplication_expression,
         parse_division_expression,
+        parse_quotient_expression,
+        parse_remainder_expression,
+    ))
+    .context("parsing arithmetic expression")
+    .parse(input)
+}
+
+/// Parse one of the expressions which read input or work with words
+fn parse_words_expression(input: Tokens) -> TokenResult<Expression> {
+    alt((
+        parse_readword_expression,
+        parse_readlist_expression,
+        parse_word_expression,
+        parse_wordp_expression,
+        parse_numberp_expression,
+        parse_integerp_expression,
+        parse_count_expression,
+    ))
+    .context("parsing word expression")
+    .parse(input)
+}
+
+/// Parse one of the expressions which read property lists
+fn parse_property_expression(input: Tokens) -> TokenResult<Expression> {
+    alt((parse_gprop_expression, parse_plist_expression))
+        .context("parsing property list expression")
+        .parse(input)
+}
+
+/// Parse a primitive along with its arguments. Primitives have a fixed number of arguments, so
+/// they can be used anywhere, including as an argument to another primitive.
+fn parse_primitive(input: Tokens) -> TokenResult<(String, Vec<Expression>)> {
+    let (rest, (name, arity)) = token("a primitive", |kind: &TokenKind| match kind {
+        TokenKind::Primitive { name, arity } => Some((name.to_owned(), *arity)),
+        _ => None,
+    })
+    .parse(input)?;
+
+    count(parse_expression, arity)
+        .context("parsing arguments for a primitive")
+        .map(|arguments| (name.clone(), arguments))
+        .parse(rest)
+}
+
+fn parse_expression(input: Tokens) -> TokenResult<Expression> {
+    alt((
+        parse_value_expression,
+        parse_getvariable_expression,
+        parse_arithmetic_expression,
         parse_equality_expression,
         parse_inequality_expression,
         parse_greater_than_expression,
@@ -200,21 +357,24 @@ fn parse_expression(input: Span) -> IResult<Span, Expression, ErrorTree<Span>> {
         parse_or_expression,
         parse_xcor_expression,
         parse_ycor_expression,
+        parse_zcor_expression,
         parse_colour_expression,
         parse_heading_expression,
+        parse_words_expression,
+        parse_list_expression,
+        parse_property_expression,
+        parse_primitive.map(|(name, arguments)| Expression::Primitive(name, arguments)),
     ))
-    // .cut()
-    .delimited_by(multispace0)
     .context("parsing expression")
     .parse(input)
 }
 
-fn parse_pen_state_commands(input: Span) -> IResult<Span, Command, ErrorTree<Span>> {
+fn parse_pen_state_commands(input: Tokens) -> TokenResult<Command> {
     alt((
-        tag("PENUP")
+        keyword("PENUP")
             .context("parsing as PENUP")
             .map(|_| Command::PenUp),
-        tag("PENDOWN")
+        keyword("PENDOWN")
             .context("parsing as PENDOWN")
             .map(|_| Command::PenDown),
     ))
@@ -222,157 +382,525 @@ fn parse_pen_state_commands(input: Span) -> IResult<Span, Command, ErrorTree<Spa
     .parse(input)
 }
 
-fn parse_single_expression_commands(input: Span) -> IResult<Span, Command, ErrorTree<Span>> {
-    let parse_verb = alt((
+fn parse_boundary_commands(input: Tokens) -> TokenResult<Command> {
+    alt((
+        keyword("WRAP")
+            .context("parsing as WRAP")
+            .map(|_| Command::SetBoundary(Boundary::Wrap)),
+        keyword("FENCE")
+            .context("parsing as FENCE")
+            .map(|_| Command::SetBoundary(Boundary::Fence)),
+        keyword("WINDOW")
+            .context("parsing as WINDOW")
+            .map(|_| Command::SetBoundary(Boundary::Window)),
+    ))
+    .context("parsing as boundary command")
+    .parse(input)
+}
+
+fn parse_single_expression_commands(input: Tokens) -> TokenResult<Command> {
+    // `alt` only takes so many parsers at once, so the turtle's movements are parsed on their own
+    let parse_movement = alt((
         command_parser!("FORWARD", Command::Forward),
         command_parser!("BACK", Command::Back),
         command_parser!("LEFT", Command::Left),
         command_parser!("RIGHT", Command::Right),
-        command_parser!("SETPENCOLOR", Command::SetPenColor),
         command_parser!("TURN", Command::Turn),
         command_parser!("SETHEADING", Command::SetHeading),
         command_parser!("SETX", Command::SetX),
         command_parser!("SETY", Command::SetY),
+        command_parser!("SETZ", Command::SetZ),
+        command_parser!("UP", Command::Up),
+        command_parser!("DOWN", Command::Down),
+        command_parser!("ROLLLEFT", Command::RollLeft),
+        command_parser!("ROLLRIGHT", Command::RollRight),
+    ));
+    let parse_verb = alt((
+        parse_movement,
+        command_parser!("SETPENCOLOR", Command::SetPenColor),
+        command_parser!("LABEL", Command::Label),
+        command_parser!("SETLABELHEIGHT", Command::SetLabelHeight),
+        command_parser!("SETFONT", Command::SetFont),
+        command_parser!("RUN", Command::Run),
+        command_parser!("PRINT", Command::Print),
+        command_parser!("SHOW", Command::Show),
+        command_parser!("TYPE", Command::Type),
+        command_parser!("SAVESTATE", Command::SaveState),
+        command_parser!("LOADSTATE", Command::LoadState),
     ))
     .context("parsing verb for a single expression command");
 
-    separated_pair(parse_verb, multispace0, parse_expression)
+    pair(parse_verb, parse_expression)
         .map(|(verb, expression)| verb(expression))
         .parse(input)
 }
 
-fn parse_variable_manipulation_commands(input: Span) -> IResult<Span, Command, ErrorTree<Span>> {
+fn parse_variable_manipulation_commands(input: Tokens) -> TokenResult<Command> {
     let parse_verb = alt((
         variable_command_parser!("MAKE", Command::MakeVariable),
         variable_command_parser!("ADDASSIGN", Command::Increment),
+        variable_command_parser!("REMPROP", Command::RemoveProperty),
     ))
     .context("parsing verb for a variable manipulation command");
 
-    separated_pair(
-        parse_verb,
-        multispace0,
-        separated_pair(parse_expression, multispace0, parse_expression),
+    tuple((parse_verb, parse_expression, parse_expression))
+        .map(|(verb, name, val)| verb(name, val))
+        .parse(input)
+}
+
+/// Parse a call to a procedure or primitive named at runtime, such as `APPLY "Box [10]`
+fn parse_apply_command(input: Tokens) -> TokenResult<Command> {
+    preceded(keyword("APPLY"), pair(parse_expression, parse_expression))
+        .map(|(name, arguments)| Command::Apply(name, arguments))
+        .context("parsing as APPLY")
+        .parse(input)
+}
+
+/// Parse a property being set in a property list, such as `PPROP "square "size "10`
+fn parse_pprop_command(input: Tokens) -> TokenResult<Command> {
+    preceded(
+        keyword("PPROP"),
+        tuple((parse_expression, parse_expression, parse_expression)),
     )
-    .map(|(verb, (name, val))| verb(name, val))
+    .map(|(list, property, value)| Command::PutProperty(list, property, value))
+    .context("parsing as PPROP")
     .parse(input)
 }
 
-fn parse_control_flow_commands(input: Span) -> IResult<Span, Command, ErrorTree<Span>> {
+/// Parse a block of commands, delimited by square brackets
+fn parse_block(input: Tokens) -> TokenResult<Vec<Command>> {
+    delimited(
+        token("[", |kind: &TokenKind| match kind {
+            TokenKind::OpenBracket => Some(()),
+            _ => None,
+        })
+        .context("parsing opening delimiter for a control flow expression"),
+        parse_commands_many.context("parsing commands inside control flow expression"),
+        token("]", |kind: &TokenKind| match kind {
+            TokenKind::CloseBracket => Some(()),
+            _ => None,
+        })
+        .context("parsing closing delimiters for a control flow expression"),
+    )
+    .parse(input)
+}
+
+fn parse_control_flow_commands(input: Tokens) -> TokenResult<Command> {
     let verb = alt((
         control_flow_parser!("IF", Command::If),
         control_flow_parser!("WHILE", Command::While),
     ))
     .context("parsing verb for a control flow command");
 
-    let commands = parse_commands_many;
-
-    separated_pair(
+    tuple((
         verb,
-        multispace0,
-        separated_pair(
-            parse_expression.context("parsing condition for a control flow expression"),
-            multispace0,
-            delimited(
-                tag("[").context("parsing opening delimiter for a control flow expression"),
-                commands.context("parsing commands inside control flow expression"),
-                tag("]").context("parsing closing delimiters for a control flow expression"),
-            )
-            .context("parsing body of a control flow expression"),
-        ),
-    )
+        parse_expression.context("parsing condition for a control flow expression"),
+        parse_block.context("parsing body of a control flow expression"),
+    ))
     .context("parsing a control flow expression")
-    .map(|(verb, (conditions, commands))| verb(conditions, commands))
+    .map(|(verb, conditions, commands)| verb(conditions, commands))
+    .parse(input)
+}
+
+/// Parse the name of a procedure. Keywords are separate tokens, so they can only be mistaken
+/// for the name of a procedure when the program defines one with that name.
+fn parse_procedure_name(input: Tokens) -> TokenResult<String> {
+    token("a procedure name", |kind: &TokenKind| match kind {
+        TokenKind::Word(name) | TokenKind::Shadowed { name, .. } => Some(name.to_owned()),
+        _ => None,
+    })
+    .parse(input)
+}
+
+/// Parse a call to a procedure named after a keyword. This has to be tried before any command
+/// starting with that keyword.
+fn parse_shadowing_invocation(input: Tokens) -> TokenResult<Command> {
+    preceded(
+        peek(token("a procedure name", |kind: &TokenKind| match kind {
+            TokenKind::Shadowed { .. } => Some(()),
+            _ => None,
+        })),
+        parse_procedure_invocation,
+    )
     .parse(input)
 }
 
-fn parse_procedure_definition(input: Span) -> IResult<Span, Command, ErrorTree<Span>> {
+fn parse_procedure_definition(input: Tokens) -> TokenResult<Command> {
     delimited(
-        tag("TO"),
-        tuple((alphanumeric0, many0(parse_expression), parse_commands_many)),
-        tag("END"),
+        keyword("TO"),
+        tuple((
+            parse_procedure_name.context("parsing procedure name for definition"),
+            many0(parse_expression),
+            parse_commands_many,
+        )),
+        keyword("END").context("parsing END of a procedure definition"),
     )
     .map(|(name, args, commands)| {
-        Command::ProcedureDefine(
-            Expression::Value(EvalResult::String(name.to_string())),
-            args,
-            commands,
-        )
+        Command::ProcedureDefine(Expression::Value(EvalResult::String(name)), args, commands)
     })
     .parse(input)
 }
 
-fn parse_procedure_invocation(input: Span) -> IResult<Span, Command, ErrorTree<Span>> {
-    let name = alphanumeric1.context("parsing procedure name for invocation");
+fn parse_procedure_invocation(input: Tokens) -> TokenResult<Command> {
+    let name = parse_procedure_name.context("parsing procedure name for invocation");
     let arguments = many0(parse_expression).context("parsing arguments for a procedure invocation");
-    separated_pair(name, multispace0, opt(arguments))
-        .map(|(name, args): (Span, Option<Vec<Expression>>)| -> Command {
-            let args: Vec<Expression> = match args {
-                Some(val) => val,
-                None => Vec::new(),
-            };
-
-            Command::ProcedureExec(name.into_fragment().to_string(), args)
+    pair(name, arguments)
+        .map(|(name, args): (String, Vec<Expression>)| -> Command {
+            Command::ProcedureExec(name, args)
         })
-        .parse(dbg!(input))
+        .parse(input)
+}
+
+/// Find the span of source covered by the tokens a parser consumed, given its input and the
+/// tokens it left over
+fn consumed_span(input: Tokens, rest: Tokens) -> miette::SourceSpan {
+    let consumed: Tokens = &input[..input.len() - rest.len()];
+    match (consumed.first(), consumed.last()) {
+        (Some(first), Some(last)) => miette::SourceSpan::new(
+            first.offset.into(),
+            last.offset + last.text.len() - first.offset,
+        ),
+        _ => miette::SourceSpan::new(0.into(), 0),
+    }
 }
 
-fn parse_command_expression(input: Span) -> IResult<Span, Command, ErrorTree<Span>> {
+/// Parse an instruction to load another file, such as `LOAD "shapes.lg`. The file to load must
+/// be a literal, as files are loaded before the program runs.
+fn parse_load(input: Tokens) -> TokenResult<Command> {
+    let (rest, name) = preceded(
+        keyword("LOAD"),
+        token("a file name", |kind: &TokenKind| match kind {
+            TokenKind::Quoted(name) if !name.is_empty() => Some(name.to_owned()),
+            _ => None,
+        })
+        .context("parsing file name to load"),
+    )
+    .parse(input)?;
+
+    // Keep track of where the file was loaded, so that errors loading it can point here
+    Ok((rest, Command::Load(name, consumed_span(input, rest))))
+}
+
+fn parse_command_expression(input: Tokens) -> TokenResult<Command> {
     alt((
-        parse_comment,
+        parse_shadowing_invocation,
+        parse_load,
         parse_pen_state_commands,
+        parse_boundary_commands,
         parse_single_expression_commands,
         parse_control_flow_commands,
         parse_variable_manipulation_commands,
-        // For some reason, the "TO" in the procedure definition is being interpreted as a procedure invocation
+        parse_apply_command,
+        parse_pprop_command,
         parse_procedure_definition,
+        parse_primitive.map(|(name, arguments)| Command::Primitive(name, arguments)),
         parse_procedure_invocation,
     ))
-    .delimited_by(multispace0)
     .context("parsing a single command")
-    .parse(dbg!(input))
-}
-
-fn parse_commands_many(input: Span) -> IResult<Span, Vec<Command>, ErrorTree<Span>> {
-    many0(parse_command_expression)
-        // Ignore comments
-        .map(|res: Vec<Command>| -> Vec<Command> {
-            res.into_iter()
-                .filter_map(|x: Command| -> Option<Command> {
-                    match x {
-                        Command::Comment => None,
-                        _ => Some(x),
-                    }
-                })
-                .collect()
-        })
+    .parse(input)
+}
+
+/// Parse a command, keeping track of where it was written
+fn parse_located_command(input: Tokens) -> TokenResult<Command> {
+    let (rest, command) = parse_command_expression(input)?;
+    let location = Location {
+        line: input[0].line,
+        span: consumed_span(input, rest),
+        src: None,
+    };
+    Ok((rest, Command::Located(location, Box::new(command))))
+}
+
+fn parse_commands_many(input: Tokens) -> TokenResult<Vec<Command>> {
+    many0(parse_located_command)
         .context("parsing multiple commands")
         .parse(input)
 }
 
-pub fn parse(input: &str) -> Result<Vec<Command>, ParseError> {
-    match parse_commands_many
-        // Cut is necessary to get full backtrace
-        .cut()
-        // Throw an error if there are any unparsed strings
-        .all_consuming()
-        .context("parsing program")
-        .parse(Span::new(input))
-    {
-        Ok((_, res)) => Ok(res),
-        Err(e) => {
-            println!("{:?}", e);
-            match e {
-                // In nom, Incomplete represents a parse that is unsuccessful due to a lack of information,
-                // usually in the context of streaming parsers. It's the parser's way of saying "I can't
-                // determine what this is, I need more information please." We already have all the information
-                // we'll ever get from the start, so this error should never occur
-                nom::Err::Incomplete(_) => unreachable!("We're not using streaming parsers"),
-                // For the other two errors that may actually happen, we want to format them for miette to use.
-                nom::Err::Error(e) => Err(format_parse_error(input, e)),
-                nom::Err::Failure(e) => Err(format_parse_error(input, e)),
+/// A delimiter which opens a block that must be closed later on
+enum Opener<'a> {
+    Bracket(&'a Token),
+    Procedure(&'a Token),
+}
+
+/// Check that every `[` has a matching `]`, and every `TO` has a matching `END`. This is done
+/// separately from the nom parsers, as nom can only tell us that it failed to find a `]` somewhere
+/// after the opening bracket, rather than which bracket was left unclosed.
+fn check_delimiters(src: &SourceFile, tokens: Tokens) -> Vec<ParseError> {
+    let mut errors: Vec<ParseError> = Vec::new();
+    let mut openers: Vec<Opener> = Vec::new();
+
+    for token in tokens {
+        // `TO` and `END` inside brackets may just be words in a list, such as `[go to the store]`
+        let in_brackets: bool = matches!(openers.last(), Some(Opener::Bracket(_)));
+        match token.kind {
+            TokenKind::Keyword(Keyword::To) if !in_brackets => {
+                openers.push(Opener::Procedure(token))
+            }
+            TokenKind::OpenBracket => openers.push(Opener::Bracket(token)),
+            TokenKind::Keyword(Keyword::End) if !in_brackets => match openers.pop() {
+                Some(_) => (),
+                None => errors.push(ParseError::new(src, token, ParseErrorKind::UnexpectedEnd)),
+            },
+            TokenKind::CloseBracket => match openers.last() {
+                Some(Opener::Bracket(_)) => {
+                    openers.pop();
+                }
+                _ => errors.push(ParseError::new(
+                    src,
+                    token,
+                    ParseErrorKind::UnexpectedClosingBracket,
+                )),
+            },
+            _ => (),
+        }
+    }
+
+    // Anything still open at the end of the file was never closed
+    errors.extend(openers.into_iter().map(|opener| match opener {
+        Opener::Bracket(token) => ParseError::new(src, token, ParseErrorKind::UnclosedBracket),
+        Opener::Procedure(token) => ParseError::new(src, token, ParseErrorKind::MissingEnd),
+    }));
+
+    errors
+}
+
+/// Find every procedure definition named after a primitive. Primitives are resolved before
+/// parsing, so such a procedure could never be called. As in `check_delimiters`, a `TO` inside
+/// brackets is taken to be a word.
+fn check_procedure_names(src: &SourceFile, tokens: Tokens) -> Vec<ParseError> {
+    let mut errors: Vec<ParseError> = Vec::new();
+    let mut brackets: usize = 0;
+
+    for pair in tokens.windows(2) {
+        match (&pair[0].kind, &pair[1].kind) {
+            (TokenKind::OpenBracket, _) => brackets += 1,
+            (TokenKind::CloseBracket, _) => brackets = brackets.saturating_sub(1),
+            (TokenKind::Keyword(Keyword::To), TokenKind::Primitive { name, .. })
+                if brackets == 0 =>
+            {
+                errors.push(ParseError::new(
+                    src,
+                    &pair[1],
+                    ParseErrorKind::PrimitiveName(name.to_owned()),
+                ))
             }
+            _ => (),
         }
     }
+
+    errors
+}
+
+/// Find where parsing should resume after a command failed to parse at the start of `tokens`.
+/// We skip to the end of the current line, unless a block was opened on it, in which case we skip
+/// to the end of the line on which that block is closed. A failed procedure definition is skipped
+/// up to its `END`.
+fn resync(tokens: Tokens) -> usize {
+    let mut procedures: usize = 0;
+    let mut brackets: usize = 0;
+
+    for (i, token) in tokens.iter().enumerate() {
+        // As in `check_delimiters`, `TO` and `END` inside brackets are taken to be words
+        match token.kind {
+            TokenKind::Keyword(Keyword::To) if brackets == 0 => procedures += 1,
+            TokenKind::Keyword(Keyword::End) if brackets == 0 => {
+                procedures = procedures.saturating_sub(1)
+            }
+            TokenKind::OpenBracket => brackets += 1,
+            TokenKind::CloseBracket => brackets = brackets.saturating_sub(1),
+            _ => (),
+        }
+
+        let end_of_line: bool = match tokens.get(i + 1) {
+            Some(next) => next.line > token.line,
+            None => true,
+        };
+        if procedures == 0 && brackets == 0 && end_of_line {
+            return i + 1;
+        }
+    }
+
+    tokens.len()
+}
+
+/// A missing argument is only noticed at the token after it, which may be the first token of the
+/// next line. In that case, the error is moved back to the end of the line the command is on, so
+/// it points at the last character of the token before it, along with any context found there.
+fn at_end_of_line(mut error: ParseError, tokens: Tokens) -> ParseError {
+    let Some(i) = tokens.iter().position(|x| x.offset == error.offset()) else {
+        return error;
+    };
+    let Some(previous) = i.checked_sub(1).map(|i| &tokens[i]) else {
+        return error;
+    };
+    if tokens[i].line > previous.line {
+        let last_char: usize = previous.text.chars().next_back().map_or(0, char::len_utf8);
+        let span = miette::SourceSpan::new(
+            (previous.offset + previous.text.len() - last_char).into(),
+            last_char,
+        );
+        for context in error.others.iter_mut() {
+            if context.span.offset() == tokens[i].offset {
+                context.span = span;
+            }
+        }
+        error.span = span;
+    }
+    error
+}
+
+/// Point the location of every command at the file it was parsed from
+fn attach_source(commands: &mut [Command], src: &SourceFile) {
+    for command in commands {
+        match command {
+            Command::Located(location, command) => {
+                location.src = Some(src.clone());
+                attach_source(std::slice::from_mut(command.as_mut()), src);
+            }
+            Command::If(_, body)
+            | Command::While(_, body)
+            | Command::ProcedureDefine(_, _, body) => attach_source(body, src),
+            _ => (),
+        }
+    }
+}
+
+/// Turn every word naming a registered primitive into a primitive token. This happens before
+/// parsing, so a procedure can't share its name with a primitive.
+fn resolve_primitives(tokens: &mut [Token], primitives: &Primitives) {
+    for token in tokens {
+        if let TokenKind::Word(word) = &token.kind {
+            if let Some(primitive) = primitives.get(word) {
+                token.kind = TokenKind::Primitive {
+                    name: primitive.name.clone(),
+                    arity: primitive.arity,
+                };
+            }
+        }
+    }
+}
+
+/// Turn every keyword spelled exactly like the name of a procedure defined in the program into a
+/// shadowed keyword, so that procedures which happen to share a name with a keyword can still be
+/// defined and called. `TO` and `END` delimit definitions, so they can never be shadowed.
+fn resolve_procedures(tokens: &mut [Token]) {
+    let shadowable = |kind: &TokenKind| matches!(kind, TokenKind::Keyword(keyword) if !matches!(keyword, Keyword::To | Keyword::End));
+
+    let names: HashSet<String> = tokens
+        .windows(2)
+        .filter(|pair| pair[0].kind == TokenKind::Keyword(Keyword::To))
+        .filter(|pair| shadowable(&pair[1].kind))
+        .map(|pair| pair[1].text.clone())
+        .collect();
+
+    for token in tokens.iter_mut() {
+        if let TokenKind::Keyword(keyword) = token.kind {
+            if shadowable(&token.kind) && names.contains(&token.text) {
+                token.kind = TokenKind::Shadowed {
+                    keyword,
+                    name: token.text.clone(),
+                };
+            }
+        }
+    }
+}
+
+/// Parse a program, recovering after each syntax error so that every error in the file is
+/// reported at once. Words naming one of the given primitives are parsed as calls to it.
+pub fn parse(src: &SourceFile, primitives: &Primitives) -> Result<Vec<Command>, Diagnostics> {
+    let mut tokens: Vec<Token> = lex(src.inner());
+    resolve_primitives(&mut tokens, primitives);
+    resolve_procedures(&mut tokens);
+    let mut structural_errors: Vec<ParseError> = check_delimiters(src, &tokens);
+    structural_errors.extend(check_procedure_names(src, &tokens));
+    let mut errors: Vec<ParseError> = Vec::new();
+    let mut commands: Vec<Command> = Vec::new();
+    let mut remaining: Tokens = &tokens;
+
+    while !remaining.is_empty() {
+        match parse_located_command
+            // Cut is necessary to get full backtrace
+            .cut()
+            .context("parsing program")
+            .parse(remaining)
+        {
+            Ok((rest, command)) => {
+                commands.push(command);
+                remaining = rest;
+            }
+            Err(e) => {
+                let skipped: Tokens = &remaining[..resync(remaining)];
+                let error = match e {
+                    // In nom, Incomplete represents a parse that is unsuccessful due to a lack of information,
+                    // usually in the context of streaming parsers. It's the parser's way of saying "I can't
+                    // determine what this is, I need more information please." We already have all the information
+                    // we'll ever get from the start, so this error should never occur
+                    nom::Err::Incomplete(_) => unreachable!("We're not using streaming parsers"),
+                    // For the other two errors that may actually happen, we want to format them for miette to use.
+                    nom::Err::Error(e) | nom::Err::Failure(e) => format_parse_error(src, e),
+                };
+                // If no parser got past the first token, it doesn't start any command we know of.
+                // Listing every command it could have been isn't helpful, so just say so.
+                let error = match error.offset() == remaining[0].offset {
+                    true => ParseError::new(src, &remaining[0], ParseErrorKind::ExpectedCommand),
+                    false => at_end_of_line(error, remaining),
+                };
+
+                // A missing or extra delimiter already has a more specific error, so there is no
+                // need to also report the syntax error it caused.
+                if !structural_errors
+                    .iter()
+                    .any(|x| skipped.iter().any(|token| token.offset == x.offset()))
+                {
+                    errors.push(error);
+                }
+
+                remaining = &remaining[skipped.len()..];
+            }
+        }
+    }
+
+    errors.extend(structural_errors);
+    match errors.is_empty() {
+        true => {
+            attach_source(&mut commands, src);
+            Ok(commands)
+        }
+        false => {
+            errors.sort_by_key(|x| x.offset());
+            Err(Diagnostics { errors })
+        }
+    }
+}
+
+/// Parse a single expression written at runtime, such as a template filled in by `MAP`. Every
+/// word must be part of the expression.
+pub(crate) fn parse_template(source: &str, primitives: &Primitives) -> Result<Expression, String> {
+    let mut tokens: Vec<Token> = lex(source);
+    resolve_primitives(&mut tokens, primitives);
+    let mut parser = all_consuming(parse_expression);
+    match parser.parse(&tokens) {
+        Ok((_, expression)) => Ok(expression),
+        Err(_) => Err("expected a single expression".to_string()),
+    }
+}
+
+/// Strip the locations from parsed commands, so they can be compared with commands built by hand,
+/// or with the same program written out differently
+pub(crate) fn without_locations(commands: Vec<Command>) -> Vec<Command> {
+    commands
+        .into_iter()
+        .map(|command| match command {
+            Command::Located(_, command) => without_locations(vec![*command]).remove(0),
+            Command::If(condition, body) => Command::If(condition, without_locations(body)),
+            Command::While(condition, body) => Command::While(condition, without_locations(body)),
+            Command::ProcedureDefine(name, parameters, body) => {
+                Command::ProcedureDefine(name, parameters, without_locations(body))
+            }
+            command => command,
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -380,25 +908,138 @@ mod tests {
     use crate::tokens::Program;
 
     use super::*;
+    use crate::errors::source_file;
     use proptest::prelude::*;
 
+    /// Remove the location of every command, so that only the structure of the program is
+    /// compared
+    fn parse_str(input: &str) -> Result<Vec<Command>, Diagnostics> {
+        parse(&source_file("test.lg", input), &Primitives::new()).map(without_locations)
+    }
+
+    #[test]
+    fn commands_know_where_they_were_written() {
+        let input = "PENDOWN\nIF EQ \"1 \"1 [\n  FORWARD \"1\n]\n";
+        let commands = parse(&source_file("test.lg", input), &Primitives::new()).unwrap();
+        let Command::Located(location, command) = &commands[1] else {
+            panic!("commands should be located");
+        };
+        assert_eq!(location.file_name(), "test.lg");
+        assert_eq!(location.line, 2);
+        assert_eq!(location.span, (8, input.len() - 9).into());
+
+        let Command::If(_, body) = command.as_ref() else {
+            panic!("expected an IF");
+        };
+        let Command::Located(location, _) = &body[0] else {
+            panic!("commands in blocks should be located");
+        };
+        assert_eq!(location.line, 3);
+        assert_eq!(location.source_line(), "  FORWARD \"1");
+    }
+
     #[test]
     fn pen_commands_shouldnt_consume_anything_else() {
-        let input = "PENDOWNextra";
+        let input = lex("PENDOWN extra");
 
-        let (remainder, res): (Span, Command) =
-            parse_pen_state_commands(Span::new(input)).expect("This should be valid");
+        let (remainder, res): (Tokens, Command) =
+            parse_pen_state_commands(&input).expect("This should be valid");
         assert_eq!(
-            (remainder.into_fragment(), res),
-            ("extra", Command::PenDown)
+            (remainder[0].kind.clone(), res),
+            (TokenKind::Word(String::from("extra")), Command::PenDown)
         );
     }
 
+    #[test]
+    fn keywords_are_case_insensitive() {
+        let input: &str = "penup\nForward \"10\nif \"true [ pendown ]";
+        let expected: Vec<Command> = vec![
+            Command::PenUp,
+            Command::Forward(Expression::Value(EvalResult::Float(10.0))),
+            Command::If(
+                Expression::Value(EvalResult::Bool(true)),
+                vec![Command::PenDown],
+            ),
+        ];
+        assert_eq!(parse_str(input).expect("this should be valid"), expected);
+    }
+
+    #[test]
+    fn procedure_names_starting_with_keywords() {
+        let input: &str = "TO TOWER\n\tFORWARD \"1\nEND\nTOWER\nIF \"TRUE [TOWER]";
+        let expected: Vec<Command> = vec![
+            Command::ProcedureDefine(
+                Expression::Value(EvalResult::String(String::from("TOWER"))),
+                Vec::new(),
+                vec![Command::Forward(Expression::Value(EvalResult::Float(1.0)))],
+            ),
+            Command::ProcedureExec(String::from("TOWER"), Vec::new()),
+            Command::If(
+                Expression::Value(EvalResult::Bool(true)),
+                vec![Command::ProcedureExec(String::from("TOWER"), Vec::new())],
+            ),
+        ];
+        assert_eq!(parse_str(input).expect("this should be valid"), expected);
+    }
+
+    #[test]
+    fn procedures_can_shadow_keywords() {
+        let input: &str = "TO Count :x\nEND\nCount COUNT \"ab\nLABEL \"ab";
+        let expected: Vec<Command> = vec![
+            Command::ProcedureDefine(
+                Expression::Value(EvalResult::String(String::from("Count"))),
+                vec![Expression::GetVariable(Box::new(Expression::Variable(
+                    EvalResult::String(String::from("x")),
+                )))],
+                Vec::new(),
+            ),
+            Command::ProcedureExec(
+                String::from("Count"),
+                vec![Expression::Count(Box::new(Expression::Value(
+                    EvalResult::String(String::from("ab")),
+                )))],
+            ),
+            Command::Label(Expression::Value(EvalResult::String(String::from("ab")))),
+        ];
+        assert_eq!(parse_str(input).expect("this should be valid"), expected);
+    }
+
+    #[test]
+    fn lists_keep_their_words() {
+        let word = |x: &str| EvalResult::String(x.to_string());
+        let input: &str = "RUN [forward \"10 [:x 2.5] TRUE]";
+        let expected: Vec<Command> = vec![Command::Run(Expression::Value(EvalResult::List(vec![
+            word("forward"),
+            word("\"10"),
+            EvalResult::List(vec![word(":x"), EvalResult::Float(2.5)]),
+            EvalResult::Bool(true),
+        ])))];
+        assert_eq!(parse_str(input).expect("this should be valid"), expected);
+
+        // `TO` and `END` in a list are words, not the start and end of a procedure
+        let input: &str = "PRINT [go to the store]\nSHOW [up down Label end]";
+        let expected: Vec<Command> = vec![
+            Command::Print(Expression::Value(EvalResult::List(vec![
+                word("go"),
+                word("to"),
+                word("the"),
+                word("store"),
+            ]))),
+            Command::Show(Expression::Value(EvalResult::List(vec![
+                word("up"),
+                word("down"),
+                word("Label"),
+                word("end"),
+            ]))),
+        ];
+        assert_eq!(parse_str(input).expect("this should be valid"), expected);
+    }
+
     #[test]
     fn procedure_definition() {
         let input: &str = "TO Line\nPENDOWN\nFORWARD \"50\nPENUP\nEND";
         let expected: Vec<Command> = vec![Command::ProcedureDefine(
-            Expression::Value(EvalResult::String(input.to_string())),
+            Expression::Value(EvalResult::String(String::from("Line"))),
             Vec::new(),
             vec![
                 Command::PenDown,
@@ -407,7 +1048,7 @@ mod tests {
             ],
         )];
 
-        let res: Vec<Command> = parse(input).expect("this should be valid");
+        let res: Vec<Command> = parse_str(input).expect("this should be valid");
         assert_eq!(res, expected)
     }
     #[test]
@@ -417,26 +1058,98 @@ mod tests {
             Expression::Value(EvalResult::Bool(true)),
             vec![Command::ProcedureExec(String::from("Box"), Vec::new())],
         )];
-        let res: Vec<Command> = parse(input).expect("this should be valid");
+        let res: Vec<Command> = parse_str(input).expect("this should be valid");
         assert_eq!(res, expected);
     }
 
+    #[test]
+    fn report_every_syntax_error() {
+        let input: &str = "FORWARD\nPENUP\nLEFT \"1 \"2 \"3\nSETX\nPENDOWN";
+        let errors = parse_str(input).expect_err("this should be invalid").errors;
+        let lines: Vec<usize> = errors
+            .iter()
+            .map(|x| input[..x.offset()].matches('\n').count() + 1)
+            .collect();
+        assert_eq!(lines, vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn list_every_expected_expression() {
+        let errors = parse_str("FORWARD")
+            .expect_err("this should be invalid")
+            .errors;
+        let ParseErrorKind::ExpectedOneOf(expected) = &errors[0].kind else {
+            panic!("expected a list of expressions, found {:?}", errors[0].kind);
+        };
+        assert!(expected.contains(&"a literal value"));
+        assert!(expected.contains(&"MAP"));
+    }
+
+    #[test]
+    fn unclosed_bracket() {
+        let input: &str = "WHILE EQ \"TRUE \"TRUE [\n    PENDOWN";
+        let errors = parse_str(input).expect_err("this should be invalid").errors;
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].kind, ParseErrorKind::UnclosedBracket));
+        assert_eq!(errors[0].offset(), input.find('[').unwrap());
+    }
+
+    #[test]
+    fn unexpected_closing_bracket() {
+        let input: &str = "PENUP ]\nPENDOWN";
+        let errors = parse_str(input).expect_err("this should be invalid").errors;
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].kind,
+            ParseErrorKind::UnexpectedClosingBracket
+        ));
+    }
+
+    #[test]
+    fn end_without_to() {
+        let input: &str = "   FORWARD \"1\nEND\n\nPENDOWN\nIF \"TRUE [\n  PENUP\n]";
+        let errors = parse_str(input).expect_err("this should be invalid").errors;
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].kind, ParseErrorKind::UnexpectedEnd));
+        assert_eq!(errors[0].offset(), input.find("END").unwrap());
+    }
+
+    #[test]
+    fn missing_end() {
+        let input: &str = "TO Box \"Arg\n   FORWARD :Arg\n\nPENDOWN";
+        let errors = parse_str(input).expect_err("this should be invalid").errors;
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].kind, ParseErrorKind::MissingEnd));
+        assert_eq!(errors[0].offset(), 0);
+    }
+
+    #[test]
+    fn procedure_named_after_a_primitive() {
+        let mut primitives = Primitives::new();
+        primitives.register("Jump", 0, |_, _| Ok(None));
+        let input: &str = "TO jump\n   FORWARD \"1\nEND\n\nPENDOWN";
+        let errors = parse(&source_file("test.lg", input), &primitives)
+            .expect_err("this should be invalid")
+            .errors;
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0].kind, ParseErrorKind::PrimitiveName(name) if name == "Jump"));
+        assert_eq!(errors[0].offset(), input.find("jump").unwrap());
+    }
+
     #[test]
     fn tolerate_whitespace() {
         let input: &str = "  PENUP  ";
         let expected: Command = Command::PenUp;
-        let (_, res): (_, Command) =
-            parse_command_expression(Span::new(input)).expect("valid syntax");
+        let (_, res): (_, Command) = parse_command_expression(&lex(input)).expect("valid syntax");
         assert_eq!(res, expected);
         let input: &str = "\nPENUP\n";
         let expected: Command = Command::PenUp;
-        let (_, res): (_, Command) =
-            parse_command_expression(Span::new(input)).expect("valid syntax");
+        let (_, res): (_, Command) = parse_command_expression(&lex(input)).expect("valid syntax");
         assert_eq!(res, expected);
         let input: &str = "\nPENUP\n\nPENDOWN\n";
         let expected: Vec<Command> = vec![Command::PenUp, Command::PenDown];
-        let (_, res): (_, Vec<Command>) =
-            parse_commands_many(Span::new(input)).expect("valid syntax");
+        let (_, res): (_, Vec<Command>) = parse_commands_many(&lex(input)).expect("valid syntax");
+        let res: Vec<Command> = without_locations(res);
         assert_eq!(res, expected);
     }
     #[test]
@@ -447,22 +1160,22 @@ mod tests {
             Command::Forward(Expression::Value(EvalResult::Float(10.0))),
             Command::PenDown,
         ];
-        let (_, res): (_, Vec<Command>) =
-            parse_commands_many(Span::new(input)).expect("valid syntax");
+        let (_, res): (_, Vec<Command>) = parse_commands_many(&lex(input)).expect("valid syntax");
+        let res: Vec<Command> = without_locations(res);
         assert_eq!(res, expected);
     }
     #[test]
     fn parse_value() {
         let input = "\"100";
-        let (_, res) = parse_value_expression(Span::new(input)).expect("This should be valid");
+        let (_, res) = parse_value_expression(&lex(input)).expect("This should be valid");
         assert_eq!(res, Expression::Value(EvalResult::Float(100.0)));
 
         let input = "\"TRUE";
-        let (_, res) = parse_value_expression(Span::new(input)).expect("This should be valid");
+        let (_, res) = parse_value_expression(&lex(input)).expect("This should be valid");
         assert_eq!(res, Expression::Value(EvalResult::Bool(true)));
 
         let input = "\"var_name1";
-        let (_, res) = parse_value_expression(Span::new(input)).expect("This should be valid");
+        let (_, res) = parse_value_expression(&lex(input)).expect("This should be valid");
         assert_eq!(
             res,
             Expression::Value(EvalResult::String(String::from("var_name1")))
@@ -472,7 +1185,7 @@ mod tests {
     #[test]
     fn parse_recursive_expression() {
         let input = "EQ + \"1 \"1 \"2";
-        let (_, res) = parse_expression(Span::new(input)).unwrap();
+        let (_, res) = parse_expression(&lex(input)).unwrap();
         assert_eq!(
             res,
             Expression::Equals(
@@ -502,14 +1215,14 @@ mod tests {
             ],
         );
         let (_, result) =
-            parse_control_flow_commands(Span::new(input)).expect("This should be valid syntax");
-        assert_eq!(result, expected);
+            parse_control_flow_commands(&lex(input)).expect("This should be valid syntax");
+        assert_eq!(without_locations(vec![result]), vec![expected]);
     }
 
     macro_rules! float_operations_strategy {
         ($fn:ident, $op:expr) => {
-            fn $fn() -> impl Strategy<Value = (String, f32, f32)> {
-                (any::<f32>(), any::<f32>())
+            fn $fn() -> impl Strategy<Value = (String, f64, f64)> {
+                (any::<f64>(), any::<f64>())
                     .prop_map(move |(a, b)| (format!("{} \"{} \"{}", $op, a, b), a, b))
             }
         };
@@ -552,20 +1265,20 @@ mod tests {
 
         #[test]
         fn test_parse_addition_expression((input, a, b) in addition_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::Add(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Float(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Float(b), "RHS value does not match expected");
                 },
 
@@ -576,20 +1289,20 @@ mod tests {
 
         #[test]
         fn test_parse_subtraction_expression((input, a, b) in subtraction_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::Subtract(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Float(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Float(b), "RHS value does not match expected");
                 },
 
@@ -600,20 +1313,20 @@ mod tests {
 
         #[test]
         fn test_parse_multiplication_expression((input, a, b) in multiplication_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::Multiply(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Float(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Float(b), "RHS value does not match expected");
                 },
 
@@ -624,20 +1337,20 @@ mod tests {
 
         #[test]
         fn test_parse_division_expression((input, a, b) in division_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::Divide(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Float(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Float(b), "RHS value does not match expected");
                 },
 
@@ -648,20 +1361,20 @@ mod tests {
 
         #[test]
         fn test_parse_float_equals_expression((input, a, b) in float_equals_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::Equals(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Float(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Float(b), "RHS value does not match expected");
                 },
 
@@ -671,20 +1384,20 @@ mod tests {
         }
         #[test]
         fn test_parse_float_notequals_expression((input, a, b) in float_notequals_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::NotEquals(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Float(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Float(b), "RHS value does not match expected");
                 },
 
@@ -694,20 +1407,20 @@ mod tests {
         }
         #[test]
         fn test_parse_float_greaterthan_expression((input, a, b) in float_gt_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::GreaterThan(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Float(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Float(b), "RHS value does not match expected");
                 },
 
@@ -718,20 +1431,20 @@ mod tests {
 
         #[test]
         fn test_parse_float_lessthan_expression((input, a, b) in float_lt_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::LessThan(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Float(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Float(b), "RHS value does not match expected");
                 },
 
@@ -741,20 +1454,20 @@ mod tests {
         }
         #[test]
         fn test_parse_bool_equals_expression((input, a, b) in bool_equals_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::Equals(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Bool(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Bool(b), "RHS value does not match expected");
                 },
 
@@ -764,20 +1477,20 @@ mod tests {
         }
         #[test]
         fn test_parse_bool_notequals_expression((input, a, b) in bool_notequals_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::NotEquals(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Bool(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Bool(b), "RHS value does not match expected");
                 },
 
@@ -787,20 +1500,20 @@ mod tests {
         }
         #[test]
         fn test_parse_bool_greaterthan_expression((input, a, b) in bool_gt_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::GreaterThan(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Bool(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Bool(b), "RHS value does not match expected");
                 },
 
@@ -811,20 +1524,20 @@ mod tests {
 
         #[test]
         fn test_parse_bool_lessthan_expression((input, a, b) in bool_lt_test()) {
-            let span = Span::new(&input);
-            match parse_expression(span) {
+            let tokens = lex(&input);
+            match parse_expression(&tokens) {
                 Ok((remaining, Expression::LessThan(lhs, rhs))) => {
                     // Ensure the expression was fully consumed
-                    assert!(remaining.fragment().is_empty(), "Input was not fully consumed");
+                    assert!(remaining.is_empty(), "Input was not fully consumed");
 
                     // Example assertions (you'll need to replace these with actual logic to extract values from `lhs` and `rhs`)
                     // Dummy program for evaluation
-                    let context = Program::new(Vec::new());
+                    let mut context = Program::new(Vec::new());
 
-                    let lhs_val = lhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let lhs_val = lhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(lhs_val, EvalResult::Bool(a), "LHS value does not match expected");
 
-                    let rhs_val = rhs.eval(&context).expect("A simple Expression::Value should not fail to evaluate");
+                    let rhs_val = rhs.eval(&mut context).expect("A simple Expression::Value should not fail to evaluate");
                     assert_eq!(rhs_val, EvalResult::Bool(b), "RHS value does not match expected");
                 },
 
@@ -0,0 +1,276 @@
+use crate::canvas::{Canvas, Line};
+
+/// How far a pixel on the canvas is on paper, so a 500 pixel canvas is 125mm across
+const MILLIMETRES_PER_PIXEL: f64 = 0.25;
+
+/// How far apart two points can be while still counting as the same point, in pixels
+const TOLERANCE: f64 = 1e-3;
+
+/// How fast the pen moves while drawing, in millimetres per minute
+const FEED_RATE: u32 = 3000;
+
+/// HPGL positions are given in plotter units, which are 0.025mm
+const HPGL_UNITS_PER_MILLIMETRE: f64 = 40.0;
+
+/// The names of the colours in `unsvg::COLORS`, for asking for a pen to be changed
+const COLOUR_NAMES: [&str; 16] = [
+    "black", "blue", "cyan", "green", "red", "magenta", "yellow", "white", "brown", "tan",
+    "forest", "aqua", "salmon", "purple", "orange", "grey",
+];
+
+/// The languages pen plotters can be driven with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PlotFormat {
+    /// G-code, where `M3` puts the pen down, `M5` lifts it, and `M0` pauses to change pens
+    GCode,
+
+    /// HP-GL, where each colour is drawn with its own pen
+    Hpgl,
+}
+
+/// A line drawn in one go, without lifting the pen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stroke {
+    /// The index of the stroke's colour in `unsvg::COLORS`
+    pub colour: usize,
+
+    /// Every corner of the stroke, from where it starts to where it ends
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Stroke {
+    fn start(&self) -> (f64, f64) {
+        self.points[0]
+    }
+
+    fn end(&self) -> (f64, f64) {
+        self.points[self.points.len() - 1]
+    }
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+/// Join the lines drawn on a canvas into strokes. Lines which carry on from where the last one
+/// ended, in the same colour, are part of the same stroke, and corners which don't turn are
+/// removed.
+pub fn strokes(canvas: &Canvas) -> Vec<Stroke> {
+    let mut strokes: Vec<Stroke> = Vec::new();
+    for line in canvas.lines() {
+        let Line { start, end, .. } = *line;
+        if distance(start, end) <= TOLERANCE {
+            continue;
+        }
+        match strokes.last_mut() {
+            Some(stroke)
+                if stroke.colour == line.colour && distance(stroke.end(), start) <= TOLERANCE =>
+            {
+                // Carrying on in the same direction doesn't need a corner
+                let corner: (f64, f64) = stroke.end();
+                if let [.., before, _] = stroke.points[..] {
+                    let (a, b) = (
+                        (corner.0 - before.0, corner.1 - before.1),
+                        (end.0 - corner.0, end.1 - corner.1),
+                    );
+                    let cross: f64 = a.0 * b.1 - a.1 * b.0;
+                    let dot: f64 = a.0 * b.0 + a.1 * b.1;
+                    if dot > 0.0 && cross.abs() <= TOLERANCE * distance(before, corner).max(1.0) {
+                        stroke.points.pop();
+                    }
+                }
+                stroke.points.push(end);
+            }
+            _ => strokes.push(Stroke {
+                colour: line.colour,
+                points: vec![start, end],
+            }),
+        }
+    }
+    strokes
+}
+
+/// Put strokes in the order they should be plotted. Each colour is drawn in turn, so pens only
+/// need to be changed once per colour, and within a colour the closest stroke is always drawn
+/// next, backwards if its end is closer, so the pen travels as little as possible while lifted.
+pub fn order(strokes: Vec<Stroke>, start: (f64, f64)) -> Vec<Stroke> {
+    let mut colours: Vec<usize> = Vec::new();
+    for stroke in &strokes {
+        if !colours.contains(&stroke.colour) {
+            colours.push(stroke.colour);
+        }
+    }
+
+    let mut ordered: Vec<Stroke> = Vec::with_capacity(strokes.len());
+    let mut position: (f64, f64) = start;
+    for colour in colours {
+        let mut remaining: Vec<Stroke> = strokes
+            .iter()
+            .filter(|x: &&Stroke| x.colour == colour)
+            .cloned()
+            .collect();
+        while !remaining.is_empty() {
+            let (index, reversed) = remaining
+                .iter()
+                .enumerate()
+                .flat_map(|(i, x)| {
+                    [
+                        (distance(position, x.start()), i, false),
+                        (distance(position, x.end()), i, true),
+                    ]
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, i, reversed)| (i, reversed))
+                .expect("there is at least one stroke left");
+
+            let mut stroke: Stroke = remaining.swap_remove(index);
+            if reversed {
+                stroke.points.reverse();
+            }
+            position = stroke.end();
+            ordered.push(stroke);
+        }
+    }
+    ordered
+}
+
+/// Turn everything drawn on a canvas into instructions for a pen plotter. Paper has its origin
+/// in the bottom left corner, so the drawing is flipped to keep it the right way up. Labels
+/// can't be plotted, so they are left out.
+pub fn plot(canvas: &Canvas, format: PlotFormat) -> String {
+    let (_, height) = canvas.dimensions();
+    let height: f64 = height as f64;
+    let strokes: Vec<Stroke> = order(strokes(canvas), (0.0, height));
+    let to_paper = |(x, y): (f64, f64)| {
+        (
+            x * MILLIMETRES_PER_PIXEL,
+            (height - y) * MILLIMETRES_PER_PIXEL,
+        )
+    };
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut colour: Option<usize> = None;
+    match format {
+        PlotFormat::GCode => {
+            lines.push("; Plotted by rslogo".to_string());
+            lines.push("G21 ; millimetres".to_string());
+            lines.push("G90 ; absolute positions".to_string());
+            lines.push("M5 ; pen up".to_string());
+            lines.push(format!("F{FEED_RATE}"));
+            for stroke in &strokes {
+                if colour != Some(stroke.colour) {
+                    colour = Some(stroke.colour);
+                    lines.push(format!(
+                        "M0 ; change to pen {} ({})",
+                        stroke.colour,
+                        COLOUR_NAMES[stroke.colour.min(COLOUR_NAMES.len() - 1)]
+                    ));
+                }
+                let (x, y) = to_paper(stroke.start());
+                lines.push(format!("G0 X{x:.3} Y{y:.3}"));
+                lines.push("M3 ; pen down".to_string());
+                for &point in &stroke.points[1..] {
+                    let (x, y) = to_paper(point);
+                    lines.push(format!("G1 X{x:.3} Y{y:.3}"));
+                }
+                lines.push("M5 ; pen up".to_string());
+            }
+            lines.push("G0 X0 Y0".to_string());
+        }
+        PlotFormat::Hpgl => {
+            let to_units = |point: (f64, f64)| {
+                let (x, y) = to_paper(point);
+                (
+                    (x * HPGL_UNITS_PER_MILLIMETRE).round() as i64,
+                    (y * HPGL_UNITS_PER_MILLIMETRE).round() as i64,
+                )
+            };
+            lines.push("IN;".to_string());
+            for stroke in &strokes {
+                if colour != Some(stroke.colour) {
+                    colour = Some(stroke.colour);
+                    // Pen 0 means putting the pen away, so pens are numbered from 1
+                    lines.push(format!("SP{};", stroke.colour + 1));
+                }
+                let (x, y) = to_units(stroke.start());
+                lines.push(format!("PU{x},{y};"));
+                let points: Vec<String> = stroke.points[1..]
+                    .iter()
+                    .map(|&point| {
+                        let (x, y) = to_units(point);
+                        format!("{x},{y}")
+                    })
+                    .collect();
+                lines.push(format!("PD{};", points.join(",")));
+            }
+            lines.push("PU0,0;".to_string());
+            lines.push("SP0;".to_string());
+        }
+    }
+    lines
+        .iter()
+        .map(|line: &String| format!("{line}\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::draw;
+
+    #[test]
+    fn collinear_lines_are_merged() {
+        let (result, canvas) = draw("PENDOWN\nFORWARD \"10\nFORWARD \"10\nRIGHT \"10\nPENUP\nFORWARD \"5\nPENDOWN\nFORWARD \"5\n");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            strokes(&canvas),
+            vec![
+                Stroke {
+                    colour: 0,
+                    points: vec![(50.0, 50.0), (50.0, 30.0), (60.0, 30.0)]
+                },
+                Stroke {
+                    colour: 0,
+                    points: vec![(60.0, 25.0), (60.0, 20.0)]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn strokes_are_ordered_by_colour_then_distance() {
+        let stroke = |colour: usize, points: Vec<(f64, f64)>| Stroke { colour, points };
+        let ordered = order(
+            vec![
+                stroke(0, vec![(90.0, 0.0), (100.0, 0.0)]),
+                stroke(1, vec![(0.0, 0.0), (5.0, 0.0)]),
+                stroke(0, vec![(20.0, 0.0), (10.0, 0.0)]),
+            ],
+            (0.0, 0.0),
+        );
+        assert_eq!(
+            ordered,
+            vec![
+                stroke(0, vec![(10.0, 0.0), (20.0, 0.0)]),
+                stroke(0, vec![(90.0, 0.0), (100.0, 0.0)]),
+                stroke(1, vec![(5.0, 0.0), (0.0, 0.0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn plots_in_each_format() {
+        let (result, canvas) = draw("PENDOWN\nFORWARD \"10\nSETPENCOLOR \"4\nRIGHT \"10\n");
+        assert_eq!(result, Ok(()));
+
+        let hpgl = plot(&canvas, PlotFormat::Hpgl);
+        assert_eq!(
+            hpgl,
+            "IN;\nSP1;\nPU500,500;\nPD500,600;\nSP5;\nPU500,600;\nPD600,600;\nPU0,0;\nSP0;\n"
+        );
+
+        let gcode = plot(&canvas, PlotFormat::GCode);
+        assert!(gcode.contains("M0 ; change to pen 4 (red)\nG0 X12.500 Y15.000\nM3 ; pen down\nG1 X15.000 Y15.000\nM5 ; pen up\n"));
+    }
+}
@@ -0,0 +1,59 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    errors::InterpreterError,
+    tokens::{EvalResult, Program},
+};
+
+/// The function behind a primitive. It is given the program it is running in, along with its
+/// evaluated arguments, and may output a value.
+pub type PrimitiveFn = Arc<
+    dyn Fn(&mut Program, &[EvalResult]) -> Result<Option<EvalResult>, InterpreterError>
+        + Send
+        + Sync,
+>;
+
+/// A command or reporter provided by the program embedding the interpreter, rather than written
+/// in Logo. Primitives always take a fixed number of arguments, which is how the parser knows
+/// where their arguments end.
+#[derive(Clone)]
+pub struct Primitive {
+    pub name: String,
+    pub arity: usize,
+    pub(crate) function: PrimitiveFn,
+}
+
+/// Every primitive known to a program. Like keywords, primitive names are case-insensitive.
+#[derive(Clone, Default)]
+pub struct Primitives {
+    primitives: HashMap<String, Primitive>,
+}
+
+impl Primitives {
+    pub fn new() -> Self {
+        Primitives::default()
+    }
+
+    /// Add a primitive, replacing any other primitive with the same name
+    pub fn register<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&mut Program, &[EvalResult]) -> Result<Option<EvalResult>, InterpreterError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.primitives.insert(
+            name.to_uppercase(),
+            Primitive {
+                name: name.to_string(),
+                arity,
+                function: Arc::new(function),
+            },
+        );
+    }
+
+    /// Find the primitive with the given name, ignoring case
+    pub fn get(&self, name: &str) -> Option<&Primitive> {
+        self.primitives.get(&name.to_uppercase())
+    }
+}
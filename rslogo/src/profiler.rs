@@ -0,0 +1,189 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    errors::InterpreterError,
+    observer::Observer,
+    tokens::{Command, Frame, Location, Program},
+};
+
+/// The name of the frame every folded stack starts from, standing in for the top level
+const ROOT: &str = "main";
+
+/// How much work went into a single procedure or loop
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameStats {
+    /// How many times the procedure was called, or the loop was started
+    pub invocations: u64,
+
+    /// How many commands ran inside it, including inside anything it called
+    pub commands: u64,
+
+    /// How long it ran for, including anything it called. Time spent in recursive calls is only
+    /// counted once.
+    pub time: Duration,
+}
+
+/// Everything measured about a program while it ran
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    /// Statistics for each procedure and loop, by the name of its frame
+    frames: HashMap<String, FrameStats>,
+
+    /// How many commands ran with each call stack, with frames joined by `;`
+    stacks: HashMap<String, u64>,
+
+    /// The procedures and loops currently running, along with when they started
+    active: Vec<(String, Instant)>,
+}
+
+impl Profile {
+    /// Statistics for each procedure and loop, by the name of its frame
+    pub fn frames(&self) -> &HashMap<String, FrameStats> {
+        &self.frames
+    }
+
+    /// A table of every procedure and loop, with the slowest first
+    pub fn report(&self) -> String {
+        let mut frames: Vec<(&String, &FrameStats)> = self.frames.iter().collect();
+        frames.sort_by(|(a_name, a), (b_name, b)| b.time.cmp(&a.time).then(a_name.cmp(b_name)));
+
+        let width: usize = frames
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain(std::iter::once("procedure".len()))
+            .max()
+            .unwrap_or(0);
+        let mut report: String = format!(
+            "{:<width$}  {:>8}  {:>10}  {:>10}\n",
+            "procedure", "calls", "commands", "time (ms)"
+        );
+        for (name, stats) in frames {
+            report += &format!(
+                "{name:<width$}  {:>8}  {:>10}  {:>10.3}\n",
+                stats.invocations,
+                stats.commands,
+                stats.time.as_secs_f64() * 1000.0
+            );
+        }
+        report
+    }
+
+    /// Every call stack in the folded format read by flamegraph tools, one per line, weighted by
+    /// how many commands ran with that stack
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<(&String, &u64)> = self.stacks.iter().collect();
+        stacks.sort();
+        stacks
+            .into_iter()
+            .map(|(stack, count)| format!("{stack} {count}\n"))
+            .collect()
+    }
+}
+
+/// Measures how many times each procedure and loop runs, how many commands run inside it, and
+/// how long it takes. The profiler can be cloned before it is given to an interpreter, so the
+/// profile can be read once the program has finished.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    profile: Arc<Mutex<Profile>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// Everything measured so far
+    pub fn profile(&self) -> Profile {
+        self.profile.lock().expect("profile lock poisoned").clone()
+    }
+}
+
+impl Observer for Profiler {
+    fn before(
+        &mut self,
+        program: &Program,
+        _location: &Location,
+        _command: &Command,
+    ) -> Result<(), InterpreterError> {
+        let mut profile = self.profile.lock().expect("profile lock poisoned");
+        let names: Vec<String> = program.call_stack().iter().map(Frame::to_string).collect();
+
+        let stack: String = std::iter::once(ROOT.to_string())
+            .chain(names.iter().cloned())
+            .collect::<Vec<String>>()
+            .join(";");
+        *profile.stacks.entry(stack).or_default() += 1;
+
+        // A recursive procedure appears in the stack several times, but each command only ran once
+        for name in names.into_iter().collect::<HashSet<String>>() {
+            profile.frames.entry(name).or_default().commands += 1;
+        }
+        Ok(())
+    }
+
+    // Frames are counted as they are entered, rather than by the command which started them, so
+    // procedures called by `APPLY` and `RUN` are counted too
+    fn enter(&mut self, _program: &Program, frame: &Frame) {
+        let mut profile = self.profile.lock().expect("profile lock poisoned");
+        let name: String = frame.to_string();
+        profile.frames.entry(name.clone()).or_default().invocations += 1;
+        profile.active.push((name, Instant::now()));
+    }
+
+    fn leave(&mut self, _program: &Program, _frame: &Frame) {
+        let mut profile = self.profile.lock().expect("profile lock poisoned");
+        if let Some((name, started)) = profile.active.pop() {
+            // The outermost call of a recursive procedure already covers the time of the others
+            if !profile.active.iter().any(|(x, _)| *x == name) {
+                profile.frames.entry(name).or_default().time += started.elapsed();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{interpreter, run};
+
+    #[test]
+    fn counts_procedures_and_loops() {
+        let profiler = Profiler::new();
+        run(
+            &mut interpreter().with_observer(profiler.clone()),
+            "TO Step\n  FORWARD \"1\nEND\nMAKE \"i \"0\nWHILE LT :i \"3 [\n  Step\n  ADDASSIGN \"i \"1\n]\n",
+        )
+        .unwrap();
+
+        let profile = profiler.profile();
+        let step = &profile.frames()["Step"];
+        assert_eq!((step.invocations, step.commands), (3, 3));
+        let while_loop = &profile.frames()["WHILE (input:5)"];
+        assert_eq!((while_loop.invocations, while_loop.commands), (1, 9));
+        assert!(while_loop.time >= step.time);
+
+        assert_eq!(
+            profile.folded(),
+            "main 3\nmain;WHILE (input:5) 6\nmain;WHILE (input:5);Step 3\n"
+        );
+        assert!(profile.report().starts_with("procedure"));
+    }
+
+    #[test]
+    fn counts_procedures_however_they_are_called() {
+        let profiler = Profiler::new();
+        run(
+            &mut interpreter().with_observer(profiler.clone()),
+            "TO Step\nEND\nStep\nAPPLY \"Step []\nRUN [Step]\n",
+        )
+        .unwrap();
+
+        let profile = profiler.profile();
+        assert_eq!(profile.frames()["Step"].invocations, 3);
+    }
+}
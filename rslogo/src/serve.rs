@@ -0,0 +1,287 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use miette::{GraphicalReportHandler, GraphicalTheme};
+
+/// How often an idle event stream is written to, so that closed tabs are noticed
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// An error which stops a live preview being served
+#[derive(thiserror::Error, Debug, miette::Diagnostic)]
+pub enum ServeError {
+    #[error("Failed to listen on port {port}")]
+    #[diagnostic(help("another program may already be using it; try a different --port"))]
+    Bind { port: u16, source: std::io::Error },
+}
+
+/// The latest drawing of a program, shared between the thread which renders it and the threads
+/// serving it to browsers
+pub struct Preview {
+    /// Where the drawing is saved
+    image: PathBuf,
+
+    state: Mutex<State>,
+
+    /// Notified whenever the program is rendered again
+    rendered: Condvar,
+}
+
+struct State {
+    /// How many times the program has been rendered. Pages reload when it changes.
+    version: u64,
+
+    /// The diagnostic from the last render, if it failed
+    error: Option<String>,
+}
+
+impl Preview {
+    pub fn new(image: &Path) -> Self {
+        Preview {
+            image: image.to_path_buf(),
+            state: Mutex::new(State {
+                version: 0,
+                error: None,
+            }),
+            rendered: Condvar::new(),
+        }
+    }
+
+    /// Record the result of rendering the program again, and tell every open page to reload
+    pub fn update(&self, result: &miette::Result<()>) {
+        let mut state = self.state.lock().unwrap();
+        state.version += 1;
+        state.error = result.as_ref().err().map(|e| {
+            // Browsers can't show the colours used on a terminal
+            let mut report: String = String::new();
+            let handler = GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor());
+            match handler.render_report(&mut report, e.as_ref()) {
+                Ok(()) => report,
+                Err(_) => e.to_string(),
+            }
+        });
+        self.rendered.notify_all();
+    }
+
+    fn version(&self) -> u64 {
+        self.state.lock().unwrap().version
+    }
+
+    /// Wait until the program is rendered after `version`, returning the new version, or `None`
+    /// if it wasn't rendered before the timeout
+    fn wait(&self, version: u64, timeout: Duration) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .rendered
+            .wait_timeout_while(state, timeout, |x| x.version == version)
+            .unwrap();
+        (state.version != version).then_some(state.version)
+    }
+
+    /// The page showing the drawing, along with the diagnostic if the last render failed
+    fn page(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let name: String = escape(&self.image.display().to_string());
+        let error: String = match &state.error {
+            Some(e) => format!("<pre>{}</pre>\n", escape(e)),
+            None => String::new(),
+        };
+        let image: String = match self.image.exists() {
+            true => format!(
+                "<img src=\"/drawing?v={}\" alt=\"{name}\">\n",
+                state.version
+            ),
+            false => String::new(),
+        };
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{name}</title>
+<style>
+body {{ font-family: sans-serif; margin: 1em; }}
+pre {{ color: #b00020; white-space: pre-wrap; }}
+img {{ border: 1px solid #ccc; }}
+</style>
+</head>
+<body>
+{error}{image}<script>
+new EventSource("/events").onmessage = (event) => {{
+    if (event.data !== "{version}") location.reload();
+}};
+</script>
+</body>
+</html>
+"#,
+            version = state.version
+        )
+    }
+}
+
+/// Serve the preview on a listener until the process exits, with a thread for each connection.
+/// `/` is a page showing the drawing, `/drawing` is the drawing itself, and `/events` is a stream
+/// of server-sent events which tells the page to reload whenever the program is rendered again.
+pub fn serve(listener: TcpListener, preview: Arc<Preview>) {
+    // A failed connection only affects the browser which made it
+    for stream in listener.incoming().flatten() {
+        let preview: Arc<Preview> = preview.clone();
+        std::thread::spawn(move || {
+            let _ = respond(stream, &preview);
+        });
+    }
+}
+
+fn respond(mut stream: TcpStream, preview: &Preview) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request: String = String::new();
+    reader.read_line(&mut request)?;
+    // The headers don't change the response, but must be read before responding
+    let mut header: String = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut words = request.split_whitespace();
+    let (method, target) = (words.next(), words.next().unwrap_or("/"));
+    let path: &str = target.split('?').next().unwrap_or(target);
+    match (method, path) {
+        (Some("GET"), "/") => send(
+            &mut stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            preview.page().as_bytes(),
+        ),
+        (Some("GET"), "/drawing") => match std::fs::read(&preview.image) {
+            Ok(image) => send(&mut stream, "200 OK", content_type(&preview.image), &image),
+            Err(_) => send(
+                &mut stream,
+                "404 Not Found",
+                "text/plain",
+                b"Nothing drawn yet",
+            ),
+        },
+        (Some("GET"), "/events") => events(&mut stream, preview),
+        (Some("GET"), _) => send(&mut stream, "404 Not Found", "text/plain", b"Not found"),
+        _ => send(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"Only GET is supported",
+        ),
+    }
+}
+
+fn send(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+/// Send the current version straight away, so a page which missed a render reloads, then a new
+/// one after every render. This only stops when the browser closes the connection.
+fn events(stream: &mut TcpStream, preview: &Preview) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\nConnection: keep-alive\r\n\r\n"
+    )?;
+    let mut version: u64 = preview.version();
+    write!(stream, "data: {version}\n\n")?;
+    loop {
+        match preview.wait(version, KEEP_ALIVE) {
+            Some(x) => {
+                version = x;
+                write!(stream, "data: {version}\n\n")?;
+            }
+            None => write!(stream, ": keep-alive\n\n")?,
+        }
+        stream.flush()?;
+    }
+}
+
+fn content_type(image: &Path) -> &'static str {
+    match image.extension().and_then(|x| x.to_str()) {
+        Some("png") => "image/png",
+        _ => "image/svg+xml",
+    }
+}
+
+/// Escape text to be shown in HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn get(address: std::net::SocketAddr, path: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        stream
+    }
+
+    /// Read from a stream until it contains `text`
+    fn read_until(stream: &mut TcpStream, text: &str) -> String {
+        let mut read: String = String::new();
+        let mut buffer = [0; 1024];
+        while !read.contains(text) {
+            let n = stream.read(&mut buffer).unwrap();
+            assert!(n > 0, "closed before {text:?} in {read:?}");
+            read += std::str::from_utf8(&buffer[..n]).unwrap();
+        }
+        read
+    }
+
+    #[test]
+    fn pages_reload_when_the_program_is_rendered() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("drawing.svg");
+        std::fs::write(&image, "<svg></svg>").unwrap();
+        let preview = Arc::new(Preview::new(&image));
+        preview.update(&Ok(()));
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let served = preview.clone();
+        std::thread::spawn(move || serve(listener, served));
+
+        let mut page = String::new();
+        get(address, "/").read_to_string(&mut page).unwrap();
+        assert!(page.contains("<img src=\"/drawing?v=1\""));
+        let mut drawing = String::new();
+        get(address, "/drawing?v=1")
+            .read_to_string(&mut drawing)
+            .unwrap();
+        assert!(drawing.starts_with("HTTP/1.1 200 OK") && drawing.ends_with("<svg></svg>"));
+
+        let mut events = get(address, "/events");
+        read_until(&mut events, "data: 1\n\n");
+        preview.update(&Err(miette::miette!("<broken>")));
+        read_until(&mut events, "data: 2\n\n");
+
+        let mut page = String::new();
+        get(address, "/").read_to_string(&mut page).unwrap();
+        assert!(page.contains("&lt;broken&gt;"));
+    }
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::Line;
+
+/// A point or direction in 3D. `x` and `y` are the same as on the canvas, so `y` grows downwards,
+/// and `z` grows towards whoever is looking at the canvas.
+pub type Vec3 = [f64; 3];
+
+pub(crate) fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub(crate) fn scale(a: Vec3, by: f64) -> Vec3 {
+    [a[0] * by, a[1] * by, a[2] * by]
+}
+
+pub(crate) fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// A direction the same way as `a`, one unit long
+pub(crate) fn normalise(a: Vec3) -> Vec3 {
+    scale(a, 1.0 / (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt())
+}
+
+/// Rotate `a` towards `b` by `angle` degrees, where `a` and `b` are at right angles to each other
+/// and the same length. Returns both of them rotated, so they stay at right angles.
+pub(crate) fn rotate(a: Vec3, b: Vec3, angle: f64) -> (Vec3, Vec3) {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    (
+        add(scale(a, cos), scale(b, sin)),
+        add(scale(b, cos), scale(a, -sin)),
+    )
+}
+
+/// A line drawn by the turtle in 3D
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub start: Vec3,
+    pub end: Vec3,
+
+    /// The index of the line's colour in `unsvg::COLORS`
+    pub colour: usize,
+}
+
+/// How points in 3D are flattened onto the canvas
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Projection {
+    /// Points are moved straight onto the canvas, so things look the same size however far away
+    /// they are
+    Orthographic,
+
+    /// Points are seen from a camera this many pixels in front of the middle of the canvas, so
+    /// things further away look smaller. Anything level with or behind the camera isn't drawn.
+    Perspective { distance: f64 },
+}
+
+/// How a turtle moving in 3D is drawn onto the canvas. The drawing is turned about the middle of
+/// the canvas by `yaw` degrees around the vertical axis, then by `pitch` degrees around the
+/// horizontal axis, and then projected.
+///
+/// SVG and PNG images draw lines at whole degrees, so projected lines may not quite meet in them.
+/// Plotter files and OBJ files are exact.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct View {
+    pub projection: Projection,
+    pub yaw: f64,
+    pub pitch: f64,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View {
+            projection: Projection::Orthographic,
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+impl View {
+    /// Where a point appears on a canvas of the given size, or `None` if it can't be seen
+    pub fn project(&self, point: Vec3, (width, height): (u32, u32)) -> Option<(f64, f64)> {
+        let centre: (f64, f64) = (width as f64 / 2.0, height as f64 / 2.0);
+        let [x, y, z] = [point[0] - centre.0, point[1] - centre.1, point[2]];
+        let ([x, _, z], _) = rotate([x, 0.0, z], [-z, 0.0, x], self.yaw);
+        let ([_, y, z], _) = rotate([0.0, y, z], [0.0, -z, y], self.pitch);
+        let scale: f64 = match self.projection {
+            Projection::Orthographic => 1.0,
+            Projection::Perspective { distance } if z < distance => distance / (distance - z),
+            Projection::Perspective { .. } => return None,
+        };
+        Some((centre.0 + x * scale, centre.1 + y * scale))
+    }
+
+    /// The line a segment appears as on a canvas of the given size, or `None` if either end of it
+    /// can't be seen
+    pub fn project_segment(&self, segment: &Segment, size: (u32, u32)) -> Option<Line> {
+        let start: (f64, f64) = self.project(segment.start, size)?;
+        let end: (f64, f64) = self.project(segment.end, size)?;
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        Some(Line {
+            start,
+            end,
+            heading: dx.atan2(-dy).to_degrees().rem_euclid(360.0),
+            length: dx.hypot(dy),
+            colour: segment.colour,
+        })
+    }
+}
+
+/// Write segments as a Wavefront OBJ file, with a polyline for each run of segments which join
+/// end to end. OBJ files are usually `y` up, so `y` is flipped.
+pub fn obj(segments: &[Segment]) -> String {
+    let mut vertices: Vec<Vec3> = Vec::new();
+    let mut polylines: Vec<Vec<usize>> = Vec::new();
+    for segment in segments {
+        let joined: bool = vertices.last() == Some(&segment.start) && !polylines.is_empty();
+        if !joined {
+            vertices.push(segment.start);
+            polylines.push(vec![vertices.len()]);
+        }
+        vertices.push(segment.end);
+        if let Some(polyline) = polylines.last_mut() {
+            polyline.push(vertices.len());
+        }
+    }
+
+    let mut obj: String = String::from("# Drawn by rslogo\n");
+    for [x, y, z] in vertices {
+        obj += &format!("v {x} {} {z}\n", -y);
+    }
+    for polyline in polylines {
+        let indices: Vec<String> = polyline.iter().map(|x| x.to_string()).collect();
+        obj += &format!("l {}\n", indices.join(" "));
+    }
+    obj
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+    }
+
+    #[test]
+    fn projects_points() {
+        let size = (100, 100);
+        let front = View::default();
+        assert_eq!(front.project([60.0, 40.0, 25.0], size), Some((60.0, 40.0)));
+
+        // Turned a quarter of the way around, depth runs across the canvas
+        let side = View {
+            yaw: 90.0,
+            ..View::default()
+        };
+        assert!(close(
+            side.project([50.0, 40.0, 10.0], size).unwrap(),
+            (40.0, 40.0)
+        ));
+
+        let perspective = View {
+            projection: Projection::Perspective { distance: 100.0 },
+            ..View::default()
+        };
+        assert!(close(
+            perspective.project([60.0, 50.0, 50.0], size).unwrap(),
+            (70.0, 50.0)
+        ));
+        assert_eq!(perspective.project([60.0, 50.0, 100.0], size), None);
+    }
+
+    #[test]
+    fn joins_segments_into_polylines() {
+        let segments = [
+            Segment {
+                start: [0.0, 0.0, 0.0],
+                end: [1.0, 0.0, 0.0],
+                colour: 0,
+            },
+            Segment {
+                start: [1.0, 0.0, 0.0],
+                end: [1.0, 1.0, 2.0],
+                colour: 0,
+            },
+            Segment {
+                start: [5.0, 5.0, 5.0],
+                end: [6.0, 5.0, 5.0],
+                colour: 0,
+            },
+        ];
+        let obj = obj(&segments);
+        assert!(obj.contains("v 1 -1 2\n"));
+        assert!(obj.ends_with("l 1 2 3\nl 4 5\n"));
+    }
+}
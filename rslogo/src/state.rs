@@ -0,0 +1,123 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    canvas::Boundary,
+    errors::StateError,
+    space::View,
+    tokens::{Command, EvalResult, Program},
+    turtle::Turtle,
+    Canvas,
+};
+
+/// A checkpoint of a run, saved by `SAVESTATE`. Restoring it and running the same program from
+/// the `next` command carries on exactly where the run left off, even if it was saved inside a
+/// loop or procedure, so a long program can be paused and resumed later, even on another machine.
+///
+/// Only what the program built up is saved. The primitives, limits, observer, input and output
+/// belong to whoever is running the program, so they are left as they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    pub turtle: Turtle,
+    pub variables: HashMap<String, EvalResult>,
+
+    /// States saved before property lists existed don't have any
+    #[serde(default)]
+    pub properties: HashMap<String, Vec<(String, EvalResult)>>,
+
+    pub procedures: HashMap<String, Command>,
+    pub canvas: Canvas,
+    pub boundary: Boundary,
+
+    /// How a turtle moving in 3D was drawn
+    #[serde(default)]
+    pub view: Option<View>,
+
+    /// How close numbers had to be to compare as equal
+    #[serde(default)]
+    pub tolerance: f64,
+
+    /// The values the optimiser worked out once, before the loops which use them
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hoisted: HashMap<usize, EvalResult>,
+
+    /// A fingerprint of the program which saved the state, so it can't be resumed with another
+    #[serde(default)]
+    pub program: Option<u64>,
+
+    /// The index of the command at the top level of the program to carry on from. If `nested`
+    /// isn't empty, the run was saved inside the command before it, which carries on instead.
+    pub next: usize,
+
+    /// Where to carry on within the command which was running, as the index of a command in each
+    /// block or procedure body it had entered, from the outermost in
+    #[serde(default)]
+    pub nested: Vec<usize>,
+}
+
+impl State {
+    /// Save the state as JSON
+    pub fn save(&self, path: &Path) -> Result<(), StateError> {
+        let json: String = serde_json::to_string(self).map_err(|e| StateError::Invalid {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+        std::fs::write(path, json).map_err(|e| StateError::Io {
+            path: path.to_path_buf(),
+            source: e,
+        })
+    }
+
+    /// Load a state saved with [`State::save`]
+    pub fn load(path: &Path) -> Result<Self, StateError> {
+        let json: String = std::fs::read_to_string(path).map_err(|e| StateError::Io {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+        serde_json::from_str(&json).map_err(|e| StateError::Invalid {
+            path: path.to_path_buf(),
+            source: e,
+        })
+    }
+}
+
+/// A program is saved as its state
+impl Serialize for Program {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.state().serialize(serializer)
+    }
+}
+
+/// A loaded program has no commands of its own, and no limits, so it can run any commands
+/// against the restored state
+impl<'de> Deserialize<'de> for Program {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state: State = State::deserialize(deserializer)?;
+        let mut program: Program = Program::new(Vec::new());
+        program.restore(state).map_err(serde::de::Error::custom)?;
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::Expression;
+
+    #[test]
+    fn programs_round_trip() {
+        let mut program = Program::new(Vec::new());
+        Command::MakeVariable(
+            Expression::Value(EvalResult::String("x".to_string())),
+            Expression::Value(EvalResult::List(vec![EvalResult::Float(1.5)])),
+        )
+        .execute(&mut program)
+        .unwrap();
+        Command::PenDown.execute(&mut program).unwrap();
+
+        let json = serde_json::to_string(&program).unwrap();
+        let loaded: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.state(), program.state());
+    }
+}
@@ -0,0 +1,56 @@
+//! Helpers shared by the tests of every module
+
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use crate::{Canvas, Interpreter, InterpreterError};
+
+/// A writer which can still be read after an interpreter or observer has taken it
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Everything written so far
+    pub(crate) fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An interpreter with a 100 by 100 canvas, which most tests draw on
+pub(crate) fn interpreter() -> Interpreter {
+    Interpreter::new(Canvas::new(100, 100))
+}
+
+/// Parse a program, using the primitives registered with the interpreter, then run it
+pub(crate) fn run(interpreter: &mut Interpreter, source: &str) -> Result<(), InterpreterError> {
+    let ast = interpreter.parse(source).unwrap();
+    interpreter.run(&ast)
+}
+
+/// Run a program on a new canvas, returning how it finished along with what it drew
+pub(crate) fn draw(source: &str) -> (Result<(), InterpreterError>, Canvas) {
+    let mut interpreter = interpreter();
+    let result = run(&mut interpreter, source);
+    (result, interpreter.into_canvas())
+}
+
+/// Run a program which should succeed, returning the interpreter along with everything the
+/// program printed
+pub(crate) fn printed(interpreter: Interpreter, source: &str) -> (Interpreter, String) {
+    let output = SharedBuffer::default();
+    let mut interpreter = interpreter.with_output(output.clone());
+    run(&mut interpreter, source).unwrap();
+    (interpreter, output.contents())
+}
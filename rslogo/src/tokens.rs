@@ -1,9 +1,16 @@
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-
-use crate::errors::InterpreterError;
-use crate::turtle::Turtle;
+use crate::canvas::{Boundary, Canvas, Label, Line};
+use crate::errors::{source_file, InterpreterError, SourceFile, TurtleError};
+use crate::limits::{property_size, variable_size, Budget, Limits};
+use crate::observer::Observer;
+use crate::parsers::{parse, parse_template, without_locations};
+use crate::primitives::{Primitive, Primitives};
+use crate::space::{Segment, View};
+use crate::state::State;
+use crate::turtle::{PenState, Turtle};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
 
 use std::ops::{Add, Div, Mul, Sub};
 /// Macro to reduce boilerplate for arithmetic expressions
@@ -55,24 +62,88 @@ macro_rules! logical_operation {
 }
 
 /// Ensure that only these types can ever be ultimately produced by the evaluation of expressions
-#[derive(Debug, Clone, PartialEq)] // I think there might be a better way of doing this, but bools and f32 are small and cheap anyways
-pub(crate) enum EvalResult {
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)] // I think there might be a better way of doing this, but bools and f64s are small and cheap anyways
+pub enum EvalResult {
     Bool(bool),
-    Float(f32),
+    Float(f64),
     String(String),
+    List(Vec<EvalResult>),
+}
+
+impl EvalResult {
+    /// Read a word written in a program or typed in, as a number or boolean if it looks like one
+    pub fn from_word(word: &str) -> Self {
+        if let Ok(x) = word.parse::<f64>() {
+            return EvalResult::Float(x);
+        }
+        match word.to_uppercase().as_str() {
+            "TRUE" => EvalResult::Bool(true),
+            "FALSE" => EvalResult::Bool(false),
+            _ => EvalResult::String(word.to_string()),
+        }
+    }
+
+    /// The items of a list, separated by spaces and without the outer brackets
+    pub fn join(items: &[EvalResult]) -> String {
+        items
+            .iter()
+            .map(EvalResult::to_string)
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// How the value is printed by `PRINT` and `TYPE`, which leave off the brackets of a list
+    fn printed(&self) -> String {
+        match self {
+            EvalResult::List(items) => EvalResult::join(items),
+            value => value.to_string(),
+        }
+    }
+
+    /// How the value would be written in a program, with a quote before a word
+    fn literal(&self) -> String {
+        match self {
+            EvalResult::List(_) => self.to_string(),
+            word => format!("\"{word}"),
+        }
+    }
+
+    /// The name of the value's type, for error messages
+    fn type_name(&self) -> &'static str {
+        match self {
+            EvalResult::Bool(_) => "boolean",
+            EvalResult::Float(_) => "float",
+            EvalResult::String(_) => "string",
+            EvalResult::List(_) => "list",
+        }
+    }
+}
+
+impl std::fmt::Display for EvalResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalResult::Bool(true) => write!(f, "TRUE"),
+            EvalResult::Bool(false) => write!(f, "FALSE"),
+            EvalResult::Float(x) => write!(f, "{x}"),
+            EvalResult::String(x) => write!(f, "{x}"),
+            EvalResult::List(x) => write!(f, "[{}]", EvalResult::join(x)),
+        }
+    }
 }
 
 /// Expressions are instructions which returns a value, but do not perform any actions.
 /// This is contrary to Commands, which perform actions, but do not return any value.
 ///
 /// Example:
-/// ```
+/// ```ignore
 /// let lhs = Expression::Value(EvalResult::Float(1));
 /// let rhs = Expression::Value(EvalResult::Float(2));
 /// assert_eq!(Expression::Add(lhs, rhs), EvalResult::Float(3));
 /// ```
-#[derive(Debug, Clone, PartialEq)]
-pub(crate) enum Expression {
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Expression {
     /// The most fundamental expression, a value, denoted by a double quote (`"`)
     /// followed by a literal value (either a float, or a boolean).
     /// This would simply evaluate to itself.
@@ -98,6 +169,14 @@ pub(crate) enum Expression {
     /// Note that `Divide(a, b)` is interpreted as `a / b`
     Divide(Box<Expression>, Box<Expression>),
 
+    /// Divide one expression by another, leaving out any fraction, so `Quotient(a, b)` is
+    /// `a / b` rounded towards zero
+    Quotient(Box<Expression>, Box<Expression>),
+
+    /// What is left over after dividing one expression by another a whole number of times. The
+    /// remainder has the same sign as `a` in `Remainder(a, b)`.
+    Remainder(Box<Expression>, Box<Expression>),
+
     /// Check if two expressions have equivalent values
     Equals(Box<Expression>, Box<Expression>),
 
@@ -124,16 +203,65 @@ pub(crate) enum Expression {
     /// Returns the turtle's y-coordinates
     YCor,
 
+    /// Returns how far the turtle is in front of the canvas, when it moves in 3D
+    ZCor,
+
     /// Returns the turtle's heading
     Heading,
 
     /// Returns the pen colour
     Colour,
+
+    /// Returns the output of a primitive provided by the host
+    Primitive(String, Vec<Expression>),
+
+    /// Reads a line of input as a single word
+    ReadWord,
+
+    /// Reads a line of input as a list of words
+    ReadList,
+
+    /// Join two words into one
+    Word(Box<Expression>, Box<Expression>),
+
+    /// Returns true if the expression is a word, rather than a list
+    WordP(Box<Expression>),
+
+    /// Returns true if the expression is a number
+    NumberP(Box<Expression>),
+
+    /// Returns true if the expression is a whole number
+    IntegerP(Box<Expression>),
+
+    /// Returns the number of characters in a word, or items in a list
+    Count(Box<Expression>),
+
+    /// Fill in a template, such as `[* ? "2]`, with each item of a list in turn, and return a
+    /// list of the results. `?` stands for the item.
+    Map(Box<Expression>, Box<Expression>),
+
+    /// Return the items of a list for which a template returns true
+    Filter(Box<Expression>, Box<Expression>),
+
+    /// Combine the items of a list, from first to last, with a template such as `[+ ?1 ?2]`.
+    /// `?1` stands for everything combined so far, and `?2` for the next item.
+    Reduce(Box<Expression>, Box<Expression>),
+
+    /// Returns the value of a property in a property list, such as `GPROP "square "size`
+    GetProperty(Box<Expression>, Box<Expression>),
+
+    /// Returns a property list as a list of each property followed by its value, or an empty
+    /// list if it has no properties
+    PropertyList(Box<Expression>),
+
+    /// A value the optimiser worked out before a loop started, by its number. These are kept
+    /// apart from the program's variables, so the program never sees them.
+    Hoisted(usize),
 }
 
 impl Expression {
-    /// Evaluates this expression. When successful, returns an instance of EvalResult (either a boolean or f32).
-    pub fn eval(&self, context: &Program) -> Result<EvalResult, InterpreterError> {
+    /// Evaluates this expression. When successful, returns an instance of EvalResult (either a boolean or f64).
+    pub fn eval(&self, context: &mut Program) -> Result<EvalResult, InterpreterError> {
         match self {
             Expression::Value(value) => Ok(value.clone()),
             Expression::Variable(name) => match name {
@@ -144,6 +272,7 @@ impl Expression {
                     Err(InterpreterError::invalid_type("variable name", "float"))
                 }
                 EvalResult::String(res) => Ok(EvalResult::String(res.to_owned())),
+                EvalResult::List(_) => Err(InterpreterError::invalid_type("variable name", "list")),
             },
             Expression::GetVariable(key) => {
                 let variable_name: String = match key.eval(context)? {
@@ -154,6 +283,9 @@ impl Expression {
                         return Err(InterpreterError::invalid_type("variable name", "float"))
                     }
                     EvalResult::String(name) => name,
+                    EvalResult::List(_) => {
+                        return Err(InterpreterError::invalid_type("variable name", "list"))
+                    }
                 };
 
                 match context.variables.get(&variable_name) {
@@ -176,14 +308,28 @@ impl Expression {
                 let divisor = rhs.eval(context)?;
                 match divisor {
                     // Additional error checking to prevent divdide by zero errors.
-                    EvalResult::Float(val) if val == (0 as f32) => {
+                    EvalResult::Float(val) if val == (0 as f64) => {
                         Err(InterpreterError::division_by_zero())
                     }
                     _ => arithmetic_operation!(div, lhs, rhs, context, "division of booleans"),
                 }
             }
-            Expression::Equals(lhs, rhs) => comparison!(eq, lhs, rhs, context),
-            Expression::NotEquals(lhs, rhs) => comparison!(ne, lhs, rhs, context),
+            Expression::Quotient(lhs, rhs) => {
+                let (dividend, divisor) = context.eval_division(lhs, rhs)?;
+                Ok(EvalResult::Float((dividend / divisor).trunc()))
+            }
+            Expression::Remainder(lhs, rhs) => {
+                let (dividend, divisor) = context.eval_division(lhs, rhs)?;
+                Ok(EvalResult::Float(dividend % divisor))
+            }
+            Expression::Equals(lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(context)?, rhs.eval(context)?);
+                Ok(EvalResult::Bool(context.equals(lhs, rhs)?))
+            }
+            Expression::NotEquals(lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(context)?, rhs.eval(context)?);
+                Ok(EvalResult::Bool(!context.equals(lhs, rhs)?))
+            }
             Expression::GreaterThan(lhs, rhs) => comparison!(gt, lhs, rhs, context),
             Expression::LessThan(lhs, rhs) => comparison!(lt, lhs, rhs, context),
             Expression::And(lhs, rhs) => logical_operation!(&&, lhs, rhs, context),
@@ -196,22 +342,181 @@ impl Expression {
                 let (_, ycor) = context.turtle.get_turtle_coords();
                 Ok(EvalResult::Float(ycor))
             }
+            Expression::ZCor => {
+                context.needs_3d("ZCOR")?;
+                Ok(EvalResult::Float(context.turtle.get_z()))
+            }
             Expression::Heading => Ok(EvalResult::Float(context.turtle.get_heading())),
             Expression::Colour => Ok(EvalResult::Float(context.turtle.get_pen_colour())),
+            Expression::Hoisted(number) => context.hoisted.get(number).cloned().ok_or_else(|| {
+                InterpreterError::unsuccessful_operation("fetching a hoisted value")
+            }),
+            Expression::Primitive(name, arguments) => context
+                .call_primitive(name, arguments)?
+                .ok_or_else(|| InterpreterError::no_output(name)),
+            Expression::ReadWord => Ok(context
+                .read_line()?
+                .map_or(EvalResult::String(String::new()), |x: String| {
+                    EvalResult::from_word(x.trim())
+                })),
+            Expression::ReadList => Ok(EvalResult::List(
+                context
+                    .read_line()?
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(EvalResult::from_word)
+                    .collect(),
+            )),
+            Expression::Word(lhs, rhs) => {
+                let words: [EvalResult; 2] = [lhs.eval(context)?, rhs.eval(context)?];
+                if words.iter().any(|x| matches!(x, EvalResult::List(_))) {
+                    return Err(InterpreterError::invalid_type("word", "list"));
+                }
+                Ok(EvalResult::from_word(&format!("{}{}", words[0], words[1])))
+            }
+            Expression::WordP(value) => Ok(EvalResult::Bool(!matches!(
+                value.eval(context)?,
+                EvalResult::List(_)
+            ))),
+            Expression::NumberP(value) => Ok(EvalResult::Bool(matches!(
+                value.eval(context)?,
+                EvalResult::Float(_)
+            ))),
+            Expression::IntegerP(value) => Ok(EvalResult::Bool(matches!(
+                value.eval(context)?,
+                EvalResult::Float(x) if x.fract() == 0.0
+            ))),
+            Expression::Count(value) => {
+                let count: usize = match value.eval(context)? {
+                    EvalResult::List(items) => items.len(),
+                    word => word.to_string().chars().count(),
+                };
+                Ok(EvalResult::Float(count as f64))
+            }
+            Expression::Map(template, list) => {
+                let template: EvalResult = template.eval(context)?;
+                let items: Vec<EvalResult> = context.eval_list(list, "map")?;
+                items
+                    .into_iter()
+                    .map(|x: EvalResult| context.fill_template(&template, &[x]))
+                    .collect::<Result<Vec<EvalResult>, InterpreterError>>()
+                    .map(EvalResult::List)
+            }
+            Expression::Filter(template, list) => {
+                let template: EvalResult = template.eval(context)?;
+                let items: Vec<EvalResult> = context.eval_list(list, "filter")?;
+                let mut kept: Vec<EvalResult> = Vec::new();
+                for item in items {
+                    match context.fill_template(&template, std::slice::from_ref(&item))? {
+                        EvalResult::Bool(true) => kept.push(item),
+                        EvalResult::Bool(false) => (),
+                        other => {
+                            return Err(InterpreterError::invalid_type(
+                                "filter condition",
+                                other.type_name(),
+                            ))
+                        }
+                    }
+                }
+                Ok(EvalResult::List(kept))
+            }
+            Expression::Reduce(template, list) => {
+                let template: EvalResult = template.eval(context)?;
+                let mut items = context.eval_list(list, "reduce")?.into_iter();
+                let first: EvalResult = items.next().ok_or_else(|| {
+                    InterpreterError::unsupported_operation("reducing an empty list")
+                })?;
+                items.try_fold(first, |combined: EvalResult, x: EvalResult| {
+                    context.fill_template(&template, &[combined, x])
+                })
+            }
+            Expression::GetProperty(list, property) => {
+                let list: String = context.eval_name(list, "property list name")?;
+                let property: String = context.eval_name(property, "property name")?;
+                context.get_property(&list, &property)
+            }
+            Expression::PropertyList(list) => {
+                let list: String = context.eval_name(list, "property list name")?;
+                let properties = context.properties.get(&list).into_iter().flatten();
+                Ok(EvalResult::List(
+                    properties
+                        .flat_map(|(name, value)| [EvalResult::from_word(name), value.clone()])
+                        .collect(),
+                ))
+            }
         }
     }
 }
 
+/// Where a command was written in the source of a program
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Location {
+    /// The line the command starts on, starting from 1
+    pub line: usize,
+
+    /// The source covered by the command, including any block it has
+    pub span: miette::SourceSpan,
+
+    /// The file the command was written in. This is filled in once the whole file is parsed.
+    #[serde(with = "file_name")]
+    pub(crate) src: Option<SourceFile>,
+}
+
+/// Saved states only keep the name of the file each command was written in, as every command
+/// would otherwise need its own copy of the file. Loaded commands can't show their source lines.
+mod file_name {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        src: &Option<SourceFile>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        src.as_ref().map(|x| x.name()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SourceFile>, D::Error> {
+        Option::<String>::deserialize(deserializer).map(|x| x.map(|name| source_file(name, "")))
+    }
+}
+
+impl Location {
+    /// The name of the file the command was written in
+    pub fn file_name(&self) -> &str {
+        self.src.as_ref().map_or("", |x| x.name())
+    }
+
+    /// The full text of the line the command starts on
+    pub fn source_line(&self) -> &str {
+        self.src
+            .as_ref()
+            .and_then(|x| x.inner().lines().nth(self.line - 1))
+            .unwrap_or("")
+    }
+}
+
+/// Locations are compared by where they are, regardless of which copy of a file they point into
+impl PartialEq for Location {
+    fn eq(&self, other: &Self) -> bool {
+        self.line == other.line && self.span == other.span && self.file_name() == other.file_name()
+    }
+}
+
 /// This is a list of executable commands for the logo language. They may take in strings, Expressions, or vectors of Commands as argument
-#[derive(Debug, Clone, PartialEq)]
-pub(crate) enum Command {
-    Comment,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Command {
     /// Command to set the pen state to up.
     PenUp,
 
     /// Command to set the pen state to down.
     PenDown,
 
+    /// Command to set what happens when the turtle reaches the edge of the canvas.
+    SetBoundary(Boundary),
+
     /// Command to move the pen forward by a certain distance.
     Forward(Expression),
 
@@ -239,12 +544,76 @@ pub(crate) enum Command {
     /// Command to set the Y-axis position of the pen to a specific value.
     SetY(Expression),
 
+    /// Command to set how far the pen is in front of the canvas, when it moves in 3D.
+    SetZ(Expression),
+
+    /// Command to tilt the pen's nose up by a certain number of degrees, when it moves in 3D.
+    Up(Expression),
+
+    /// Command to tilt the pen's nose down by a certain number of degrees, when it moves in 3D.
+    Down(Expression),
+
+    /// Command to roll the pen onto its left side by a certain number of degrees, when it moves
+    /// in 3D.
+    RollLeft(Expression),
+
+    /// Command to roll the pen onto its right side by a certain number of degrees, when it moves
+    /// in 3D.
+    RollRight(Expression),
+
+    /// Command to write text at the turtle's position, running along its heading.
+    Label(Expression),
+
+    /// Command to set the height of text written by `LABEL`.
+    SetLabelHeight(Expression),
+
+    /// Command to set the font family of text written by `LABEL`.
+    SetFont(Expression),
+
+    /// Command to run a list of instructions, such as `[FORWARD "10]`, as if it were written in
+    /// the program.
+    Run(Expression),
+
+    /// Command to call the named procedure or primitive with the values in a list as its
+    /// arguments.
+    Apply(Expression, Expression),
+
+    /// Command to write a value to the output, followed by a new line. Lists are written without
+    /// their outer brackets.
+    Print(Expression),
+
+    /// Command to write a value to the output, followed by a new line, with lists in brackets.
+    Show(Expression),
+
+    /// Command to write a value to the output like `PRINT`, without a new line.
+    Type(Expression),
+
+    /// Command to save the turtle, variables, procedures and drawing to a file, so the program
+    /// can be resumed from the next command, even inside a loop or procedure.
+    SaveState(Expression),
+
+    /// Command to replace the turtle, variables, procedures and drawing with those saved in a
+    /// file. Only the state is swapped: the program carries on from the command after this one,
+    /// not from where the state was saved, which only `Interpreter::resume` can do.
+    LoadState(Expression),
+
     /// Command to create a new variable.
     MakeVariable(Expression, Expression),
 
     /// Command to increment the value of an existing variable by a certain number. Will not work if the variable does not exist yet.
     Increment(Expression, Expression),
 
+    /// Command to set a property in a property list, creating the list if it doesn't exist yet.
+    PutProperty(Expression, Expression, Expression),
+
+    /// Command to remove a property from a property list. Removing a property which isn't set
+    /// does nothing.
+    RemoveProperty(Expression, Expression),
+
+    /// Work out a value before a loop starts, for `Expression::Hoisted` with the same number to
+    /// read. Only the optimiser makes these.
+    Hoist(usize, Expression),
+
     /// Command to execute a set of commands only if an expression evaluates to true
     If(Expression, Vec<Command>),
 
@@ -256,513 +625,557 @@ pub(crate) enum Command {
 
     /// An executable procedure
     ProcedureExec(String, Vec<Expression>),
+
+    /// Run a primitive provided by the host, ignoring anything it outputs
+    Primitive(String, Vec<Expression>),
+
+    /// A command along with where it was written. The parser wraps every command in one of these,
+    /// so that tools such as the tracer and debugger can say where the program is.
+    Located(Location, Box<Command>),
+
+    /// Load the procedures from another file. These are resolved by the `Loader` before the
+    /// program runs, so they should never be executed.
+    Load(String, miette::SourceSpan),
 }
 
 impl Command {
+    /// The command itself, without where it was written
+    pub fn without_location(&self) -> &Command {
+        match self {
+            Command::Located(_, command) => command.without_location(),
+            command => command,
+        }
+    }
+
     /// Run the command token
-    fn execute(&self, context: &mut Program) -> Result<(), Box<dyn Error>> {
+    pub(crate) fn execute(&self, context: &mut Program) -> Result<(), InterpreterError> {
+        if let Command::Located(location, command) = self {
+            context.notify_before(location, command)?;
+            let result: Result<(), InterpreterError> = match command.without_location() {
+                // Loops are the only commands without a name which can run for a long time, so
+                // they get a frame of their own, named by where they were written
+                Command::While(_, _) => {
+                    context.push_frame(Frame::Loop(location.clone()));
+                    let result = command.execute(context);
+                    context.pop_frame();
+                    result
+                }
+                _ => command.execute(context),
+            };
+            context.notify_after(location, command, &result);
+            return result;
+        }
+        context.budget.step(&context.limits)?;
+
         match self {
-            Command::Comment => Ok(()),
             // Pen state manipulation
             Command::PenUp => match context.turtle.set_pen_state(crate::turtle::PenState::Up) {
                 crate::turtle::PenState::Up => Ok(()),
-                crate::turtle::PenState::Down => Err(Box::new(
-                    InterpreterError::unsuccessful_operation("setting the pen state to up"),
+                crate::turtle::PenState::Down => Err(InterpreterError::unsuccessful_operation(
+                    "setting the pen state to up",
                 )),
             },
             Command::PenDown => match context.turtle.set_pen_state(crate::turtle::PenState::Down) {
                 crate::turtle::PenState::Down => Ok(()),
-                crate::turtle::PenState::Up => Err(Box::new(
-                    InterpreterError::unsuccessful_operation("setting the pen state to down"),
+                crate::turtle::PenState::Up => Err(InterpreterError::unsuccessful_operation(
+                    "setting the pen state to down",
                 )),
             },
+            Command::SetBoundary(boundary) => context.set_boundary(*boundary),
             Command::SetPenColor(colour) => match colour.eval(context)? {
-                EvalResult::Bool(_) => Err(Box::new(InterpreterError::invalid_type(
-                    "pen colour",
-                    "boolean",
-                ))),
+                EvalResult::Bool(_) => Err(InterpreterError::invalid_type("pen colour", "boolean")),
                 EvalResult::Float(val) => {
                     context.turtle.set_pen_colour(val)?;
                     Ok(())
                 }
-                EvalResult::String(_) => Err(Box::new(InterpreterError::invalid_type(
-                    "pen colour",
-                    "string",
-                ))),
+                EvalResult::String(_) => {
+                    Err(InterpreterError::invalid_type("pen colour", "string"))
+                }
+                EvalResult::List(_) => Err(InterpreterError::invalid_type("pen colour", "list")),
             },
 
             // Turtle movement
             Command::Forward(distance) => {
                 let value: EvalResult = distance.eval(context)?;
                 match value {
-                    EvalResult::String(_) => Err(Box::new(InterpreterError::invalid_type(
-                        "distance", "string",
-                    ))),
-                    EvalResult::Bool(_) => Err(Box::new(InterpreterError::invalid_type(
-                        "distance", "boolean",
-                    ))),
-                    EvalResult::Float(forward_distance) => {
-                        let _ = context.turtle.move_turtle(None, Some(forward_distance));
-                        Ok(())
+                    EvalResult::String(_) => {
+                        Err(InterpreterError::invalid_type("distance", "string"))
                     }
+                    EvalResult::List(_) => Err(InterpreterError::invalid_type("distance", "list")),
+                    EvalResult::Bool(_) => {
+                        Err(InterpreterError::invalid_type("distance", "boolean"))
+                    }
+                    EvalResult::Float(forward_distance) => context.walk(0.0, forward_distance),
                 }
             }
             Command::Back(distance) => {
                 let value: EvalResult = distance.eval(context)?;
                 match value {
-                    EvalResult::String(_) => Err(Box::new(InterpreterError::invalid_type(
-                        "distance", "string",
-                    ))),
-                    EvalResult::Bool(_) => Err(Box::new(InterpreterError::invalid_type(
-                        "distance", "boolean",
-                    ))),
-                    EvalResult::Float(backward_distance) => {
-                        let _ = context.turtle.move_turtle(None, Some(-backward_distance));
-                        Ok(())
+                    EvalResult::String(_) => {
+                        Err(InterpreterError::invalid_type("distance", "string"))
+                    }
+                    EvalResult::List(_) => Err(InterpreterError::invalid_type("distance", "list")),
+                    EvalResult::Bool(_) => {
+                        Err(InterpreterError::invalid_type("distance", "boolean"))
                     }
+                    EvalResult::Float(backward_distance) => context.walk(180.0, backward_distance),
                 }
             }
             Command::Left(distance) => {
                 let value: EvalResult = distance.eval(context)?;
                 match value {
-                    EvalResult::String(_) => Err(Box::new(InterpreterError::invalid_type(
-                        "distance", "string",
-                    ))),
-                    EvalResult::Bool(_) => Err(Box::new(InterpreterError::invalid_type(
-                        "distance", "boolean",
-                    ))),
-                    EvalResult::Float(leftward_distance) => {
-                        let _ = context.turtle.move_turtle(Some(-leftward_distance), None);
-                        Ok(())
+                    EvalResult::String(_) => {
+                        Err(InterpreterError::invalid_type("distance", "string"))
+                    }
+                    EvalResult::List(_) => Err(InterpreterError::invalid_type("distance", "list")),
+                    EvalResult::Bool(_) => {
+                        Err(InterpreterError::invalid_type("distance", "boolean"))
                     }
+                    EvalResult::Float(leftward_distance) => context.walk(-90.0, leftward_distance),
                 }
             }
             Command::Right(distance) => {
                 let value: EvalResult = distance.eval(context)?;
                 match value {
-                    EvalResult::String(_) => Err(Box::new(InterpreterError::invalid_type(
-                        "distance", "string",
-                    ))),
-                    EvalResult::Bool(_) => Err(Box::new(InterpreterError::invalid_type(
-                        "distance", "boolean",
-                    ))),
-                    EvalResult::Float(rightward_distance) => {
-                        let _ = context.turtle.move_turtle(Some(rightward_distance), None);
-                        Ok(())
+                    EvalResult::String(_) => {
+                        Err(InterpreterError::invalid_type("distance", "string"))
+                    }
+                    EvalResult::List(_) => Err(InterpreterError::invalid_type("distance", "list")),
+                    EvalResult::Bool(_) => {
+                        Err(InterpreterError::invalid_type("distance", "boolean"))
                     }
+                    EvalResult::Float(rightward_distance) => context.walk(90.0, rightward_distance),
                 }
             }
 
             // Turtle state manipulation
             Command::Turn(angle) => match angle.eval(context)? {
-                EvalResult::Bool(_) => {
-                    Err(Box::new(InterpreterError::invalid_type("angle", "bool")))
-                }
+                EvalResult::Bool(_) => Err(InterpreterError::invalid_type("angle", "bool")),
                 EvalResult::Float(val) => {
-                    context.turtle.turn(val)?;
+                    match context.view {
+                        Some(_) => context.turtle.yaw(val)?,
+                        None => context.turtle.turn(val)?,
+                    };
                     Ok(())
                 }
-                EvalResult::String(_) => {
-                    Err(Box::new(InterpreterError::invalid_type("angle", "string")))
-                }
+                EvalResult::String(_) => Err(InterpreterError::invalid_type("angle", "string")),
+                EvalResult::List(_) => Err(InterpreterError::invalid_type("angle", "list")),
             },
             Command::SetHeading(angle) => match angle.eval(context)? {
-                EvalResult::Bool(_) => {
-                    Err(Box::new(InterpreterError::invalid_type("angle", "bool")))
-                }
+                EvalResult::Bool(_) => Err(InterpreterError::invalid_type("angle", "bool")),
                 EvalResult::Float(val) => {
                     context.turtle.set_heading(val)?;
                     Ok(())
                 }
-                EvalResult::String(_) => {
-                    Err(Box::new(InterpreterError::invalid_type("angle", "string")))
-                }
+                EvalResult::String(_) => Err(InterpreterError::invalid_type("angle", "string")),
+                EvalResult::List(_) => Err(InterpreterError::invalid_type("angle", "list")),
             },
             Command::SetX(x) => match x.eval(context)? {
-                EvalResult::Bool(_) => Err(Box::new(InterpreterError::invalid_type(
-                    "coordinate",
-                    "bool",
-                ))),
+                EvalResult::Bool(_) => Err(InterpreterError::invalid_type("coordinate", "bool")),
+                EvalResult::Float(val) => context.set_position(Some(val), None),
+                EvalResult::String(_) => Err(InterpreterError::invalid_type("bool", "string")),
+                EvalResult::List(_) => Err(InterpreterError::invalid_type("bool", "list")),
+            },
+            Command::SetY(y) => match y.eval(context)? {
+                EvalResult::Bool(_) => Err(InterpreterError::invalid_type("coordinate", "bool")),
+                EvalResult::Float(val) => context.set_position(None, Some(val)),
+                EvalResult::String(_) => Err(InterpreterError::invalid_type("bool", "string")),
+                EvalResult::List(_) => Err(InterpreterError::invalid_type("bool", "list")),
+            },
+
+            // Turtle movement in 3D
+            Command::SetZ(z) => {
+                context.needs_3d("SETZ")?;
+                let z: f64 = context.eval_float(z, "coordinate")?;
+                context.turtle.set_z(z);
+                Ok(())
+            }
+            Command::Up(angle) => {
+                context.needs_3d("UP")?;
+                let angle: f64 = context.eval_float(angle, "angle")?;
+                context.turtle.pitch(angle)?;
+                Ok(())
+            }
+            Command::Down(angle) => {
+                context.needs_3d("DOWN")?;
+                let angle: f64 = context.eval_float(angle, "angle")?;
+                context
+                    .turtle
+                    .pitch(360.0 - angle)
+                    .map_err(|_| TurtleError::AngleOutOfRange(angle))?;
+                Ok(())
+            }
+            Command::RollLeft(angle) => {
+                context.needs_3d("ROLLLEFT")?;
+                let angle: f64 = context.eval_float(angle, "angle")?;
+                context
+                    .turtle
+                    .roll(360.0 - angle)
+                    .map_err(|_| TurtleError::AngleOutOfRange(angle))?;
+                Ok(())
+            }
+            Command::RollRight(angle) => {
+                context.needs_3d("ROLLRIGHT")?;
+                let angle: f64 = context.eval_float(angle, "angle")?;
+                context.turtle.roll(angle)?;
+                Ok(())
+            }
+
+            // Text
+            Command::Label(text) => {
+                let text: String = text.eval(context)?.to_string();
+                let mut label = context.turtle.label(text);
+                if let Some(view) = context.view {
+                    let position = [label.position.0, label.position.1, context.turtle.get_z()];
+                    // Text behind the camera can't be seen
+                    match view.project(position, context.canvas.dimensions()) {
+                        Some(x) => label.position = x,
+                        None => return Ok(()),
+                    }
+                }
+                context.write(label)
+            }
+            Command::SetLabelHeight(height) => match height.eval(context)? {
+                EvalResult::Bool(_) => Err(InterpreterError::invalid_type("label height", "bool")),
                 EvalResult::Float(val) => {
-                    context.turtle.set_coordinates(Some(val), None)?;
+                    context.turtle.set_label_height(val)?;
                     Ok(())
                 }
                 EvalResult::String(_) => {
-                    Err(Box::new(InterpreterError::invalid_type("bool", "string")))
+                    Err(InterpreterError::invalid_type("label height", "string"))
                 }
+                EvalResult::List(_) => Err(InterpreterError::invalid_type("label height", "list")),
             },
-            Command::SetY(y) => match y.eval(context)? {
-                EvalResult::Bool(_) => Err(Box::new(InterpreterError::invalid_type(
-                    "coordinate",
-                    "bool",
-                ))),
-                EvalResult::Float(val) => {
-                    context.turtle.set_coordinates(None, Some(val))?;
+            Command::SetFont(font) => match font.eval(context)? {
+                EvalResult::Bool(_) => Err(InterpreterError::invalid_type("font", "bool")),
+                EvalResult::Float(_) => Err(InterpreterError::invalid_type("font", "float")),
+                EvalResult::List(_) => Err(InterpreterError::invalid_type("font", "list")),
+                EvalResult::String(val) => {
+                    context.turtle.set_font(val);
                     Ok(())
                 }
-                EvalResult::String(_) => {
-                    Err(Box::new(InterpreterError::invalid_type("bool", "string")))
-                }
             },
 
+            // Instructions as data
+            Command::Run(list) => {
+                let commands: Vec<Command> = context.parse_list(list)?;
+                context.nested(|context: &mut Program| context.run_block(&commands, 0, &[]))
+            }
+            Command::Apply(name, arguments) => {
+                let name: String = match name.eval(context)? {
+                    EvalResult::String(x) => x,
+                    other => {
+                        return Err(InterpreterError::invalid_type(
+                            "procedure name",
+                            other.type_name(),
+                        ))
+                    }
+                };
+                let arguments: Vec<Expression> = context
+                    .eval_list(arguments, "arguments")?
+                    .into_iter()
+                    .map(Expression::Value)
+                    .collect();
+                match context.primitives.get(&name) {
+                    Some(_) => context.call_primitive(&name, &arguments).map(|_| ()),
+                    None => Command::ProcedureExec(name, arguments).execute(context),
+                }
+            }
+
+            // Output
+            Command::Print(value) => {
+                let text: String = value.eval(context)?.printed();
+                context.print(&format!("{text}\n"))
+            }
+            Command::Show(value) => {
+                let text: String = value.eval(context)?.to_string();
+                context.print(&format!("{text}\n"))
+            }
+            Command::Type(value) => {
+                let text: String = value.eval(context)?.printed();
+                context.print(&text)
+            }
+
+            // Checkpoints
+            Command::SaveState(path) => {
+                let path: String = context.eval_path(path)?;
+                context.state().save(Path::new(&path))?;
+                Ok(())
+            }
+            Command::LoadState(path) => {
+                let path: String = context.eval_path(path)?;
+                let state: State = State::load(Path::new(&path))?;
+                context.restore(state)
+            }
+
             // Variable manipulation
             Command::MakeVariable(name, value) => {
                 let name = match name.eval(context)? {
                     EvalResult::Bool(_) => {
-                        return Err(Box::new(InterpreterError::invalid_type(
-                            "variable name",
-                            "bool",
-                        )))
+                        return Err(InterpreterError::invalid_type("variable name", "bool"))
                     }
                     EvalResult::Float(_) => {
-                        return Err(Box::new(InterpreterError::invalid_type(
-                            "variable name",
-                            "float",
-                        )))
+                        return Err(InterpreterError::invalid_type("variable name", "float"))
                     }
                     EvalResult::String(x) => x,
+                    EvalResult::List(_) => {
+                        return Err(InterpreterError::invalid_type("variable name", "list"))
+                    }
                 };
-                context
-                    .variables
-                    .insert(name.to_owned(), value.eval(context)?);
-                Ok(())
+                let value: EvalResult = value.eval(context)?;
+                context.set_var(name, value)
             }
             Command::Increment(name, value) => {
                 let name: String = match name.eval(context)? {
                     EvalResult::Bool(_) => {
-                        return Err(Box::new(InterpreterError::invalid_type(
-                            "variable name",
-                            "bool",
-                        )))
+                        return Err(InterpreterError::invalid_type("variable name", "bool"))
                     }
                     EvalResult::Float(_) => {
-                        return Err(Box::new(InterpreterError::invalid_type(
-                            "variable name",
-                            "float",
-                        )))
+                        return Err(InterpreterError::invalid_type("variable name", "float"))
                     }
                     EvalResult::String(x) => x,
+                    EvalResult::List(_) => {
+                        return Err(InterpreterError::invalid_type("variable name", "list"))
+                    }
                 };
                 match context.variables.contains_key(&name) {
                     true => {
-                        let incremented = match context.variables.get(&name) {
+                        let incremented = match context.variables.get(&name).cloned() {
                             Some(res) => match res {
                                 EvalResult::Bool(_) => {
-                                    return Err(Box::new(InterpreterError::invalid_type(
+                                    return Err(InterpreterError::invalid_type(
                                         "increment target",
                                         "boolean",
-                                    )))
+                                    ))
                                 }
                                 EvalResult::Float(res) => {
-                                    let increment_value: f32 = match value.eval(context)? {
+                                    let increment_value: f64 = match value.eval(context)? {
                                         EvalResult::Bool(_) => {
-                                            return Err(Box::new(InterpreterError::invalid_type(
+                                            return Err(InterpreterError::invalid_type(
                                                 "incrementing a float",
                                                 "boolean",
-                                            )))
+                                            ))
                                         }
                                         EvalResult::Float(val) => val,
                                         EvalResult::String(_) => {
-                                            return Err(Box::new(InterpreterError::invalid_type(
+                                            return Err(InterpreterError::invalid_type(
                                                 "incrementint a float",
                                                 "string",
-                                            )))
+                                            ))
+                                        }
+                                        EvalResult::List(_) => {
+                                            return Err(InterpreterError::invalid_type(
+                                                "incrementint a float",
+                                                "list",
+                                            ))
                                         }
                                     };
 
                                     EvalResult::Float(res + increment_value)
                                 }
                                 EvalResult::String(_) => {
-                                    return Err(Box::new(InterpreterError::invalid_type(
+                                    return Err(InterpreterError::invalid_type(
                                         "increment target",
                                         "string",
-                                    )))
+                                    ))
+                                }
+                                EvalResult::List(_) => {
+                                    return Err(InterpreterError::invalid_type(
+                                        "increment target",
+                                        "list",
+                                    ))
                                 }
                             },
                             None => {
-                                return Err(Box::new(InterpreterError::unsuccessful_operation(
+                                return Err(InterpreterError::unsuccessful_operation(
                                     "fetching value to increment",
-                                )))
+                                ))
                             }
                         };
-                        context.variables.insert(name, incremented);
-                        Ok(())
+                        context.set_var(name, incremented)
                     }
-                    false => Err(Box::new(InterpreterError::undefined_var(name.as_str()))),
+                    false => Err(InterpreterError::undefined_var(name.as_str())),
                 }
             }
+            Command::PutProperty(list, property, value) => {
+                let list: String = context.eval_name(list, "property list name")?;
+                let property: String = context.eval_name(property, "property name")?;
+                let value: EvalResult = value.eval(context)?;
+                context.put_property(list, property, value)
+            }
+            Command::RemoveProperty(list, property) => {
+                let list: String = context.eval_name(list, "property list name")?;
+                let property: String = context.eval_name(property, "property name")?;
+                context.remove_property(&list, &property)
+            }
+            Command::Hoist(number, value) => {
+                let value: EvalResult = value.eval(context)?;
+                context.hoisted.insert(*number, value);
+                Ok(())
+            }
 
             // Control flow
             Command::If(expression, commands) => match expression.eval(context)? {
                 EvalResult::Bool(condition) => {
                     if condition {
-                        // Iteratively execute each command and filter for errors
-                        let errors: Vec<Box<dyn Error>> = commands
-                            .iter()
-                            .map(|x: &Command| -> Result<(), Box<dyn Error>> { x.execute(context) })
-                            .filter(|x: &Result<(), Box<dyn Error>>| x.is_err())
-                            .map(|x: Result<(), Box<dyn Error>>| -> Box<dyn Error> {
-                                x.expect_err("We filtered for errors")
-                            })
-                            .collect();
-
-                        // If there are errors, we return an error
-                        match errors.is_empty() {
-                            false => Err(Box::new(InterpreterError::unsuccessful_operation(
-                                "conditional statement",
-                            ))),
-
-                            // If there are no errors, we're all good
-                            true => Ok(()),
-                        }
+                        context.run_block(commands, 0, &[])
                     } else {
                         Ok(())
                     }
                 }
 
                 // Invalid types
-                EvalResult::Float(_) => Err(Box::new(InterpreterError::invalid_type(
-                    "condition",
-                    "float",
-                ))),
-                EvalResult::String(_) => Err(Box::new(InterpreterError::invalid_type(
-                    "condition",
-                    "string",
-                ))),
+                EvalResult::Float(_) => Err(InterpreterError::invalid_type("condition", "float")),
+                EvalResult::String(_) => Err(InterpreterError::invalid_type("condition", "string")),
+                EvalResult::List(_) => Err(InterpreterError::invalid_type("condition", "list")),
             },
             Command::While(expression, commands) => match expression.eval(context)? {
                 EvalResult::Bool(condition) => {
                     let mut mutable_condition: bool = condition;
                     while mutable_condition {
-                        // Update the mutable condition at the start of the loop
+                        // An empty loop never executes a command, so count each iteration too
+                        context.budget.step(&context.limits)?;
+
+                        context.run_block(commands, 0, &[])?;
+
+                        // Update the mutable condition before the next iteration
                         mutable_condition = match expression.eval(context)? {
                             EvalResult::Bool(val) => val,
 
                             // Invalid types
                             EvalResult::Float(_) => {
-                                return Err(Box::new(InterpreterError::invalid_type(
-                                    "condition",
-                                    "float",
-                                )))
+                                return Err(InterpreterError::invalid_type("condition", "float"))
                             }
                             EvalResult::String(_) => {
-                                return Err(Box::new(InterpreterError::invalid_type(
-                                    "condition",
-                                    "string",
-                                )))
+                                return Err(InterpreterError::invalid_type("condition", "string"))
                             }
-                        };
-
-                        // Iteratively execute each command and filter for errors
-                        let errors: Vec<Result<(), Box<dyn Error>>> = commands
-                            .iter()
-                            .map(|x: &Command| -> Result<(), Box<dyn Error>> { x.execute(context) })
-                            .filter(|x: &Result<(), Box<dyn Error>>| x.is_err())
-                            .collect();
-
-                        // If there are errors, we return an error
-                        match errors.is_empty() {
-                            false => {
-                                return Err(Box::new(InterpreterError::unsuccessful_operation(
-                                    "conditional statement",
-                                )));
+                            EvalResult::List(_) => {
+                                return Err(InterpreterError::invalid_type("condition", "list"))
                             }
-
-                            // If there are no errors, we're all good
-                            true => (),
-                        }
+                        };
                     }
                     Ok(())
                 }
-                EvalResult::Float(_) => Err(Box::new(InterpreterError::invalid_type(
-                    "condition",
-                    "float",
-                ))),
-                EvalResult::String(_) => Err(Box::new(InterpreterError::invalid_type(
-                    "condition",
-                    "string",
-                ))),
+                EvalResult::Float(_) => Err(InterpreterError::invalid_type("condition", "float")),
+                EvalResult::String(_) => Err(InterpreterError::invalid_type("condition", "string")),
+                EvalResult::List(_) => Err(InterpreterError::invalid_type("condition", "list")),
             },
             Command::ProcedureDefine(name, parameters, commands) => {
                 let procedure_name: String = match name.eval(context)? {
                     EvalResult::Bool(_) => {
-                        return Err(Box::new(InterpreterError::invalid_type(
-                            "procedure name",
-                            "boolean",
-                        )))
+                        return Err(InterpreterError::invalid_type("procedure name", "boolean"))
                     }
                     EvalResult::Float(_) => {
-                        return Err(Box::new(InterpreterError::invalid_type(
-                            "procedure name",
-                            "float",
-                        )))
+                        return Err(InterpreterError::invalid_type("procedure name", "float"))
                     }
                     EvalResult::String(val) => val,
+                    EvalResult::List(_) => {
+                        return Err(InterpreterError::invalid_type("procedure name", "list"))
+                    }
                 };
                 let procedure: Command =
                     Command::ProcedureBase(parameters.to_vec(), commands.to_vec());
                 context.add_procedure(procedure_name, procedure)?;
                 Ok(())
             }
-            Command::ProcedureBase(_, _) => Err(Box::new(InterpreterError::unsupported_operation(
+            Command::ProcedureBase(_, _) => Err(InterpreterError::unsupported_operation(
                 "executing a base procedure without defining parameter values",
-            ))),
+            )),
+            Command::Load(_, _) => Err(InterpreterError::unsupported_operation(
+                "loading a file anywhere but the top level of a program",
+            )),
+            Command::Primitive(name, arguments) => {
+                context.call_primitive(name, arguments)?;
+                Ok(())
+            }
+            Command::Located(_, _) => unreachable!("located commands are run above"),
             Command::ProcedureExec(name, parameter_values) => {
                 let (parameter_names, commands) = match context.get_procedure(name)? {
                     Command::ProcedureBase(parameter_names, commands) => {
                         // Apparently, to_owned is necessary, as a shared borrow of Vec<Command> means that the .map() in the commands execution causes the borrow checker to freak out?
                         // I think it was because returning an immutable reference for the vector of commands means that the mutable reference to context (in the above `match context.get_procedure()`
                         // invocation) never gets dropped? Not sure why, though.
-                        (parameter_names, commands.to_owned())
+                        (parameter_names.to_owned(), commands.to_owned())
                     }
                     _ => {
-                        return Err(Box::new(InterpreterError::invalid_type(
+                        return Err(InterpreterError::invalid_type(
                             "procedure",
                             "not ProcedureBase",
-                        )));
+                        ));
                     }
                 };
 
-                let (evaluated_name, name_evaluation_errors) = parameter_names
-                    .par_iter()
-                    // Evaluate each of the expressions in the vector
-                    .map(|x| x.eval(context))
-                    // Parameter names can only be a string, so map floats and booleans to an error
-                    .map(|y: Result<EvalResult, InterpreterError>| -> Result<String, InterpreterError> {
-                        match y {
-                            Ok(val) => match val {
-                                EvalResult::Bool(_) => Err(InterpreterError::invalid_type(
-                                    "procedure parameter name",
-                                    "boolean",
-                                )),
-                                EvalResult::Float(_) => Err(InterpreterError::invalid_type("procedure parameter name", "float")),
-                                EvalResult::String(res) => Ok(res),
-                            },
-                            Err(e) => Err(e),
-                        }})
-                    .fold(
-                        || (Vec::new(), Vec::new()), // Closure that returns a new accumulator for each thread
-                        |mut acc, result| {
-                            // The accumulator and the current item from the iteration
-                            match result {
-                                Ok(ok) => acc.0.push(ok), // If the result is Ok, push it into the first vector
-                                Err(err) => acc.1.push(err), // If the result is Err, push it into the second vector
-                            };
-                            acc // Return the updated accumulator for the next iteration or combination
-                        },
-                    )
-                    .reduce(
-                        || (Vec::new(), Vec::new()), // Identity value for the combination process
-                        |mut acc1, acc2| {
-                            // acc1 is the accumulator being built, acc2 is from another thread
-                            acc1.0.extend(acc2.0); // Extend the first vector of acc1 with the first vector of acc2
-                            acc1.1.extend(acc2.1); // Extend the second vector of acc1 with the second vector of acc2
-                            acc1 // Return the combined accumulator
-                        },
-                    );
-
-                if !name_evaluation_errors.is_empty() {
-                    return Err(Box::new(
-                        name_evaluation_errors
-                            .first()
-                            .expect(
-                                "we checked that the `name_evaluation_errors` vector is not empty",
-                            )
-                            .clone(),
-                    ));
-                }
-
-                let (evaluated_params, params_evaluation_errors) = parameter_values
-                    .par_iter()
-                    // Evaluate each of the expressions in the vector
-                    .map(|x| x.eval(context))
-                    // Separate the result into two vectors, depending on whether or not they were successful
-                    .fold(
-                        || (Vec::new(), Vec::new()), // Closure that returns a new accumulator for each thread
-                        |mut acc, result| {
-                            // The accumulator and the current item from the iteration
-                            match result {
-                                Ok(ok) => acc.0.push(ok), // If the result is Ok, push it into the first vector
-                                Err(err) => acc.1.push(err), // If the result is Err, push it into the second vector
-                            };
-                            acc // Return the updated accumulator for the next iteration or combination
-                        },
-                    )
-                    // Join tgether the results of the different threads
-                    .reduce(
-                        || (Vec::new(), Vec::new()), // Identity value for the combination process
-                        |mut acc1, acc2| {
-                            // acc1 is the accumulator being built, acc2 is from another thread
-                            acc1.0.extend(acc2.0); // Extend the first vector of acc1 with the first vector of acc2
-                            acc1.1.extend(acc2.1); // Extend the second vector of acc1 with the second vector of acc2
-                            acc1 // Return the combined accumulator
-                        },
-                    );
-
-                // Check if there were any issues when evaluating the parameter values. If so, terminate early.
-                if !params_evaluation_errors.is_empty() {
-                    return Err(Box::new(
-                        params_evaluation_errors
-                            .first()
-                            .expect(
-                                "we checked that the `params_evaluation_errors` vector is not empty",
-                            )
-                            .clone(),
-                    ));
-                }
-
-                // You can use rayon's parallel iterators here, but it's going to involve mutexes and whatnot
-                // We don't really care about the successful return values of the insertion, only the errors
-                let insertion_errors: Vec<InterpreterError> = evaluated_name
+                // Parameter names can only be a string, so map floats and booleans to an error
+                let evaluated_name: Vec<String> = parameter_names
                     .iter()
-                    .zip(evaluated_params)
-                    // Insert the key-value pairs and collect the results
-                    .map(|(key, val): (&String, EvalResult)| {
-                        context.insert_var(key.to_string(), val)
-                    })
-                    // Filter for only errors, as the successful operations do not matter
-                    .filter(|x: &Result<(), InterpreterError>| x.is_err())
-                    // Extract the actual error from the result type
-                    .map(|y: Result<(), InterpreterError>| {
-                        y.expect_err("we have previously filtered for errors")
+                    .map(|x: &Expression| match x.eval(context)? {
+                        EvalResult::Bool(_) => Err(InterpreterError::invalid_type(
+                            "procedure parameter name",
+                            "boolean",
+                        )),
+                        EvalResult::Float(_) => Err(InterpreterError::invalid_type(
+                            "procedure parameter name",
+                            "float",
+                        )),
+                        EvalResult::String(res) => Ok(res),
+                        EvalResult::List(_) => Err(InterpreterError::invalid_type(
+                            "procedure parameter name",
+                            "list",
+                        )),
                     })
-                    .collect();
-
-                // Check if there was any issues inserting the variables. If so, terminate early.
-                if !insertion_errors.is_empty() {
-                    return Err(Box::new(
-                        insertion_errors
-                            .first()
-                            .expect("we checked that the `insertion_errors` vector is not empty")
-                            .clone(),
-                    ));
-                }
+                    .collect::<Result<Vec<String>, InterpreterError>>()?;
 
-                // Once we have the stage set up (i.e. the variables inserted), we can iterate through the vector of commands
-                let execution_errors: Vec<Box<dyn Error>> = commands
+                // Evaluating a parameter can run a primitive, which may change the program, so
+                // they are evaluated in order. Terminate early if any of them fail.
+                let evaluated_params: Vec<EvalResult> = parameter_values
                     .iter()
-                    .map(|x| x.execute(context))
-                    .filter(|y| y.is_err())
-                    .map(|z| z.expect_err("we have already previously filtered for errors"))
-                    .collect();
+                    .map(|x: &Expression| x.eval(context))
+                    .collect::<Result<Vec<EvalResult>, InterpreterError>>()?;
 
-                // Check if there were any errors during execution
-                if !execution_errors.is_empty() {
-                    // For some reason, this is the only way I could find to get the owned value for the first Box<dyn Error>
-                    let error = execution_errors
-                        .into_iter()
-                        .next()
-                        .expect("we have checked that the `execution_errors` vector is not empty");
-                    return Err(error);
-                }
+                // Parameters are ordinary variables, created by the call if they don't exist yet.
+                // Terminate early if any of them don't fit in the memory limit.
+                evaluated_name
+                    .into_iter()
+                    .zip(evaluated_params)
+                    .try_for_each(|(key, val): (String, EvalResult)| context.set_var(key, val))?;
 
-                // If it gets this far, then the execution should have succeeded
-                Ok(())
+                // Once we have the stage set up (i.e. the variables inserted), we can iterate through the vector of commands
+                context.call_procedure(name, &commands, 0, &[])
             }
         }
     }
 }
 
+/// A parsed Logo program, ready to be run by an `Interpreter`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ast {
+    commands: Vec<Command>,
+}
+
+impl Ast {
+    /// The top-level commands of the program, in the order they run
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// A 64-bit FNV-1a hash of the program's commands. Locations are left out, so moving
+    /// commands between lines or changing comments doesn't change it.
+    pub fn fingerprint(&self) -> u64 {
+        let json: String = serde_json::to_string(&without_locations(self.commands.clone()))
+            .expect("commands should always serialise");
+        json.bytes()
+            .fold(0xcbf29ce484222325, |hash: u64, byte: u8| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+}
+
+impl From<Vec<Command>> for Ast {
+    fn from(commands: Vec<Command>) -> Self {
+        Ast { commands }
+    }
+}
+
 /// The parsed logo program.
 pub struct Program {
     /// List of commands contained in the program. This will be iterated through and executed.
@@ -771,22 +1184,795 @@ pub struct Program {
     /// List of variables defined in the program.
     variables: HashMap<String, EvalResult>,
 
+    /// The property lists set by `PPROP`, each with its properties in the order they were first
+    /// set. Lists without any properties are removed.
+    properties: HashMap<String, Vec<(String, EvalResult)>>,
+
     /// The turtle itself
     turtle: Turtle,
 
     /// A list of known procedures
     procedures: HashMap<String, Command>,
-}
 
-impl Program {
-    /// Create a new program, with an empty `commands` vector and `variables` hash map.
-    pub fn new(commands: Vec<Command>) -> Self {
-        Program {
-            commands,
-            variables: HashMap::new(),
-            turtle: Turtle::new(),
-            procedures: HashMap::new(),
-        }
+    /// Everything the turtle has drawn
+    canvas: Canvas,
+
+    /// Commands and reporters provided by the host
+    primitives: Primitives,
+
+    /// Limits on the resources the program may use
+    limits: Limits,
+
+    /// How much of each limit has been used
+    budget: Budget,
+
+    /// Watches each command as it runs, such as a tracer or debugger
+    observer: Option<Box<dyn Observer>>,
+
+    /// The procedure calls and loops the program is currently inside of, outermost first
+    call_stack: Vec<Frame>,
+
+    /// What happens when the turtle reaches the edge of the canvas
+    boundary: Boundary,
+
+    /// Where `PRINT`, `SHOW` and `TYPE` write to
+    output: Box<dyn Write + Send>,
+
+    /// Where `READWORD` and `READLIST` read from
+    input: Box<dyn BufRead + Send>,
+
+    /// How the turtle is drawn when it moves in 3D, or `None` if it moves on the canvas
+    view: Option<View>,
+
+    /// The index of the next command to run at the top level
+    position: usize,
+
+    /// The index of the command running in each block the program is inside of, starting with
+    /// the top level, so that a state saved anywhere can be resumed from the same place
+    path: Vec<usize>,
+
+    /// A fingerprint of the program being run, saved with its state so that the state is only
+    /// resumed with the same program
+    fingerprint: Option<u64>,
+
+    /// How far apart two numbers may be for `EQ` to say they are equal
+    tolerance: f64,
+
+    /// The values the optimiser hoisted out of loops, by their number
+    hoisted: HashMap<usize, EvalResult>,
+}
+
+/// A procedure call or loop which a program is inside of
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Frame {
+    /// A call to the named procedure
+    Procedure(String),
+
+    /// A `WHILE` loop, written at the given location
+    Loop(Location),
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Frame::Procedure(name) => write!(f, "{name}"),
+            Frame::Loop(location) => {
+                write!(f, "WHILE ({}:{})", location.file_name(), location.line)
+            }
+        }
+    }
+}
+
+impl Program {
+    /// Create a new program, with an empty `commands` vector and `variables` hash map.
+    pub fn new(commands: Vec<Command>) -> Self {
+        Program {
+            commands,
+            variables: HashMap::new(),
+            properties: HashMap::new(),
+            turtle: Turtle::new(),
+            procedures: HashMap::new(),
+            canvas: Canvas::new(0, 0),
+            primitives: Primitives::new(),
+            limits: Limits::default(),
+            budget: Budget::new(),
+            observer: None,
+            call_stack: Vec::new(),
+            boundary: Boundary::default(),
+            output: Box::new(std::io::stdout()),
+            input: Box::new(BufReader::new(std::io::stdin())),
+            position: 0,
+            path: Vec::new(),
+            fingerprint: None,
+            view: None,
+            tolerance: 0.0,
+            hoisted: HashMap::new(),
+        }
+    }
+
+    /// Create a new program which draws onto the given canvas, with the turtle starting in the
+    /// middle of it.
+    pub fn with_canvas(commands: Vec<Command>, canvas: Canvas) -> Self {
+        let (width, height) = canvas.dimensions();
+        let mut turtle: Turtle = Turtle::new();
+        let _ = turtle.set_coordinates(Some(width as f64 / 2.0), Some(height as f64 / 2.0));
+
+        Program {
+            turtle,
+            canvas,
+            ..Program::new(commands)
+        }
+    }
+
+    /// Move the turtle along a line, keeping to the boundary. If the turtle is fenced in and the
+    /// line leaves the canvas, the turtle stays where it was.
+    fn draw(&mut self, line: Line) -> Result<(), InterpreterError> {
+        if self.canvas.contains(line.end) {
+            return self.paint(line);
+        }
+        match self.boundary {
+            Boundary::Window => self.paint(line),
+            Boundary::Fence => {
+                self.turtle
+                    .set_coordinates(Some(line.start.0), Some(line.start.1))?;
+                Err(TurtleError::InvalidCoordinates(line.end.0, line.end.1).into())
+            }
+            Boundary::Wrap => {
+                let lines: Vec<Line> = self.canvas.wrap_line(&line);
+                let (x, y) = lines.last().map_or(line.start, |x: &Line| x.end);
+                self.turtle.set_coordinates(Some(x), Some(y))?;
+                lines.into_iter().try_for_each(|x: Line| self.paint(x))
+            }
+        }
+    }
+
+    /// Move the turtle to the given coordinates, keeping to the boundary. Coordinates which
+    /// aren't given are left as they are.
+    fn set_position(&mut self, x: Option<f64>, y: Option<f64>) -> Result<(), InterpreterError> {
+        let (old_x, old_y) = self.turtle.get_turtle_coords();
+        let position: (f64, f64) = (x.unwrap_or(old_x), y.unwrap_or(old_y));
+        let (x, y) = match self.boundary {
+            Boundary::Window => position,
+            Boundary::Wrap => self.canvas.wrap_point(position),
+            Boundary::Fence if self.canvas.contains(position) => position,
+            Boundary::Fence => {
+                return Err(TurtleError::InvalidCoordinates(position.0, position.1).into())
+            }
+        };
+        self.turtle.set_coordinates(Some(x), Some(y))?;
+        Ok(())
+    }
+
+    /// Move the turtle the given distance, in a direction relative to its heading, drawing a line
+    /// if its pen is down
+    fn walk(&mut self, angle: f64, distance: f64) -> Result<(), InterpreterError> {
+        match self.view {
+            Some(view) => {
+                let segment: Segment = self.turtle.walk_3d(angle, distance);
+                self.draw_3d(view, segment)
+            }
+            None => {
+                let line: Line = self.turtle.walk(angle, distance);
+                self.draw(line)
+            }
+        }
+    }
+
+    /// Draw a segment the turtle travelled along in 3D, if its pen is down, along with where it
+    /// appears on the canvas. Segments behind the camera are kept, but don't appear.
+    fn draw_3d(&mut self, view: View, segment: Segment) -> Result<(), InterpreterError> {
+        if *self.turtle.get_pen_state() == PenState::Down {
+            self.check_segment_limit()?;
+            if let Some(line) = view.project_segment(&segment, self.canvas.dimensions()) {
+                self.canvas.draw(line);
+            }
+            self.canvas.draw_3d(segment);
+        }
+        Ok(())
+    }
+
+    /// Let the turtle move in 3D, drawn onto the canvas with the given view, or only on the
+    /// canvas if the view is `None`
+    pub fn set_view(&mut self, view: Option<View>) {
+        self.view = view;
+    }
+
+    /// Commands which only make sense in 3D are errors when the turtle moves on the canvas
+    fn needs_3d(&self, name: &str) -> Result<(), InterpreterError> {
+        match self.view {
+            Some(_) => Ok(()),
+            None => Err(InterpreterError::Needs3d(name.to_string())),
+        }
+    }
+
+    /// Change what happens when the turtle reaches the edge of the canvas. The turtle is moved
+    /// back onto the canvas when wrapping, and can't be fenced in while it is off the canvas.
+    /// The edges of the canvas don't stop a turtle moving in 3D, so it can only be windowed.
+    pub fn set_boundary(&mut self, boundary: Boundary) -> Result<(), InterpreterError> {
+        if self.view.is_some() && boundary != Boundary::Window {
+            return Err(InterpreterError::unsupported_operation(
+                "wrapping or fencing in a turtle which moves in 3D",
+            ));
+        }
+        let previous: Boundary = std::mem::replace(&mut self.boundary, boundary);
+        self.set_position(None, None).inspect_err(|_| {
+            self.boundary = previous;
+        })
+    }
+
+    pub fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    /// Draw a line the turtle travelled along, if its pen is down
+    fn paint(&mut self, line: Line) -> Result<(), InterpreterError> {
+        if *self.turtle.get_pen_state() == PenState::Down {
+            self.check_segment_limit()?;
+            self.canvas.draw(line);
+        }
+        Ok(())
+    }
+
+    /// Write a label onto the canvas. Labels are written whether or not the pen is down, and count
+    /// towards the same limit as lines.
+    fn write(&mut self, label: Label) -> Result<(), InterpreterError> {
+        self.check_segment_limit()?;
+        self.canvas.write(label);
+        Ok(())
+    }
+
+    /// Stop the program if drawing anything more would go over the limit on lines and labels. In
+    /// 3D, every segment counts, including those behind the camera which have no line.
+    fn check_segment_limit(&self) -> Result<(), InterpreterError> {
+        let drawn: usize = match self.view {
+            Some(_) => self.canvas.segments().len(),
+            None => self.canvas.lines().len(),
+        };
+        match self.limits.max_segments {
+            Some(max) if drawn + self.canvas.labels().len() >= max => {
+                Err(InterpreterError::SegmentLimitExceeded(max))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Write text to the output. The output is flushed straight away, so that a prompt written by
+    /// `TYPE` is seen before the program waits for input.
+    fn print(&mut self, text: &str) -> Result<(), InterpreterError> {
+        self.output
+            .write_all(text.as_bytes())
+            .and_then(|_| self.output.flush())
+            .map_err(|e| InterpreterError::Io(e.to_string()))
+    }
+
+    /// Read a line of input, without the line ending. Returns `None` once the input has run out.
+    fn read_line(&mut self) -> Result<Option<String>, InterpreterError> {
+        let mut line: String = String::new();
+        let read: usize = self
+            .input
+            .read_line(&mut line)
+            .map_err(|e| InterpreterError::Io(e.to_string()))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let length: usize = line.trim_end_matches(['\r', '\n']).len();
+        line.truncate(length);
+        Ok(Some(line))
+    }
+
+    /// Evaluate an expression which must be a list, such as the list given to `MAP`
+    fn eval_list(
+        &mut self,
+        list: &Expression,
+        field: &str,
+    ) -> Result<Vec<EvalResult>, InterpreterError> {
+        match list.eval(self)? {
+            EvalResult::List(items) => Ok(items),
+            other => Err(InterpreterError::invalid_type(field, other.type_name())),
+        }
+    }
+
+    /// Fill in a template with the given values, then evaluate it. `?` and `?1` stand for the
+    /// first value, `?2` for the second, and so on.
+    fn fill_template(
+        &mut self,
+        template: &EvalResult,
+        values: &[EvalResult],
+    ) -> Result<EvalResult, InterpreterError> {
+        let EvalResult::List(words) = template else {
+            return Err(InterpreterError::invalid_type(
+                "template",
+                template.type_name(),
+            ));
+        };
+        let source: String = words
+            .iter()
+            .map(|word: &EvalResult| {
+                let slot: Option<&EvalResult> = match word {
+                    EvalResult::String(x) if x == "?" => values.first(),
+                    EvalResult::String(x) => x
+                        .strip_prefix('?')
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .and_then(|n| values.get(n.checked_sub(1)?)),
+                    _ => None,
+                };
+                slot.map_or_else(|| word.to_string(), EvalResult::literal)
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        let expression: Expression = parse_template(&source, &self.primitives)
+            .map_err(|e| InterpreterError::InvalidList(source, e))?;
+        self.nested(|context: &mut Program| expression.eval(context))
+    }
+
+    /// Run `f` one call deeper, stopping if that goes past the depth limit
+    fn nested<T>(
+        &mut self,
+        f: impl FnOnce(&mut Program) -> Result<T, InterpreterError>,
+    ) -> Result<T, InterpreterError> {
+        self.budget.enter(&self.limits)?;
+        let result: Result<T, InterpreterError> = f(self);
+        self.budget.leave();
+        result
+    }
+
+    /// Write the output of `PRINT`, `SHOW` and `TYPE` somewhere other than standard output
+    pub fn set_output(&mut self, output: impl Write + Send + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Read the input of `READWORD` and `READLIST` from somewhere other than standard input
+    pub fn set_input(&mut self, input: impl BufRead + Send + 'static) {
+        self.input = Box::new(input);
+    }
+
+    /// Set the value of a variable, making sure it fits in the memory limit
+    fn set_var(&mut self, name: String, value: EvalResult) -> Result<(), InterpreterError> {
+        let old: usize = self
+            .variables
+            .get(&name)
+            .map_or(0, |x| variable_size(&name, x));
+        self.budget
+            .reallocate(&self.limits, old, variable_size(&name, &value))?;
+        self.variables.insert(name, value);
+        Ok(())
+    }
+
+    /// The value of a property in a property list
+    fn get_property(&self, list: &str, property: &str) -> Result<EvalResult, InterpreterError> {
+        self.properties
+            .get(list)
+            .and_then(|x| x.iter().find(|(name, _)| name == property))
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| InterpreterError::UndefinedProperty(list.into(), property.into()))
+    }
+
+    /// Set a property in a property list, making sure it fits in the memory limit
+    fn put_property(
+        &mut self,
+        list: String,
+        property: String,
+        value: EvalResult,
+    ) -> Result<(), InterpreterError> {
+        let old: usize = self
+            .get_property(&list, &property)
+            .map_or(0, |x| property_size(&list, &property, &x));
+        self.budget
+            .reallocate(&self.limits, old, property_size(&list, &property, &value))?;
+        let properties = self.properties.entry(list).or_default();
+        match properties.iter_mut().find(|(name, _)| *name == property) {
+            Some((_, old)) => *old = value,
+            None => properties.push((property, value)),
+        }
+        Ok(())
+    }
+
+    fn remove_property(&mut self, list: &str, property: &str) -> Result<(), InterpreterError> {
+        let Some(properties) = self.properties.get_mut(list) else {
+            return Ok(());
+        };
+        if let Some(i) = properties.iter().position(|(name, _)| name == property) {
+            let (_, value) = properties.remove(i);
+            if properties.is_empty() {
+                self.properties.remove(list);
+            }
+            self.budget
+                .reallocate(&self.limits, property_size(list, property, &value), 0)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_observer(&mut self, observer: Option<Box<dyn Observer>>) {
+        self.observer = observer;
+    }
+
+    /// Tell the observer a command is about to run. The observer is taken out of the program
+    /// while it runs, so that it can look at the rest of the program.
+    fn notify_before(
+        &mut self,
+        location: &Location,
+        command: &Command,
+    ) -> Result<(), InterpreterError> {
+        match self.observer.take() {
+            Some(mut observer) => {
+                let result = observer.before(self, location, command);
+                self.observer = Some(observer);
+                result
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Tell the observer a command has finished running
+    fn notify_after(
+        &mut self,
+        location: &Location,
+        command: &Command,
+        result: &Result<(), InterpreterError>,
+    ) {
+        if let Some(mut observer) = self.observer.take() {
+            observer.after(self, location, command, result);
+            self.observer = Some(observer);
+        }
+    }
+
+    /// Enter a procedure call or loop, telling the observer about it
+    fn push_frame(&mut self, frame: Frame) {
+        self.call_stack.push(frame);
+        if let Some(mut observer) = self.observer.take() {
+            observer.enter(
+                self,
+                self.call_stack.last().expect("a frame was just pushed"),
+            );
+            self.observer = Some(observer);
+        }
+    }
+
+    /// Leave the procedure call or loop entered most recently, telling the observer about it
+    fn pop_frame(&mut self) {
+        if let Some(mut observer) = self.observer.take() {
+            if let Some(frame) = self.call_stack.last() {
+                observer.leave(self, frame);
+            }
+            self.observer = Some(observer);
+        }
+        self.call_stack.pop();
+    }
+
+    /// How many procedure calls deep the program currently is, where `0` is the top level
+    pub fn depth(&self) -> usize {
+        self.call_stack
+            .iter()
+            .filter(|x| matches!(x, Frame::Procedure(_)))
+            .count()
+    }
+
+    /// The procedure calls and loops the program is currently inside of, outermost first
+    pub fn call_stack(&self) -> &[Frame] {
+        &self.call_stack
+    }
+
+    /// Every variable currently defined
+    pub fn variables(&self) -> &HashMap<String, EvalResult> {
+        &self.variables
+    }
+
+    /// Every property list with any properties, by its name
+    pub fn properties(&self) -> &HashMap<String, Vec<(String, EvalResult)>> {
+        &self.properties
+    }
+
+    /// Everything needed to carry on running the program from the command after the one running
+    /// now, even if that is inside a block or procedure
+    pub fn state(&self) -> State {
+        let (next, nested): (usize, Vec<usize>) = match self.path.split_first() {
+            Some((top, inner)) => {
+                let mut nested: Vec<usize> = inner.to_vec();
+                if let Some(last) = nested.last_mut() {
+                    *last += 1;
+                }
+                (top + 1, nested)
+            }
+            None => (self.position, Vec::new()),
+        };
+        State {
+            turtle: self.turtle.clone(),
+            variables: self.variables.clone(),
+            properties: self.properties.clone(),
+            procedures: self.procedures.clone(),
+            canvas: self.canvas.clone(),
+            boundary: self.boundary,
+            view: self.view,
+            tolerance: self.tolerance,
+            hoisted: self.hoisted.clone(),
+            program: self.fingerprint,
+            next,
+            nested,
+        }
+    }
+
+    /// Replace the turtle, variables, procedures, drawing and settings with those in a saved
+    /// state. The restored variables and property lists must fit in the memory limit. Which
+    /// command runs next is left to the caller.
+    pub fn restore(&mut self, state: State) -> Result<(), InterpreterError> {
+        let size = |variables: &HashMap<String, EvalResult>,
+                    properties: &HashMap<String, Vec<(String, EvalResult)>>|
+         -> usize {
+            let properties = properties.iter().flat_map(|(list, x)| {
+                x.iter()
+                    .map(move |(name, value)| property_size(list, name, value))
+            });
+            variables
+                .iter()
+                .map(|(k, v)| variable_size(k, v))
+                .chain(properties)
+                .sum()
+        };
+        self.budget.reallocate(
+            &self.limits,
+            size(&self.variables, &self.properties),
+            size(&state.variables, &state.properties),
+        )?;
+        self.turtle = state.turtle;
+        self.variables = state.variables;
+        self.properties = state.properties;
+        self.procedures = state.procedures;
+        self.canvas = state.canvas;
+        self.boundary = state.boundary;
+        self.view = state.view;
+        self.tolerance = state.tolerance;
+        self.hoisted = state.hoisted;
+        Ok(())
+    }
+
+    /// Run a program's commands at the top level, starting from the one at `start`, and stopping
+    /// at the first one which fails. If `within` isn't empty, the command at `start` was already
+    /// running when the program was saved, and carries on from `within` it.
+    pub(crate) fn run(
+        &mut self,
+        ast: &Ast,
+        start: usize,
+        within: &[usize],
+    ) -> Result<(), InterpreterError> {
+        self.fingerprint = Some(ast.fingerprint());
+        self.path.clear();
+        self.run_block(ast.commands(), start, within)
+    }
+
+    /// Run a block of commands from the one at `start`, keeping track of where the program is
+    /// so that it can be saved. Stops at the first command which fails, so its error reaches the
+    /// caller.
+    fn run_block(
+        &mut self,
+        commands: &[Command],
+        start: usize,
+        within: &[usize],
+    ) -> Result<(), InterpreterError> {
+        self.path.push(start);
+        let mut result: Result<(), InterpreterError> = Ok(());
+        for (i, command) in commands.iter().enumerate().skip(start) {
+            if let Some(current) = self.path.last_mut() {
+                *current = i;
+            }
+            if self.path.len() == 1 {
+                self.position = i + 1;
+            }
+            result = match within {
+                [] => command.execute(self),
+                _ if i > start => command.execute(self),
+                _ => self.resume_within(command, within, None),
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+        self.path.pop();
+        result
+    }
+
+    /// Carry on running a command which was already running when the program was saved, from
+    /// the position `within` it, then finish running it as usual
+    fn resume_within(
+        &mut self,
+        command: &Command,
+        within: &[usize],
+        location: Option<&Location>,
+    ) -> Result<(), InterpreterError> {
+        let Some((&start, within)) = within.split_first() else {
+            return command.execute(self);
+        };
+        match command {
+            Command::Located(location, command) => {
+                self.resume_within(command, &[&[start], within].concat(), Some(location))
+            }
+            Command::If(_, commands) => self.run_block(commands, start, within),
+            // The rest of the iteration is run, then the loop carries on as usual
+            Command::While(_, commands) => {
+                if let Some(location) = location {
+                    self.push_frame(Frame::Loop(location.clone()));
+                }
+                let result: Result<(), InterpreterError> = self
+                    .run_block(commands, start, within)
+                    .and_then(|()| command.execute(self));
+                if location.is_some() {
+                    self.pop_frame();
+                }
+                result
+            }
+            Command::ProcedureExec(name, _) => self.resume_procedure(name, start, within),
+            Command::Apply(name, _) => {
+                let name: String = self.eval_name(name, "procedure name")?;
+                self.resume_procedure(&name, start, within)
+            }
+            // The list is worked out again, which gives the same commands as long as it only
+            // depends on what was saved
+            Command::Run(list) => {
+                let commands: Vec<Command> = self.parse_list(list)?;
+                self.nested(|context: &mut Program| context.run_block(&commands, start, within))
+            }
+            _ => Err(InterpreterError::StateMismatch),
+        }
+    }
+
+    /// Carry on running a procedure which was already running when the program was saved. Its
+    /// parameters are ordinary variables, so they were saved along with the rest.
+    fn resume_procedure(
+        &mut self,
+        name: &str,
+        start: usize,
+        within: &[usize],
+    ) -> Result<(), InterpreterError> {
+        let commands: Vec<Command> = match self.procedures.get(name) {
+            Some(Command::ProcedureBase(_, commands)) => commands.clone(),
+            _ => return Err(InterpreterError::StateMismatch),
+        };
+        self.call_procedure(name, &commands, start, within)
+    }
+
+    /// Run the body of a procedure, whose parameters have already been set
+    fn call_procedure(
+        &mut self,
+        name: &str,
+        commands: &[Command],
+        start: usize,
+        within: &[usize],
+    ) -> Result<(), InterpreterError> {
+        self.push_frame(Frame::Procedure(name.to_string()));
+        let result: Result<(), InterpreterError> =
+            self.nested(|context: &mut Program| context.run_block(commands, start, within));
+        self.pop_frame();
+        result
+    }
+
+    /// Parse a list of instructions given to `RUN`
+    fn parse_list(&mut self, list: &Expression) -> Result<Vec<Command>, InterpreterError> {
+        let source: String = list.eval(self)?.printed();
+        parse(&source_file("RUN", source.as_str()), &self.primitives)
+            .map_err(|e| InterpreterError::InvalidList(source, e.errors()[0].kind.to_string()))
+    }
+
+    /// Evaluate an expression which must be a number, such as an angle
+    fn eval_float(&mut self, value: &Expression, field: &str) -> Result<f64, InterpreterError> {
+        match value.eval(self)? {
+            EvalResult::Float(x) => Ok(x),
+            other => Err(InterpreterError::invalid_type(field, other.type_name())),
+        }
+    }
+
+    /// Evaluate the numbers to divide for `QUOTIENT` and `REMAINDER`, which can't divide by zero
+    fn eval_division(
+        &mut self,
+        dividend: &Expression,
+        divisor: &Expression,
+    ) -> Result<(f64, f64), InterpreterError> {
+        let dividend: f64 = self.eval_float(dividend, "dividend")?;
+        match self.eval_float(divisor, "divisor")? {
+            0.0 => Err(InterpreterError::division_by_zero()),
+            divisor => Ok((dividend, divisor)),
+        }
+    }
+
+    /// Evaluate the name of a property list or property. Numbers and booleans are named by how
+    /// they are written, so `"1` names the same property however it was worked out.
+    fn eval_name(&mut self, name: &Expression, field: &str) -> Result<String, InterpreterError> {
+        match name.eval(self)? {
+            EvalResult::List(_) => Err(InterpreterError::invalid_type(field, "list")),
+            word => Ok(word.to_string()),
+        }
+    }
+
+    /// Evaluate the path of a file to save or load a state in
+    fn eval_path(&mut self, path: &Expression) -> Result<String, InterpreterError> {
+        match path.eval(self)? {
+            EvalResult::String(x) => Ok(x),
+            other => Err(InterpreterError::invalid_type(
+                "state file",
+                other.type_name(),
+            )),
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Let `EQ` and `NE` treat numbers which are at most `tolerance` apart as equal, so counters
+    /// built up from fractions can still be compared
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance;
+    }
+
+    /// Whether two values are equal, allowing numbers to be as far apart as the tolerance
+    fn equals(&self, lhs: EvalResult, rhs: EvalResult) -> Result<bool, InterpreterError> {
+        match (lhs, rhs) {
+            (EvalResult::Float(lhs), EvalResult::Float(rhs)) => {
+                Ok(lhs == rhs || (lhs - rhs).abs() <= self.tolerance)
+            }
+            (EvalResult::Bool(lhs), EvalResult::Bool(rhs)) => Ok(lhs == rhs),
+            _ => Err(InterpreterError::unsupported_operation(
+                "comparison of different types",
+            )),
+        }
+    }
+
+    /// Start counting commands and time towards the limits again
+    pub(crate) fn restart_budget(&mut self) {
+        self.budget.restart();
+    }
+
+    /// Add a primitive which programs can use. Programs must be parsed with the same primitives
+    /// as the program they are run in.
+    pub fn register_primitive<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&mut Program, &[EvalResult]) -> Result<Option<EvalResult>, InterpreterError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.primitives.register(name, arity, function);
+    }
+
+    pub fn turtle(&self) -> &Turtle {
+        &self.turtle
+    }
+
+    pub fn turtle_mut(&mut self) -> &mut Turtle {
+        &mut self.turtle
+    }
+
+    pub fn primitives(&self) -> &Primitives {
+        &self.primitives
+    }
+
+    /// Evaluate the arguments of a primitive, then run it
+    fn call_primitive(
+        &mut self,
+        name: &str,
+        arguments: &[Expression],
+    ) -> Result<Option<EvalResult>, InterpreterError> {
+        let primitive: Primitive = self
+            .primitives
+            .get(name)
+            .cloned()
+            .ok_or_else(|| InterpreterError::undefined_primitive(name))?;
+        let values: Vec<EvalResult> = arguments
+            .iter()
+            .map(|x: &Expression| x.eval(self))
+            .collect::<Result<Vec<EvalResult>, InterpreterError>>()?;
+        (primitive.function)(self, &values)
+    }
+
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+
+    pub fn into_canvas(self) -> Canvas {
+        self.canvas
     }
 
     pub fn add_procedure(
@@ -799,7 +1985,6 @@ impl Program {
                 self.procedures.insert(name, procedure);
                 Ok(())
             }
-            Command::Comment => Err(InterpreterError::invalid_type("procedure", "comment")),
             Command::PenUp => Err(InterpreterError::invalid_type(
                 "procedure",
                 "pen up command",
@@ -808,6 +1993,10 @@ impl Program {
                 "procedure",
                 "pen down command",
             )),
+            Command::SetBoundary(_) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "boundary command",
+            )),
             Command::Forward(_) => Err(InterpreterError::invalid_type(
                 "procedure",
                 "forward command",
@@ -828,10 +2017,48 @@ impl Program {
                 "procedure",
                 "set x-coordinate command",
             )),
+            Command::SetZ(_) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "set z-coordinate command",
+            )),
+            Command::Up(_) => Err(InterpreterError::invalid_type("procedure", "up command")),
+            Command::Down(_) => Err(InterpreterError::invalid_type("procedure", "down command")),
+            Command::RollLeft(_) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "roll left command",
+            )),
+            Command::RollRight(_) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "roll right command",
+            )),
+            Command::Label(_) => Err(InterpreterError::invalid_type("procedure", "label command")),
+            Command::SetLabelHeight(_) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "set label height command",
+            )),
+            Command::SetFont(_) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "set font command",
+            )),
             Command::SetY(_) => Err(InterpreterError::invalid_type(
                 "procedure",
                 "set y-coordinate command",
             )),
+            Command::Run(_) => Err(InterpreterError::invalid_type("procedure", "run command")),
+            Command::Apply(_, _) => {
+                Err(InterpreterError::invalid_type("procedure", "apply command"))
+            }
+            Command::Print(_) => Err(InterpreterError::invalid_type("procedure", "print command")),
+            Command::Show(_) => Err(InterpreterError::invalid_type("procedure", "show command")),
+            Command::Type(_) => Err(InterpreterError::invalid_type("procedure", "type command")),
+            Command::SaveState(_) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "save state command",
+            )),
+            Command::LoadState(_) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "load state command",
+            )),
             Command::MakeVariable(_, _) => Err(InterpreterError::invalid_type(
                 "procedure",
                 "make variable command",
@@ -840,6 +2067,17 @@ impl Program {
                 "procedure",
                 "add assign command",
             )),
+            Command::PutProperty(_, _, _) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "put property command",
+            )),
+            Command::RemoveProperty(_, _) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "remove property command",
+            )),
+            Command::Hoist(_, _) => {
+                Err(InterpreterError::invalid_type("procedure", "hoisted value"))
+            }
             Command::If(_, _) => Err(InterpreterError::invalid_type(
                 "procedure",
                 "conditional statement",
@@ -856,6 +2094,14 @@ impl Program {
                 "procedure",
                 "completed procedure",
             )),
+            Command::Load(_, _) => Err(InterpreterError::invalid_type(
+                "procedure",
+                "load instruction",
+            )),
+            Command::Primitive(_, _) => {
+                Err(InterpreterError::invalid_type("procedure", "primitive"))
+            }
+            Command::Located(_, procedure) => self.add_procedure(name, *procedure),
         }
     }
 
@@ -869,9 +2115,11 @@ impl Program {
     }
 
     pub fn insert_var(&mut self, key: String, val: EvalResult) -> Result<(), InterpreterError> {
-        match self.variables.insert(key, val) {
-            Some(_) => Ok(()),
-            None => Err(InterpreterError::unsuccessful_operation(
+        let existed: bool = self.variables.contains_key(&key);
+        self.set_var(key, val)?;
+        match existed {
+            true => Ok(()),
+            false => Err(InterpreterError::unsuccessful_operation(
                 "inserting a new variable",
             )),
         }
@@ -879,17 +2127,18 @@ impl Program {
 
     /// Execute the program by iterating through the `commands` vector and executing them.
     /// Returns a vector of errors
-    pub fn execute(&mut self) -> Vec<Box<dyn Error>> {
+    pub fn execute(&mut self) -> Vec<InterpreterError> {
         // We can take the command vector as they're not going to be used again after this
         let commands: Vec<Command> = std::mem::take(&mut self.commands);
-        let mut result: Vec<Result<(), Box<dyn Error>>> = Vec::new();
+        self.budget.restart();
+        let mut result: Vec<Result<(), InterpreterError>> = Vec::new();
         commands.into_iter().for_each(|command: Command| {
             let curr_result = command.execute(self);
             result.push(curr_result);
         });
 
         // return only the errors
-        let errors: Vec<Box<dyn Error>> = result
+        let errors: Vec<InterpreterError> = result
             .into_iter()
             .filter(|x| x.is_err())
             .map(|x| x.unwrap_err())
@@ -907,86 +2156,144 @@ mod tests {
     #[test]
     fn valid_add() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let mut context: Program = Program::new(Vec::new());
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
         assert_eq!(
             Expression::Add(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
-            Expression::Value(EvalResult::Float(3_f32))
-                .eval(&context)
+            Expression::Value(EvalResult::Float(3_f64))
+                .eval(&mut context)
                 .unwrap(),
         );
     }
     #[test]
     fn valid_sub() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let mut context: Program = Program::new(Vec::new());
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
         assert_eq!(
             Expression::Subtract(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
-            Expression::Value(EvalResult::Float(-1_f32))
-                .eval(&context)
+            Expression::Value(EvalResult::Float(-1_f64))
+                .eval(&mut context)
                 .unwrap(),
         );
     }
     #[test]
     fn valid_multiply() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let mut context: Program = Program::new(Vec::new());
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
         assert_eq!(
             Expression::Multiply(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
-            Expression::Value(EvalResult::Float(2_f32))
-                .eval(&context)
+            Expression::Value(EvalResult::Float(2_f64))
+                .eval(&mut context)
                 .unwrap(),
         );
     }
     #[test]
     fn valid_divide() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let mut context: Program = Program::new(Vec::new());
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
         assert_eq!(
             Expression::Divide(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
-            Expression::Value(EvalResult::Float(0.5_f32))
-                .eval(&context)
+            Expression::Value(EvalResult::Float(0.5_f64))
+                .eval(&mut context)
                 .unwrap(),
         );
     }
     #[test]
     fn invalid_divide_by_zero() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
 
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(0_f32));
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(0_f64));
 
         assert_eq!(
-            Expression::Divide(Box::new(lhs), Box::new(rhs)).eval(&context),
+            Expression::Divide(Box::new(lhs), Box::new(rhs)).eval(&mut context),
             Err(InterpreterError::division_by_zero())
         )
     }
+    #[test]
+    fn quotient_and_remainder_round_towards_zero() {
+        // Dummy program to satisfy parameter
+        let mut context: Program = Program::new(Vec::new());
+        let value = |x: f64| Box::new(Expression::Value(EvalResult::Float(x)));
+        assert_eq!(
+            Expression::Quotient(value(-7.0), value(2.0)).eval(&mut context),
+            Ok(EvalResult::Float(-3.0))
+        );
+        assert_eq!(
+            Expression::Remainder(value(-7.0), value(2.0)).eval(&mut context),
+            Ok(EvalResult::Float(-1.0))
+        );
+        assert_eq!(
+            Expression::Remainder(value(7.5), value(2.0)).eval(&mut context),
+            Ok(EvalResult::Float(1.5))
+        );
+        assert_eq!(
+            Expression::Quotient(value(1.0), value(0.0)).eval(&mut context),
+            Err(InterpreterError::division_by_zero())
+        );
+        assert_eq!(
+            Expression::Remainder(value(1.0), value(-0.0)).eval(&mut context),
+            Err(InterpreterError::division_by_zero())
+        );
+    }
+    #[test]
+    fn equals_within_tolerance() {
+        let mut context: Program = Program::new(Vec::new());
+        let sum = || {
+            Box::new(Expression::Add(
+                Box::new(Expression::Value(EvalResult::Float(0.1))),
+                Box::new(Expression::Value(EvalResult::Float(0.2))),
+            ))
+        };
+        let third = || Box::new(Expression::Value(EvalResult::Float(0.3)));
+        assert_eq!(
+            Expression::Equals(sum(), third()).eval(&mut context),
+            Ok(EvalResult::Bool(false))
+        );
+        context.set_tolerance(1e-9);
+        assert_eq!(
+            Expression::Equals(sum(), third()).eval(&mut context),
+            Ok(EvalResult::Bool(true))
+        );
+        assert_eq!(
+            Expression::NotEquals(sum(), third()).eval(&mut context),
+            Ok(EvalResult::Bool(false))
+        );
+        assert_eq!(
+            Expression::Equals(
+                Box::new(Expression::Value(EvalResult::Float(f64::INFINITY))),
+                Box::new(Expression::Value(EvalResult::Float(f64::INFINITY))),
+            )
+            .eval(&mut context),
+            Ok(EvalResult::Bool(true))
+        );
+    }
 
     #[test]
     fn invalid_arithmetic_on_bool() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
         let rhs: Expression = Expression::Value(EvalResult::Bool(false));
 
         assert_eq!(
-            Expression::Add(Box::new(lhs), Box::new(rhs)).eval(&context),
+            Expression::Add(Box::new(lhs), Box::new(rhs)).eval(&mut context),
             Err(InterpreterError::unsupported_operation(
                 "addition of booleans"
             ))
@@ -994,7 +2301,7 @@ mod tests {
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
         let rhs: Expression = Expression::Value(EvalResult::Bool(false));
         assert_eq!(
-            Expression::Subtract(Box::new(lhs), Box::new(rhs)).eval(&context),
+            Expression::Subtract(Box::new(lhs), Box::new(rhs)).eval(&mut context),
             Err(InterpreterError::unsupported_operation(
                 "subtraction of booleans"
             ))
@@ -1002,7 +2309,7 @@ mod tests {
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
         let rhs: Expression = Expression::Value(EvalResult::Bool(false));
         assert_eq!(
-            Expression::Multiply(Box::new(lhs), Box::new(rhs)).eval(&context),
+            Expression::Multiply(Box::new(lhs), Box::new(rhs)).eval(&mut context),
             Err(InterpreterError::unsupported_operation(
                 "multiplication of booleans"
             ))
@@ -1010,7 +2317,7 @@ mod tests {
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
         let rhs: Expression = Expression::Value(EvalResult::Bool(false));
         assert_eq!(
-            Expression::Divide(Box::new(lhs), Box::new(rhs)).eval(&context),
+            Expression::Divide(Box::new(lhs), Box::new(rhs)).eval(&mut context),
             Err(InterpreterError::unsupported_operation(
                 "division of booleans"
             ))
@@ -1020,14 +2327,14 @@ mod tests {
     #[test]
     fn valid_and() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
 
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
         let rhs: Expression = Expression::Value(EvalResult::Bool(false));
 
         assert_eq!(
             Expression::And(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
@@ -1037,7 +2344,7 @@ mod tests {
 
         assert_eq!(
             Expression::And(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
@@ -1046,7 +2353,7 @@ mod tests {
 
         assert_eq!(
             Expression::And(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
@@ -1054,14 +2361,14 @@ mod tests {
     #[test]
     fn valid_or() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
 
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
         let rhs: Expression = Expression::Value(EvalResult::Bool(false));
 
         assert_eq!(
             Expression::Or(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
@@ -1071,7 +2378,7 @@ mod tests {
 
         assert_eq!(
             Expression::Or(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
@@ -1080,7 +2387,7 @@ mod tests {
 
         assert_eq!(
             Expression::Or(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
@@ -1089,21 +2396,21 @@ mod tests {
     #[test]
     fn invalid_logic_on_float() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let mut context: Program = Program::new(Vec::new());
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
 
         assert_eq!(
-            Expression::And(Box::new(lhs), Box::new(rhs)).eval(&context),
+            Expression::And(Box::new(lhs), Box::new(rhs)).eval(&mut context),
             Err(InterpreterError::unsupported_operation(
                 "logical operation of non-booleans"
             ))
         );
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
 
         assert_eq!(
-            Expression::Or(Box::new(lhs), Box::new(rhs)).eval(&context),
+            Expression::Or(Box::new(lhs), Box::new(rhs)).eval(&mut context),
             Err(InterpreterError::unsupported_operation(
                 "logical operation of non-booleans"
             ))
@@ -1113,23 +2420,23 @@ mod tests {
     #[test]
     fn valid_greater_than_float() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let mut context: Program = Program::new(Vec::new());
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
 
         assert_eq!(
             Expression::GreaterThan(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
 
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
 
         assert_eq!(
             Expression::GreaterThan(Box::new(rhs), Box::new(lhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
@@ -1137,13 +2444,13 @@ mod tests {
     #[test]
     fn valid_greater_than_bool() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
         let rhs: Expression = Expression::Value(EvalResult::Bool(false));
 
         assert_eq!(
             Expression::GreaterThan(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
@@ -1152,7 +2459,7 @@ mod tests {
 
         assert_eq!(
             Expression::GreaterThan(Box::new(rhs), Box::new(lhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
@@ -1160,23 +2467,23 @@ mod tests {
     #[test]
     fn valid_less_than_float() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let mut context: Program = Program::new(Vec::new());
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
 
         assert_eq!(
             Expression::LessThan(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
 
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
 
         assert_eq!(
             Expression::LessThan(Box::new(rhs), Box::new(lhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
@@ -1184,13 +2491,13 @@ mod tests {
     #[test]
     fn valid_less_than_bool() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
         let rhs: Expression = Expression::Value(EvalResult::Bool(false));
 
         assert_eq!(
             Expression::LessThan(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
@@ -1199,7 +2506,7 @@ mod tests {
 
         assert_eq!(
             Expression::LessThan(Box::new(rhs), Box::new(lhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
@@ -1208,24 +2515,24 @@ mod tests {
     #[test]
     fn valid_equals_float() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
 
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(1_f32));
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(1_f64));
 
         assert_eq!(
             Expression::Equals(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
 
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
 
         assert_eq!(
             Expression::Equals(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
@@ -1233,24 +2540,24 @@ mod tests {
     #[test]
     fn valid_not_equals_float() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
 
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(1_f32));
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(1_f64));
 
         assert_eq!(
             Expression::NotEquals(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
 
-        let lhs: Expression = Expression::Value(EvalResult::Float(1_f32));
-        let rhs: Expression = Expression::Value(EvalResult::Float(2_f32));
+        let lhs: Expression = Expression::Value(EvalResult::Float(1_f64));
+        let rhs: Expression = Expression::Value(EvalResult::Float(2_f64));
 
         assert_eq!(
             Expression::NotEquals(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
@@ -1259,14 +2566,14 @@ mod tests {
     #[test]
     fn valid_not_equals_bool() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
 
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
         let rhs: Expression = Expression::Value(EvalResult::Bool(true));
 
         assert_eq!(
             Expression::NotEquals(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
@@ -1276,7 +2583,7 @@ mod tests {
 
         assert_eq!(
             Expression::NotEquals(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
@@ -1284,14 +2591,14 @@ mod tests {
     #[test]
     fn valid_equals_bool() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
 
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
         let rhs: Expression = Expression::Value(EvalResult::Bool(true));
 
         assert_eq!(
             Expression::Equals(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
@@ -1301,7 +2608,7 @@ mod tests {
 
         assert_eq!(
             Expression::Equals(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(false)
         );
@@ -1310,13 +2617,13 @@ mod tests {
     #[test]
     fn invalid_equals() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
 
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
-        let rhs: Expression = Expression::Value(EvalResult::Float(1_f32));
+        let rhs: Expression = Expression::Value(EvalResult::Float(1_f64));
 
         assert_eq!(
-            Expression::Equals(Box::new(lhs), Box::new(rhs)).eval(&context),
+            Expression::Equals(Box::new(lhs), Box::new(rhs)).eval(&mut context),
             Err(InterpreterError::unsupported_operation(
                 "comparison of different types"
             ))
@@ -1326,13 +2633,13 @@ mod tests {
     #[test]
     fn invalid_not_equals() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
 
         let lhs: Expression = Expression::Value(EvalResult::Bool(true));
-        let rhs: Expression = Expression::Value(EvalResult::Float(1_f32));
+        let rhs: Expression = Expression::Value(EvalResult::Float(1_f64));
 
         assert_eq!(
-            Expression::NotEquals(Box::new(lhs), Box::new(rhs)).eval(&context),
+            Expression::NotEquals(Box::new(lhs), Box::new(rhs)).eval(&mut context),
             Err(InterpreterError::unsupported_operation(
                 "comparison of different types"
             ))
@@ -1342,31 +2649,31 @@ mod tests {
     #[test]
     fn integration() {
         // Dummy program to satisfy parameter
-        let context: Program = Program::new(Vec::new());
+        let mut context: Program = Program::new(Vec::new());
 
         let lhs: Expression = Expression::Divide(
             Box::new(Expression::Add(
-                Box::new(Expression::Value(EvalResult::Float(2_f32))),
-                Box::new(Expression::Value(EvalResult::Float(2_f32))),
+                Box::new(Expression::Value(EvalResult::Float(2_f64))),
+                Box::new(Expression::Value(EvalResult::Float(2_f64))),
             )),
-            Box::new(Expression::Value(EvalResult::Float(2_f32))),
+            Box::new(Expression::Value(EvalResult::Float(2_f64))),
         ); // should evaluate to 2
         let rhs: Expression = Expression::Multiply(
             Box::new(Expression::Subtract(
-                Box::new(Expression::Value(EvalResult::Float(5_f32))),
-                Box::new(Expression::Value(EvalResult::Float(2_f32))),
+                Box::new(Expression::Value(EvalResult::Float(5_f64))),
+                Box::new(Expression::Value(EvalResult::Float(2_f64))),
             )),
             Box::new(Expression::Add(
-                Box::new(Expression::Value(EvalResult::Float(2_f32))),
-                Box::new(Expression::Value(EvalResult::Float(2_f32))),
+                Box::new(Expression::Value(EvalResult::Float(2_f64))),
+                Box::new(Expression::Value(EvalResult::Float(2_f64))),
             )),
         ); // should evaluate to 12
 
-        assert_eq!(lhs.eval(&context).unwrap(), EvalResult::Float(2_f32));
-        assert_eq!(rhs.eval(&context).unwrap(), EvalResult::Float(12_f32));
+        assert_eq!(lhs.eval(&mut context).unwrap(), EvalResult::Float(2_f64));
+        assert_eq!(rhs.eval(&mut context).unwrap(), EvalResult::Float(12_f64));
         assert_eq!(
             Expression::LessThan(Box::new(lhs), Box::new(rhs))
-                .eval(&context)
+                .eval(&mut context)
                 .unwrap(),
             EvalResult::Bool(true)
         );
@@ -1396,18 +2703,18 @@ mod tests {
         #![proptest_config(ProptestConfig::with_cases(100000))]
         // This doesn't seem to work because of weird floating point stuff
         // #[test]
-        // fn add_floats_correctly(lhs in proptest::num::f32::NORMAL, rhs in proptest::num::f32::NORMAL) {
-        //     let context = Program::new(Vec::new()); // Assuming this creates a suitable context for evaluation
+        // fn add_floats_correctly(lhs in proptest::num::f64::NORMAL, rhs in proptest::num::f64::NORMAL) {
+        //     let mut context = Program::new(Vec::new()); // Assuming this creates a suitable context for evaluation
         //     let lhs_expr = Expression::Value(EvalResult::Float(lhs));
         //     let rhs_expr = Expression::Value(EvalResult::Float(rhs));
 
         //     let add_expr = Expression::Add(Box::new(lhs_expr), Box::new(rhs_expr));
 
         //     // Evaluate the addition expression
-        //     match add_expr.eval(&context) {
+        //     match add_expr.eval(&mut context) {
         //         Ok(EvalResult::Float(result)) => {
         //             // Assert the property: The result should be approximately equal to the sum of lhs and rhs
-        //             prop_assert!((result - (lhs + rhs)).abs() < f32::EPSILON.abs());
+        //             prop_assert!((result - (lhs + rhs)).abs() < f64::EPSILON.abs());
         //         },
         //         _ => prop_assert!(false, "Expected Float result from addition"),
         //     }
@@ -1415,7 +2722,7 @@ mod tests {
 
         // This doesn't seem to work because of weird floating point stuff
         // #[test]
-        // fn move_turtle_correctly(movements in proptest::collection::vec((proptest::num::f32::NORMAL, proptest::num::f32::NORMAL), 0..1000)) {
+        // fn move_turtle_correctly(movements in proptest::collection::vec((proptest::num::f64::NORMAL, proptest::num::f64::NORMAL), 0..1000)) {
         //     let (x_incr, y_incr) = movements
         //         .par_iter()
         //         .fold(|| (0.0, 0.0), |acc, &x| (acc.0 + x.0, acc.1 + x.1))
@@ -1450,12 +2757,12 @@ mod tests {
         //     prop_assert!(errors.is_empty());
         //     let (end_x, end_y) = program.turtle.get_turtle_coords();
 
-        //     prop_assert!(( end_x - (start_x + x_incr) ).abs() < f32::EPSILON.abs());
-        //     prop_assert!(( end_y - (start_y + y_incr) ).abs() < f32::EPSILON.abs());
+        //     prop_assert!(( end_x - (start_x + x_incr) ).abs() < f64::EPSILON.abs());
+        //     prop_assert!(( end_y - (start_y + y_incr) ).abs() < f64::EPSILON.abs());
         // }
 
         #[test]
-        fn set_colour_correctly(colour in any::<f32>()) {
+        fn set_colour_correctly(colour in any::<f64>()) {
            let mut program = Program::new(vec![Command::SetPenColor(Expression::Value(EvalResult::Float(colour)))]);
             let errors = program.execute();
             match colour {
@@ -1474,11 +2781,11 @@ mod tests {
         }
 
         #[test]
-        fn turn_turtle_correctly(angles in any::<Vec<f32>>()) {
+        fn turn_turtle_correctly(angles in any::<Vec<f64>>()) {
             let mut commands: Vec<Command> = Vec::new();
 
             let mut num_expected_failures: usize = 0;
-            let mut expected_change_in_angle: f32 = 0.0;
+            let mut expected_change_in_angle: f64 = 0.0;
             for angle in angles {
                 if (0.0..=360.0).contains(&angle) {
                     expected_change_in_angle += angle;
@@ -0,0 +1,716 @@
+use std::collections::HashMap;
+
+use crate::{
+    canvas::Boundary,
+    errors::TranspileError,
+    tokens::{Ast, Command, EvalResult, Expression, Location},
+};
+
+/// The runtime each script starts with, which draws and checks values the same way as rslogo
+const PYTHON_RUNTIME: &str = include_str!("transpile/runtime.py");
+const JAVASCRIPT_RUNTIME: &str = include_str!("transpile/runtime.js");
+
+/// The languages a program can be transpiled to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Target {
+    /// Python 3, drawing with the `turtle` module which comes with it
+    Python,
+
+    /// JavaScript for a web page, drawing onto a new `<canvas>` at the end of the page
+    JavaScript,
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Python => write!(f, "Python"),
+            Target::JavaScript => write!(f, "JavaScript"),
+        }
+    }
+}
+
+/// A procedure, and the function it becomes
+struct Procedure {
+    function: String,
+    parameters: Vec<String>,
+}
+
+/// Writes a script a line at a time, keeping track of how far to indent
+struct Emitter<'a> {
+    target: Target,
+    procedures: HashMap<String, Procedure>,
+
+    /// The innermost command being transpiled, which errors point at
+    location: Option<&'a Location>,
+
+    script: String,
+    indent: usize,
+}
+
+/// Transpile a program into a standalone script, which draws the same thing onto a canvas of the
+/// given size. Procedures become functions, and `IF` and `WHILE` become the language's own
+/// control flow. Values are checked as the program runs, so errors such as an angle out of range
+/// stop the script as they would stop rslogo.
+///
+/// Anything which needs the interpreter itself, such as `RUN`, templates, saved states,
+/// primitives, `WRAP`, `FENCE` and moving in 3D, can't be transpiled.
+pub fn transpile(
+    ast: &Ast,
+    target: Target,
+    width: u32,
+    height: u32,
+) -> Result<String, TranspileError> {
+    let mut emitter = Emitter {
+        target,
+        procedures: HashMap::new(),
+        location: None,
+        script: String::new(),
+        indent: 0,
+    };
+    let mut definitions: Vec<(&Location, &Command)> = Vec::new();
+    emitter.find_procedures(ast.commands(), None, &mut definitions)?;
+
+    match target {
+        Target::Python => {
+            emitter.line("#!/usr/bin/env python3");
+            emitter.line("\"\"\"Transpiled from Logo by rslogo. Run it with Python 3.\"\"\"");
+            emitter.line("");
+            emitter.line(&format!("WIDTH = {width}"));
+            emitter.line(&format!("HEIGHT = {height}"));
+            emitter.line("");
+            emitter.script += PYTHON_RUNTIME;
+        }
+        Target::JavaScript => {
+            emitter.line("// Transpiled from Logo by rslogo. Load it at the end of a page's body,");
+            emitter.line("// and it draws onto a new canvas there.");
+            emitter.line("\"use strict\";");
+            emitter.line("");
+            emitter.line(&format!("const WIDTH = {width};"));
+            emitter.line(&format!("const HEIGHT = {height};"));
+            emitter.line("");
+            emitter.script += JAVASCRIPT_RUNTIME;
+        }
+    }
+
+    for (location, definition) in definitions {
+        let Command::ProcedureDefine(name, _, commands) = definition else {
+            continue;
+        };
+        let name: &str = emitter.literal_name(name, "a procedure named by an expression")?;
+        let procedure: &Procedure = &emitter.procedures[name];
+        let (function, parameters) = (procedure.function.clone(), procedure.parameters.clone());
+        let arguments: Vec<String> = (0..parameters.len()).map(|i| format!("p{i}")).collect();
+
+        emitter.location = Some(location);
+        emitter.line("");
+        emitter.line("");
+        let header: String = match target {
+            Target::Python => format!("def {function}({}):", arguments.join(", ")),
+            Target::JavaScript => format!("function {function}({}) {{", arguments.join(", ")),
+        };
+        emitter.open(header);
+        // Parameters are ordinary variables, which stay set after the call
+        for (parameter, argument) in parameters.iter().zip(&arguments) {
+            let make: String = emitter.call("make", &[quote(parameter), argument.clone()]);
+            emitter.statement(make);
+        }
+        emitter.block(commands)?;
+        emitter.close();
+    }
+
+    emitter.line("");
+    emitter.line("");
+    emitter.location = None;
+    let header: &str = match target {
+        Target::Python => "def main():",
+        Target::JavaScript => "function main() {",
+    };
+    emitter.open(header.to_string());
+    emitter.block(ast.commands())?;
+    emitter.close();
+    emitter.line("");
+    emitter.line("");
+    match target {
+        Target::Python => {
+            emitter.line("if __name__ == \"__main__\":");
+            emitter.line("    run(main)");
+        }
+        Target::JavaScript => emitter.line("run(main);"),
+    }
+    Ok(emitter.script)
+}
+
+/// Quote a word as a string literal. JSON strings are valid in both Python and JavaScript.
+fn quote(word: &str) -> String {
+    serde_json::to_string(word).expect("strings can always be written as JSON")
+}
+
+/// A Python or JavaScript identifier for a name which may have any characters in it
+fn identifier(prefix: &str, name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|x| match x.is_ascii_alphanumeric() {
+            true => x,
+            false => '_',
+        })
+        .collect();
+    format!("{prefix}{name}")
+}
+
+impl<'a> Emitter<'a> {
+    fn line(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.script += &"    ".repeat(self.indent);
+        }
+        self.script += text;
+        self.script.push('\n');
+    }
+
+    /// Start a block, such as a loop or function, with the line which opens it
+    fn open(&mut self, header: String) {
+        self.line(&header);
+        self.indent += 1;
+    }
+
+    fn close(&mut self) {
+        self.indent -= 1;
+        if self.target == Target::JavaScript {
+            self.line("}");
+        }
+    }
+
+    fn statement(&mut self, code: String) {
+        match self.target {
+            Target::Python => self.line(&code),
+            Target::JavaScript => self.line(&format!("{code};")),
+        }
+    }
+
+    /// A call to a function in the runtime, which is named in the style of the target language
+    fn call(&self, function: &str, arguments: &[String]) -> String {
+        let function: String = match self.target {
+            Target::Python => function.to_string(),
+            Target::JavaScript => function
+                .split('_')
+                .enumerate()
+                .map(|(i, x)| match i {
+                    0 => x.to_string(),
+                    _ => x[..1].to_uppercase() + &x[1..],
+                })
+                .collect(),
+        };
+        format!("{function}({})", arguments.join(", "))
+    }
+
+    fn unsupported(&self, what: &str) -> TranspileError {
+        TranspileError::Unsupported {
+            what: what.to_string(),
+            target: self.target,
+            src: self.location.and_then(|x| x.src.clone()),
+            span: self.location.map(|x| x.span),
+        }
+    }
+
+    /// The name of a procedure or parameter, which must be written in the program
+    fn literal_name(&self, name: &'a Expression, what: &str) -> Result<&'a str, TranspileError> {
+        match name {
+            Expression::Value(EvalResult::String(x)) => Ok(x),
+            _ => Err(self.unsupported(what)),
+        }
+    }
+
+    /// Find every procedure the program defines, so that they can become functions before the
+    /// program is written
+    fn find_procedures(
+        &mut self,
+        commands: &'a [Command],
+        location: Option<&'a Location>,
+        definitions: &mut Vec<(&'a Location, &'a Command)>,
+    ) -> Result<(), TranspileError> {
+        for command in commands {
+            match command {
+                Command::Located(location, command) => self.find_procedures(
+                    std::slice::from_ref(command),
+                    Some(location),
+                    definitions,
+                )?,
+                Command::ProcedureDefine(name, parameters, body) => {
+                    self.location = location;
+                    let name: &str =
+                        self.literal_name(name, "a procedure named by an expression")?;
+                    if self.procedures.contains_key(name) {
+                        return Err(TranspileError::Redefined {
+                            name: name.to_string(),
+                            src: location.and_then(|x| x.src.clone()),
+                            span: location.map(|x| x.span),
+                        });
+                    }
+                    let parameters: Vec<String> = parameters
+                        .iter()
+                        .map(|x| {
+                            self.literal_name(x, "a parameter named by an expression")
+                                .map(str::to_string)
+                        })
+                        .collect::<Result<Vec<String>, TranspileError>>()?;
+                    // Names which only differ in their punctuation need functions of their own
+                    let mut function: String = identifier("to_", name);
+                    while self.procedures.values().any(|x| x.function == function) {
+                        function.push('_');
+                    }
+                    self.procedures.insert(
+                        name.to_string(),
+                        Procedure {
+                            function,
+                            parameters,
+                        },
+                    );
+                    if let Some(location) = location {
+                        definitions.push((location, command));
+                    }
+                    self.find_procedures(body, location, definitions)?;
+                }
+                Command::If(_, body) | Command::While(_, body) => {
+                    self.find_procedures(body, location, definitions)?
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the commands in a block. Python can't have an empty block, so it gets a `pass`.
+    fn block(&mut self, commands: &'a [Command]) -> Result<(), TranspileError> {
+        if commands.is_empty() && self.target == Target::Python {
+            self.line("pass");
+        }
+        let outer: Option<&Location> = self.location;
+        commands.iter().try_for_each(|x| self.command(x))?;
+        self.location = outer;
+        Ok(())
+    }
+
+    fn command(&mut self, command: &'a Command) -> Result<(), TranspileError> {
+        let simple = |function: &'static str, value: &'a Expression| Some((function, value));
+        let single: Option<(&str, &Expression)> = match command {
+            Command::Forward(x) => simple("forward", x),
+            Command::Back(x) => simple("back", x),
+            Command::Left(x) => simple("left", x),
+            Command::Right(x) => simple("right", x),
+            Command::SetPenColor(x) => simple("set_pen_colour", x),
+            Command::Turn(x) => simple("turn", x),
+            Command::SetHeading(x) => simple("set_heading", x),
+            Command::SetX(x) => simple("set_x", x),
+            Command::SetY(x) => simple("set_y", x),
+            Command::Label(x) => simple("label", x),
+            Command::SetLabelHeight(x) => simple("set_label_height", x),
+            Command::SetFont(x) => simple("set_font", x),
+            Command::Print(x) => simple("print_value", x),
+            Command::Show(x) => simple("show", x),
+            Command::Type(x) => simple("type_value", x),
+            _ => None,
+        };
+        if let Some((function, value)) = single {
+            let value: String = self.expression(value)?;
+            let call: String = self.call(function, &[value]);
+            self.statement(call);
+            return Ok(());
+        }
+
+        match command {
+            Command::Located(location, command) => {
+                self.location = Some(location);
+                self.command(command)
+            }
+            Command::PenUp => {
+                let call: String = self.call("pen_up", &[]);
+                self.statement(call);
+                Ok(())
+            }
+            Command::PenDown => {
+                let call: String = self.call("pen_down", &[]);
+                self.statement(call);
+                Ok(())
+            }
+            Command::MakeVariable(name, value) | Command::Increment(name, value) => {
+                let function: &str = match command {
+                    Command::MakeVariable(_, _) => "make",
+                    _ => "increment",
+                };
+                let arguments: [String; 2] = [self.expression(name)?, self.expression(value)?];
+                let call: String = self.call(function, &arguments);
+                self.statement(call);
+                Ok(())
+            }
+            Command::If(condition, body) | Command::While(condition, body) => {
+                let keyword: &str = match command {
+                    Command::If(_, _) => "if",
+                    _ => "while",
+                };
+                let condition: String = self.expression(condition)?;
+                let condition: String = self.call("truth", &[condition]);
+                let header: String = match self.target {
+                    Target::Python => format!("{keyword} {condition}:"),
+                    Target::JavaScript => format!("{keyword} ({condition}) {{"),
+                };
+                self.open(header);
+                self.block(body)?;
+                self.close();
+                Ok(())
+            }
+            // The function is written before the program, but it can only be called once its `TO`
+            // has run and put it in the runtime's table of procedures
+            Command::ProcedureDefine(name, _, _) => {
+                let name: &str = self.literal_name(name, "a procedure named by an expression")?;
+                let function: String = self.procedures[name].function.clone();
+                let call: String = self.call("define", &[quote(name), function]);
+                self.statement(call);
+                Ok(())
+            }
+            Command::ProcedureExec(name, arguments) => {
+                let Some(procedure) = self.procedures.get(name) else {
+                    return Err(TranspileError::UndefinedProcedure {
+                        name: name.clone(),
+                        src: self.location.and_then(|x| x.src.clone()),
+                        span: self.location.map(|x| x.span),
+                    });
+                };
+                if procedure.parameters.len() != arguments.len() {
+                    return Err(TranspileError::Arity {
+                        name: name.clone(),
+                        expected: procedure.parameters.len(),
+                        given: arguments.len(),
+                        src: self.location.and_then(|x| x.src.clone()),
+                        span: self.location.map(|x| x.span),
+                    });
+                }
+                // The procedure is fetched before its arguments are worked out, as in rslogo
+                let function: String = self.call("fetch_procedure", &[quote(name)]);
+                let arguments: Vec<String> = arguments
+                    .iter()
+                    .map(|x| self.expression(x))
+                    .collect::<Result<Vec<String>, TranspileError>>()?;
+                self.statement(format!("{function}({})", arguments.join(", ")));
+                Ok(())
+            }
+            Command::PutProperty(list, property, value) => {
+                let arguments: [String; 3] = [
+                    self.expression(list)?,
+                    self.expression(property)?,
+                    self.expression(value)?,
+                ];
+                let call: String = self.call("put_property", &arguments);
+                self.statement(call);
+                Ok(())
+            }
+            Command::RemoveProperty(list, property) => {
+                let arguments: [String; 2] = [self.expression(list)?, self.expression(property)?];
+                let call: String = self.call("remove_property", &arguments);
+                self.statement(call);
+                Ok(())
+            }
+            Command::Apply(name, arguments) => {
+                let arguments: [String; 2] = [self.expression(name)?, self.expression(arguments)?];
+                let call: String = self.call("apply", &arguments);
+                self.statement(call);
+                Ok(())
+            }
+            // Scripts always draw like `WINDOW`, which is where the turtle starts
+            Command::SetBoundary(Boundary::Window) => {
+                if self.target == Target::Python {
+                    self.line("pass");
+                }
+                Ok(())
+            }
+            Command::SetBoundary(_) => Err(self.unsupported("WRAP and FENCE")),
+            Command::SetZ(_)
+            | Command::Up(_)
+            | Command::Down(_)
+            | Command::RollLeft(_)
+            | Command::RollRight(_) => Err(self.unsupported("moving in 3D")),
+            Command::Run(_) => Err(self.unsupported("RUN")),
+            Command::SaveState(_) | Command::LoadState(_) => {
+                Err(self.unsupported("saving and loading states"))
+            }
+            Command::Primitive(name, _) => Err(self.unsupported(&format!("the primitive {name}"))),
+            Command::ProcedureBase(_, _) | Command::Load(_, _) => {
+                Err(self.unsupported("an unresolved procedure or LOAD"))
+            }
+            Command::Hoist(_, _) => Err(self.unsupported("an optimised program")),
+            _ => unreachable!("commands with a single expression are written above"),
+        }
+    }
+
+    fn expression(&self, expression: &Expression) -> Result<String, TranspileError> {
+        let binary = |function: &str, lhs: &Expression, rhs: &Expression| {
+            Ok(self.call(function, &[self.expression(lhs)?, self.expression(rhs)?]))
+        };
+        let unary = |function: &str, value: &Expression| {
+            Ok(self.call(function, &[self.expression(value)?]))
+        };
+        match expression {
+            Expression::Value(value) | Expression::Variable(value) => Ok(self.value(value)),
+            Expression::GetVariable(name) => unary("get", name),
+            Expression::Add(lhs, rhs) => binary("add", lhs, rhs),
+            Expression::Subtract(lhs, rhs) => binary("sub", lhs, rhs),
+            Expression::Multiply(lhs, rhs) => binary("mul", lhs, rhs),
+            Expression::Divide(lhs, rhs) => binary("div", lhs, rhs),
+            Expression::Quotient(lhs, rhs) => binary("quotient", lhs, rhs),
+            Expression::Remainder(lhs, rhs) => binary("remainder", lhs, rhs),
+            Expression::Equals(lhs, rhs) => binary("eq", lhs, rhs),
+            Expression::NotEquals(lhs, rhs) => binary("ne", lhs, rhs),
+            Expression::GreaterThan(lhs, rhs) => binary("gt", lhs, rhs),
+            Expression::LessThan(lhs, rhs) => binary("lt", lhs, rhs),
+            Expression::And(lhs, rhs) => binary("both", lhs, rhs),
+            Expression::Or(lhs, rhs) => binary("either", lhs, rhs),
+            Expression::Word(lhs, rhs) => binary("word", lhs, rhs),
+            Expression::WordP(value) => unary("wordp", value),
+            Expression::NumberP(value) => unary("numberp", value),
+            Expression::IntegerP(value) => unary("integerp", value),
+            Expression::Count(value) => unary("count", value),
+            Expression::GetProperty(list, property) => binary("get_property", list, property),
+            Expression::PropertyList(list) => unary("property_list", list),
+            Expression::XCor => Ok(self.call("xcor", &[])),
+            Expression::YCor => Ok(self.call("ycor", &[])),
+            Expression::Heading => Ok(self.call("heading", &[])),
+            Expression::Colour => Ok(self.call("colour", &[])),
+            Expression::ReadWord => Ok(self.call("read_word", &[])),
+            Expression::ReadList => Ok(self.call("read_list", &[])),
+            Expression::ZCor => Err(self.unsupported("moving in 3D")),
+            Expression::Map(_, _) | Expression::Filter(_, _) | Expression::Reduce(_, _) => {
+                Err(self.unsupported("MAP, FILTER and REDUCE"))
+            }
+            Expression::Primitive(name, _) => {
+                Err(self.unsupported(&format!("the primitive {name}")))
+            }
+            Expression::Hoisted(_) => Err(self.unsupported("an optimised program")),
+        }
+    }
+
+    /// A value written as a literal in the target language
+    fn value(&self, value: &EvalResult) -> String {
+        match (value, self.target) {
+            (EvalResult::Bool(true), Target::Python) => "True".to_string(),
+            (EvalResult::Bool(false), Target::Python) => "False".to_string(),
+            (EvalResult::Bool(x), Target::JavaScript) => x.to_string(),
+            (EvalResult::Float(x), _) if x.is_finite() => format!("{x:?}"),
+            (EvalResult::Float(x), Target::Python) if x.is_nan() => "math.nan".to_string(),
+            (EvalResult::Float(x), Target::Python) if *x > 0.0 => "math.inf".to_string(),
+            (EvalResult::Float(_), Target::Python) => "-math.inf".to_string(),
+            (EvalResult::Float(x), Target::JavaScript) => format!("{x}").replace("inf", "Infinity"),
+            (EvalResult::String(x), _) => quote(x),
+            (EvalResult::List(items), _) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|x| self.value(x))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, process::Output};
+
+    use super::*;
+    use crate::{loader::Loader, parse, Canvas, Interpreter};
+
+    /// Stands in for Python's turtle module, recording lines instead of opening a window
+    const TURTLE_STUB: &str = r##"
+import json
+
+LINES = []
+
+
+class Screen:
+    def __getattr__(self, name):
+        return lambda *args: None
+
+
+class Turtle:
+    def __init__(self):
+        self.position, self.down, self.colour = (0, 0), True, None
+
+    def __getattr__(self, name):
+        return lambda *args, **kwargs: None
+
+    def penup(self):
+        self.down = False
+
+    def pendown(self):
+        self.down = True
+
+    def pencolor(self, colour):
+        self.colour = "#%02x%02x%02x" % colour
+
+    def goto(self, position):
+        if self.down:
+            LINES.append([self.position, position, self.colour])
+        self.position = position
+
+
+def done():
+    print(json.dumps(LINES))
+"##;
+
+    /// Stands in for a web page, recording lines instead of drawing them
+    const CANVAS_STUB: &str = r##"
+const lines = [];
+let position = [0, 0];
+const context = new Proxy({
+    moveTo(x, y) { position = [x, y]; },
+    lineTo(x, y) { lines.push([position, [x, y], this.strokeStyle]); position = [x, y]; },
+}, { get: (target, name) => target[name] ?? (() => {}) });
+globalThis.document = {
+    createElement: () => ({ getContext: () => context }),
+    body: { appendChild() {} },
+};
+globalThis.prompt = () => null;
+process.on("exit", () => console.log(JSON.stringify(lines)));
+"##;
+
+    /// Run a script with a stub in place of its graphics, returning whether it stopped with an
+    /// error, and the lines it drew
+    fn run_script(target: Target, script: &str) -> (bool, Vec<Line>) {
+        let dir = tempfile::tempdir().unwrap();
+        let output: Output = match target {
+            Target::Python => {
+                std::fs::write(dir.path().join("turtle.py"), TURTLE_STUB).unwrap();
+                std::fs::write(dir.path().join("script.py"), script).unwrap();
+                std::process::Command::new("python3")
+                    .arg(dir.path().join("script.py"))
+                    .env("PYTHONPATH", dir.path())
+                    .stdin(std::process::Stdio::null())
+                    .output()
+                    .expect("python3 should be installed")
+            }
+            Target::JavaScript => {
+                std::fs::write(dir.path().join("stub.js"), CANVAS_STUB).unwrap();
+                std::fs::write(dir.path().join("script.js"), script).unwrap();
+                std::process::Command::new("node")
+                    .arg("--require")
+                    .arg(dir.path().join("stub.js"))
+                    .arg(dir.path().join("script.js"))
+                    .output()
+                    .expect("node should be installed")
+            }
+        };
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let failed: bool = stderr.starts_with("Error: ");
+        let expected_status: bool = target == Target::JavaScript || !failed;
+        assert_eq!(output.status.success(), expected_status, "{stderr}");
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let drawn: Vec<(Vec<f64>, Vec<f64>, String)> =
+            serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+        let lines: Vec<Line> = drawn
+            .into_iter()
+            .map(|(start, end, colour)| {
+                // Turtle graphics has its origin in the middle, with `y` growing upwards
+                let point = |x: Vec<f64>| match target {
+                    Target::Python => (x[0] + 250.0, 250.0 - x[1]),
+                    Target::JavaScript => (x[0], x[1]),
+                };
+                (point(start), point(end), colour)
+            })
+            .collect();
+        (failed, lines)
+    }
+
+    type Line = ((f64, f64), (f64, f64), String);
+
+    fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+    }
+
+    /// Check that a script draws the same lines as rslogo, and stops with an error if it does
+    fn draws_the_same(target: Target, ast: &Ast, script: &str, name: &str) {
+        let (failed, lines) = run_script(target, script);
+
+        let mut interpreter = Interpreter::new(Canvas::new(500, 500));
+        let result = interpreter.run(ast);
+        assert_eq!(result.is_err(), failed, "{name}: {result:?}");
+        let expected = interpreter.canvas().lines();
+        assert_eq!(expected.len(), lines.len(), "{name}");
+        for (expected, (start, end, colour)) in expected.iter().zip(lines) {
+            let rgb = unsvg::COLORS[expected.colour];
+            let rgb = format!("#{:02x}{:02x}{:02x}", rgb.red, rgb.green, rgb.blue);
+            assert!(
+                close(expected.start, start) && close(expected.end, end),
+                "{name}: {expected:?} was drawn from {start:?} to {end:?}"
+            );
+            assert_eq!(rgb, colour, "{name}");
+        }
+    }
+
+    /// How many of the examples parse, all of which should transpile
+    const EXAMPLES_WHICH_PARSE: usize = 43;
+
+    fn examples_draw_the_same(target: Target) {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("logo_examples");
+        let mut transpiled: usize = 0;
+        for entry in std::fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            // Examples which don't parse have nothing to transpile
+            let Ok(ast) = Loader::new(Vec::new()).load(&path) else {
+                continue;
+            };
+            let script = transpile(&ast, target, 500, 500)
+                .unwrap_or_else(|e| panic!("{}: {e:?}", path.display()));
+            draws_the_same(target, &ast, &script, &path.display().to_string());
+            transpiled += 1;
+        }
+        assert_eq!(transpiled, EXAMPLES_WHICH_PARSE);
+    }
+
+    /// Procedures can only be called once their `TO` has run
+    fn definitions_run_in_order(target: Target) {
+        for source in [
+            "IF \"FALSE [\n  TO Foo\n    FORWARD \"10\n  END\n]\nPENDOWN\nFoo\n",
+            "PENDOWN\nFoo\nTO Foo\n  FORWARD \"10\nEND\n",
+            "TO Foo\n  FORWARD \"10\nEND\nPENDOWN\nFoo\n",
+        ] {
+            let ast = parse(source).unwrap();
+            let script = transpile(&ast, target, 500, 500).unwrap();
+            draws_the_same(target, &ast, &script, source);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs python3"]
+    fn python_draws_the_same() {
+        examples_draw_the_same(Target::Python);
+        definitions_run_in_order(Target::Python);
+    }
+
+    #[test]
+    #[ignore = "needs node"]
+    fn javascript_draws_the_same() {
+        examples_draw_the_same(Target::JavaScript);
+        definitions_run_in_order(Target::JavaScript);
+    }
+
+    #[test]
+    fn writes_native_control_flow() {
+        let ast =
+            parse("TO Box \"size\n FORWARD :size\nEND\nWHILE \"TRUE [ IF \"TRUE [ Box \"1 ] ]")
+                .unwrap();
+        let python = transpile(&ast, Target::Python, 500, 500).unwrap();
+        assert!(python.contains("def to_Box(p0):\n    make(\"size\", p0)\n"));
+        assert!(python.contains("    while truth(True):\n        if truth(True):\n"));
+        let javascript = transpile(&ast, Target::JavaScript, 500, 500).unwrap();
+        assert!(javascript.contains("            fetchProcedure(\"Box\")(1.0);\n"));
+    }
+
+    #[test]
+    fn rejects_what_needs_the_interpreter() {
+        let ast = parse("WRAP\nFORWARD \"10").unwrap();
+        assert!(matches!(
+            transpile(&ast, Target::Python, 500, 500),
+            Err(TranspileError::Unsupported { span: Some(_), .. })
+        ));
+    }
+}
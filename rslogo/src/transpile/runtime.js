@@ -0,0 +1,501 @@
+// The runtime below works the same way as rslogo, so the program draws the same thing. Numbers
+// are double precision, the same as in rslogo.
+
+class LogoError extends Error {}
+
+const COLOURS = [
+    "#000000", "#0000ff", "#00ffff", "#00ff00", "#ff0000", "#ff00ff", "#ffff00", "#ffffff",
+    "#a52a2a", "#d2b48c", "#228b22", "#7fffd4", "#fa8072", "#800080", "#ffa500", "#808080",
+];
+const NUMBER = /^[+-]?(\d+\.?\d*(e[+-]?\d+)?|\.\d+(e[+-]?\d+)?|inf|infinity|nan)$/i;
+
+const T = {
+    x: WIDTH / 2,
+    y: HEIGHT / 2,
+    heading: 0,
+    down: false,
+    colour: 0,
+    labelHeight: 12,
+    font: "sans-serif",
+};
+const V = new Map();
+const PROPERTIES = new Map();
+const PROCEDURES = new Map();
+let CONTEXT = null;
+let OUTPUT = "";
+
+function run(main) {
+    const canvas = document.createElement("canvas");
+    canvas.width = WIDTH;
+    canvas.height = HEIGHT;
+    document.body.appendChild(canvas);
+    CONTEXT = canvas.getContext("2d");
+    try {
+        main();
+    } catch (e) {
+        if (!(e instanceof LogoError)) {
+            throw e;
+        }
+        console.error(`Error: ${e.message}`);
+    } finally {
+        if (OUTPUT !== "") {
+            console.log(OUTPUT);
+        }
+    }
+}
+
+// Values
+
+function typeName(value) {
+    switch (typeof value) {
+        case "boolean":
+            return "boolean";
+        case "number":
+            return "float";
+        case "string":
+            return "string";
+        default:
+            return "list";
+    }
+}
+
+function number(value, field) {
+    if (typeof value === "number") {
+        return value;
+    }
+    throw new LogoError(`Invalid type for ${field}: ${typeName(value)}`);
+}
+
+function wordOf(value, field) {
+    if (typeof value === "string") {
+        return value;
+    }
+    throw new LogoError(`Invalid type for ${field}: ${typeName(value)}`);
+}
+
+function nameOf(value, field) {
+    if (Array.isArray(value)) {
+        throw new LogoError(`Invalid type for ${field}: list`);
+    }
+    return text(value);
+}
+
+function truth(value) {
+    if (typeof value === "boolean") {
+        return value;
+    }
+    throw new LogoError(`Invalid type for condition: ${typeName(value)}`);
+}
+
+function fromWord(word) {
+    if (NUMBER.test(word)) {
+        const special = word.replace(/^[+-]/, "").toLowerCase();
+        const sign = word.startsWith("-") ? -1 : 1;
+        if (special === "nan") {
+            return NaN;
+        }
+        return special.startsWith("inf") ? sign * Infinity : Number(word);
+    }
+    if (word.toUpperCase() === "TRUE") {
+        return true;
+    }
+    if (word.toUpperCase() === "FALSE") {
+        return false;
+    }
+    return word;
+}
+
+/** Write a number with as few digits as it takes to read it back, like rslogo */
+function fmt(x) {
+    if (Number.isNaN(x)) {
+        return "NaN";
+    }
+    if (!Number.isFinite(x)) {
+        return x > 0 ? "inf" : "-inf";
+    }
+    let written = "";
+    for (let precision = 0; precision < 17; precision++) {
+        written = x.toExponential(precision);
+        if (Number(written) === x) {
+            break;
+        }
+    }
+    const [mantissa, exponent] = written.split("e");
+    const sign = mantissa.startsWith("-") || Object.is(x, -0) ? "-" : "";
+    const digits = mantissa.replace("-", "").replace(".", "");
+    const point = Number(exponent) + 1;
+    if (point <= 0) {
+        return `${sign}0.${"0".repeat(-point)}${digits}`;
+    }
+    if (point >= digits.length) {
+        return `${sign}${digits}${"0".repeat(point - digits.length)}`;
+    }
+    return `${sign}${digits.slice(0, point)}.${digits.slice(point)}`;
+}
+
+function text(value) {
+    switch (typeof value) {
+        case "boolean":
+            return value ? "TRUE" : "FALSE";
+        case "number":
+            return fmt(value);
+        case "string":
+            return value;
+        default:
+            return `[${printed(value)}]`;
+    }
+}
+
+function printed(value) {
+    return Array.isArray(value) ? value.map(text).join(" ") : text(value);
+}
+
+// Expressions
+
+function arithmetic(operation, name, lhs, rhs) {
+    if (typeof lhs === "number" && typeof rhs === "number") {
+        return operation(lhs, rhs);
+    }
+    throw new LogoError(`Unsupported operation: ${name}`);
+}
+
+function add(lhs, rhs) {
+    return arithmetic((a, b) => a + b, "addition of booleans", lhs, rhs);
+}
+
+function sub(lhs, rhs) {
+    return arithmetic((a, b) => a - b, "subtraction of booleans", lhs, rhs);
+}
+
+function mul(lhs, rhs) {
+    return arithmetic((a, b) => a * b, "multiplication of booleans", lhs, rhs);
+}
+
+function div(lhs, rhs) {
+    if (rhs === 0) {
+        throw new LogoError("Division by zero");
+    }
+    return arithmetic((a, b) => a / b, "division of booleans", lhs, rhs);
+}
+
+function division(lhs, rhs) {
+    lhs = number(lhs, "dividend");
+    rhs = number(rhs, "divisor");
+    if (rhs === 0) {
+        throw new LogoError("Division by zero");
+    }
+    return [lhs, rhs];
+}
+
+function quotient(lhs, rhs) {
+    [lhs, rhs] = division(lhs, rhs);
+    return Math.trunc(lhs / rhs);
+}
+
+function remainder(lhs, rhs) {
+    [lhs, rhs] = division(lhs, rhs);
+    return lhs % rhs;
+}
+
+function compare(operation, lhs, rhs) {
+    const kind = typeof lhs;
+    if (kind === typeof rhs && (kind === "number" || kind === "boolean")) {
+        return operation(lhs, rhs);
+    }
+    throw new LogoError("Unsupported operation: comparison of different types");
+}
+
+function eq(lhs, rhs) {
+    return compare((a, b) => a === b, lhs, rhs);
+}
+
+function ne(lhs, rhs) {
+    return compare((a, b) => a !== b, lhs, rhs);
+}
+
+function gt(lhs, rhs) {
+    return compare((a, b) => a > b, lhs, rhs);
+}
+
+function lt(lhs, rhs) {
+    return compare((a, b) => a < b, lhs, rhs);
+}
+
+function logical(operation, lhs, rhs) {
+    if (typeof lhs === "boolean" && typeof rhs === "boolean") {
+        return operation(lhs, rhs);
+    }
+    throw new LogoError("Unsupported operation: logical operation of non-booleans");
+}
+
+function both(lhs, rhs) {
+    return logical((a, b) => a && b, lhs, rhs);
+}
+
+function either(lhs, rhs) {
+    return logical((a, b) => a || b, lhs, rhs);
+}
+
+function get(name) {
+    name = wordOf(name, "variable name");
+    if (!V.has(name)) {
+        throw new LogoError(`Variable not found: ${name}`);
+    }
+    return V.get(name);
+}
+
+function xcor() {
+    return T.x;
+}
+
+function ycor() {
+    return T.y;
+}
+
+function heading() {
+    return T.heading;
+}
+
+function colour() {
+    return T.colour;
+}
+
+function readWord() {
+    const line = prompt();
+    return line === null ? "" : fromWord(line.trim());
+}
+
+function readList() {
+    return (prompt() ?? "").split(/\s+/).filter((x) => x !== "").map(fromWord);
+}
+
+function word(lhs, rhs) {
+    if (Array.isArray(lhs) || Array.isArray(rhs)) {
+        throw new LogoError("Invalid type for word: list");
+    }
+    return fromWord(text(lhs) + text(rhs));
+}
+
+function wordp(value) {
+    return !Array.isArray(value);
+}
+
+function numberp(value) {
+    return typeof value === "number";
+}
+
+function integerp(value) {
+    return Number.isInteger(value);
+}
+
+function count(value) {
+    return Array.isArray(value) ? value.length : [...text(value)].length;
+}
+
+function getProperty(name, prop) {
+    name = nameOf(name, "property list name");
+    prop = nameOf(prop, "property name");
+    const properties = PROPERTIES.get(name) ?? new Map();
+    if (!properties.has(prop)) {
+        throw new LogoError(`Property not found: ${prop} in ${name}`);
+    }
+    return properties.get(prop);
+}
+
+function propertyList(name) {
+    const properties = PROPERTIES.get(nameOf(name, "property list name")) ?? new Map();
+    return [...properties].flatMap(([prop, value]) => [fromWord(prop), value]);
+}
+
+// Commands
+
+function penColour() {
+    return COLOURS[Math.min(Math.trunc(T.colour), COLOURS.length - 1)];
+}
+
+function truncate(x) {
+    return Number.isNaN(x) ? 0 : Math.trunc(Math.max(-2147483648, Math.min(2147483647, x)));
+}
+
+const AXES = new Map([[0, [0, 1]], [90, [1, 0]], [180, [0, -1]], [270, [-1, 0]]]);
+
+/** Headings are cut down to whole degrees, and headings along the axes are exact */
+function sinCos(heading) {
+    const degrees = ((truncate(heading) % 360) + 360) % 360;
+    if (AXES.has(degrees)) {
+        return AXES.get(degrees);
+    }
+    const radians = degrees * (Math.PI / 180);
+    return [Math.sin(radians), Math.cos(radians)];
+}
+
+function walk(angle, distance) {
+    const [sin, cos] = sinCos(T.heading + angle);
+    const end = [T.x + sin * distance, T.y - cos * distance];
+    const start = [T.x, T.y];
+    [T.x, T.y] = end;
+    if (T.down) {
+        CONTEXT.strokeStyle = penColour();
+        CONTEXT.beginPath();
+        CONTEXT.moveTo(...start);
+        CONTEXT.lineTo(...end);
+        CONTEXT.stroke();
+    }
+}
+
+function forward(distance) {
+    walk(0, number(distance, "distance"));
+}
+
+function back(distance) {
+    walk(180, number(distance, "distance"));
+}
+
+function left(distance) {
+    walk(-90, number(distance, "distance"));
+}
+
+function right(distance) {
+    walk(90, number(distance, "distance"));
+}
+
+function penUp() {
+    T.down = false;
+}
+
+function penDown() {
+    T.down = true;
+}
+
+function setPenColour(value) {
+    value = number(value, "pen colour");
+    if (!(value >= 0 && value <= 15)) {
+        throw new LogoError(`Colour out of range: ${fmt(value)}`);
+    }
+    T.colour = value;
+}
+
+function angle(value) {
+    value = number(value, "angle");
+    if (!(value >= 0 && value <= 360)) {
+        throw new LogoError(`Angle out of range: ${fmt(value)}`);
+    }
+    return value;
+}
+
+function turn(value) {
+    T.heading = T.heading + angle(value);
+}
+
+function setHeading(value) {
+    T.heading = angle(value);
+}
+
+function setX(value) {
+    T.x = number(value, "coordinate");
+}
+
+function setY(value) {
+    T.y = number(value, "coordinate");
+}
+
+function label(value) {
+    // Text normally runs to the right, which is a heading of 90 degrees
+    CONTEXT.save();
+    CONTEXT.translate(T.x, T.y);
+    CONTEXT.rotate(((T.heading - 90) * Math.PI) / 180);
+    CONTEXT.fillStyle = penColour();
+    CONTEXT.font = `${T.labelHeight}px ${T.font}`;
+    CONTEXT.fillText(text(value), 0, 0);
+    CONTEXT.restore();
+}
+
+function setLabelHeight(value) {
+    value = number(value, "label height");
+    if (!(value > 0 && Number.isFinite(value))) {
+        throw new LogoError(`Label height must be positive: ${fmt(value)}`);
+    }
+    T.labelHeight = value;
+}
+
+function setFont(value) {
+    T.font = wordOf(value, "font");
+}
+
+/** Write text to the console, which can only write whole lines */
+function write(value) {
+    const lines = (OUTPUT + value).split("\n");
+    OUTPUT = lines.pop();
+    lines.forEach((x) => console.log(x));
+}
+
+function printValue(value) {
+    write(`${printed(value)}\n`);
+}
+
+function show(value) {
+    write(`${text(value)}\n`);
+}
+
+function typeValue(value) {
+    write(printed(value));
+}
+
+function make(name, value) {
+    V.set(wordOf(name, "variable name"), value);
+}
+
+function increment(name, value) {
+    name = wordOf(name, "variable name");
+    if (!V.has(name)) {
+        throw new LogoError(`Variable not found: ${name}`);
+    }
+    const sum = number(V.get(name), "increment target") + number(value, "incrementing a float");
+    V.set(name, sum);
+}
+
+function putProperty(name, prop, value) {
+    name = nameOf(name, "property list name");
+    prop = nameOf(prop, "property name");
+    if (!PROPERTIES.has(name)) {
+        PROPERTIES.set(name, new Map());
+    }
+    PROPERTIES.get(name).set(prop, value);
+}
+
+function removeProperty(name, prop) {
+    name = nameOf(name, "property list name");
+    const properties = PROPERTIES.get(name);
+    if (properties !== undefined) {
+        properties.delete(nameOf(prop, "property name"));
+        if (properties.size === 0) {
+            PROPERTIES.delete(name);
+        }
+    }
+}
+
+function define(name, procedure) {
+    PROCEDURES.set(name, procedure);
+}
+
+function fetchProcedure(name) {
+    const procedure = PROCEDURES.get(name);
+    if (procedure === undefined) {
+        throw new LogoError("Unsuccessful operation: fetching a procedure");
+    }
+    return procedure;
+}
+
+function apply(name, args) {
+    name = wordOf(name, "procedure name");
+    if (!Array.isArray(args)) {
+        throw new LogoError(`Invalid type for arguments: ${typeName(args)}`);
+    }
+    const procedure = fetchProcedure(name);
+    if (args.length !== procedure.length) {
+        throw new LogoError(
+            `${name} takes ${procedure.length} input(s), but was given ${args.length}`,
+        );
+    }
+    procedure(...args);
+}
@@ -0,0 +1,469 @@
+# The runtime below works the same way as rslogo, so the program draws the same thing. Numbers
+# are double precision, the same as in rslogo.
+
+import math
+import re
+import sys
+import turtle
+
+
+class LogoError(Exception):
+    """An error which stops the program, as it would stop rslogo"""
+
+
+COLOURS = [
+    (0, 0, 0), (0, 0, 255), (0, 255, 255), (0, 255, 0),
+    (255, 0, 0), (255, 0, 255), (255, 255, 0), (255, 255, 255),
+    (165, 42, 42), (210, 180, 140), (34, 139, 34), (127, 255, 212),
+    (250, 128, 114), (128, 0, 128), (255, 165, 0), (128, 128, 128),
+]
+NUMBER = re.compile(r"[+-]?(\d+\.?\d*(e[+-]?\d+)?|\.\d+(e[+-]?\d+)?|inf|infinity|nan)", re.I)
+
+
+class TurtleState:
+    x = WIDTH / 2
+    y = HEIGHT / 2
+    heading = 0.0
+    down = False
+    colour = 0.0
+    label_height = 12.0
+    font = "sans-serif"
+
+
+T = TurtleState()
+V = {}
+PROPERTIES = {}
+PROCEDURES = {}
+PEN = None
+
+
+def run(main):
+    global PEN
+    screen = turtle.Screen()
+    screen.setup(WIDTH, HEIGHT)
+    screen.title("rslogo")
+    screen.colormode(255)
+    screen.tracer(0)
+    PEN = turtle.Turtle()
+    PEN.hideturtle()
+    PEN.penup()
+    failed = False
+    try:
+        main()
+    except LogoError as e:
+        print(f"Error: {e}", file=sys.stderr)
+        failed = True
+    sys.stdout.flush()
+    screen.update()
+    turtle.done()
+    sys.exit(1 if failed else 0)
+
+
+# Values
+
+
+def type_name(value):
+    if isinstance(value, bool):
+        return "boolean"
+    if isinstance(value, float):
+        return "float"
+    if isinstance(value, str):
+        return "string"
+    return "list"
+
+
+def number(value, field):
+    if isinstance(value, float):
+        return value
+    raise LogoError(f"Invalid type for {field}: {type_name(value)}")
+
+
+def word_of(value, field):
+    if isinstance(value, str):
+        return value
+    raise LogoError(f"Invalid type for {field}: {type_name(value)}")
+
+
+def name_of(value, field):
+    if isinstance(value, list):
+        raise LogoError(f"Invalid type for {field}: list")
+    return text(value)
+
+
+def truth(value):
+    if isinstance(value, bool):
+        return value
+    raise LogoError(f"Invalid type for condition: {type_name(value)}")
+
+
+def from_word(word):
+    if NUMBER.fullmatch(word):
+        return float(word)
+    if word.upper() == "TRUE":
+        return True
+    if word.upper() == "FALSE":
+        return False
+    return word
+
+
+def fmt(x):
+    """Write a number with as few digits as it takes to read it back, like rslogo"""
+    if math.isnan(x):
+        return "NaN"
+    if math.isinf(x):
+        return "inf" if x > 0 else "-inf"
+    for precision in range(17):
+        written = f"{x:.{precision}e}"
+        if float(written) == x:
+            break
+    mantissa, exponent = written.split("e")
+    sign = "-" if mantissa.startswith("-") else ""
+    digits = mantissa.lstrip("-").replace(".", "")
+    point = int(exponent) + 1
+    if point <= 0:
+        return f"{sign}0.{'0' * -point}{digits}"
+    if point >= len(digits):
+        return f"{sign}{digits}{'0' * (point - len(digits))}"
+    return f"{sign}{digits[:point]}.{digits[point:]}"
+
+
+def text(value):
+    if isinstance(value, bool):
+        return "TRUE" if value else "FALSE"
+    if isinstance(value, float):
+        return fmt(value)
+    if isinstance(value, str):
+        return value
+    return f"[{printed(value)}]"
+
+
+def printed(value):
+    if isinstance(value, list):
+        return " ".join(text(x) for x in value)
+    return text(value)
+
+
+# Expressions
+
+
+def arithmetic(operation, name, lhs, rhs):
+    if isinstance(lhs, float) and isinstance(rhs, float):
+        return operation(lhs, rhs)
+    raise LogoError(f"Unsupported operation: {name}")
+
+
+def add(lhs, rhs):
+    return arithmetic(lambda a, b: a + b, "addition of booleans", lhs, rhs)
+
+
+def sub(lhs, rhs):
+    return arithmetic(lambda a, b: a - b, "subtraction of booleans", lhs, rhs)
+
+
+def mul(lhs, rhs):
+    return arithmetic(lambda a, b: a * b, "multiplication of booleans", lhs, rhs)
+
+
+def div(lhs, rhs):
+    if isinstance(rhs, float) and rhs == 0:
+        raise LogoError("Division by zero")
+    return arithmetic(lambda a, b: a / b, "division of booleans", lhs, rhs)
+
+
+def division(lhs, rhs):
+    lhs, rhs = number(lhs, "dividend"), number(rhs, "divisor")
+    if rhs == 0:
+        raise LogoError("Division by zero")
+    return lhs, rhs
+
+
+def quotient(lhs, rhs):
+    lhs, rhs = division(lhs, rhs)
+    result = lhs / rhs
+    return float(math.trunc(result)) if math.isfinite(result) else result
+
+
+def remainder(lhs, rhs):
+    lhs, rhs = division(lhs, rhs)
+    return math.fmod(lhs, rhs) if math.isfinite(lhs) else math.nan
+
+
+def compare(operation, lhs, rhs):
+    if type(lhs) is type(rhs) and isinstance(lhs, (bool, float)):
+        return operation(lhs, rhs)
+    raise LogoError("Unsupported operation: comparison of different types")
+
+
+def eq(lhs, rhs):
+    return compare(lambda a, b: a == b, lhs, rhs)
+
+
+def ne(lhs, rhs):
+    return compare(lambda a, b: a != b, lhs, rhs)
+
+
+def gt(lhs, rhs):
+    return compare(lambda a, b: a > b, lhs, rhs)
+
+
+def lt(lhs, rhs):
+    return compare(lambda a, b: a < b, lhs, rhs)
+
+
+def logical(operation, lhs, rhs):
+    if isinstance(lhs, bool) and isinstance(rhs, bool):
+        return operation(lhs, rhs)
+    raise LogoError("Unsupported operation: logical operation of non-booleans")
+
+
+def both(lhs, rhs):
+    return logical(lambda a, b: a and b, lhs, rhs)
+
+
+def either(lhs, rhs):
+    return logical(lambda a, b: a or b, lhs, rhs)
+
+
+def get(name):
+    name = word_of(name, "variable name")
+    if name not in V:
+        raise LogoError(f"Variable not found: {name}")
+    return V[name]
+
+
+def xcor():
+    return T.x
+
+
+def ycor():
+    return T.y
+
+
+def heading():
+    return T.heading
+
+
+def colour():
+    return T.colour
+
+
+def read_line():
+    line = sys.stdin.readline()
+    return line.rstrip("\r\n") if line else None
+
+
+def read_word():
+    line = read_line()
+    return "" if line is None else from_word(line.strip())
+
+
+def read_list():
+    return [from_word(x) for x in (read_line() or "").split()]
+
+
+def word(lhs, rhs):
+    if isinstance(lhs, list) or isinstance(rhs, list):
+        raise LogoError("Invalid type for word: list")
+    return from_word(text(lhs) + text(rhs))
+
+
+def wordp(value):
+    return not isinstance(value, list)
+
+
+def numberp(value):
+    return isinstance(value, float)
+
+
+def integerp(value):
+    return isinstance(value, float) and value.is_integer()
+
+
+def count(value):
+    return float(len(value) if isinstance(value, list) else len(text(value)))
+
+
+def get_property(name, prop):
+    name = name_of(name, "property list name")
+    prop = name_of(prop, "property name")
+    properties = PROPERTIES.get(name, {})
+    if prop not in properties:
+        raise LogoError(f"Property not found: {prop} in {name}")
+    return properties[prop]
+
+
+def property_list(name):
+    properties = PROPERTIES.get(name_of(name, "property list name"), {})
+    return [x for prop, value in properties.items() for x in (from_word(prop), value)]
+
+
+# Commands
+
+
+def screen(x, y):
+    return (x - WIDTH / 2, HEIGHT / 2 - y)
+
+
+def pen_colour():
+    return COLOURS[min(int(T.colour), len(COLOURS) - 1)]
+
+
+def truncate(x):
+    return 0 if math.isnan(x) else int(max(-2147483648, min(2147483647, x)))
+
+
+AXES = {0: (0.0, 1.0), 90: (1.0, 0.0), 180: (0.0, -1.0), 270: (-1.0, 0.0)}
+
+
+def sin_cos(heading):
+    """Headings are cut down to whole degrees, and headings along the axes are exact"""
+    degrees = truncate(heading) % 360
+    if degrees in AXES:
+        return AXES[degrees]
+    radians = math.radians(degrees)
+    return (math.sin(radians), math.cos(radians))
+
+
+def walk(angle, distance):
+    sin, cos = sin_cos(T.heading + angle)
+    end = (T.x + sin * distance, T.y - cos * distance)
+    start = (T.x, T.y)
+    T.x, T.y = end
+    if T.down:
+        PEN.pencolor(pen_colour())
+        PEN.penup()
+        PEN.goto(screen(*start))
+        PEN.pendown()
+        PEN.goto(screen(*end))
+        PEN.penup()
+
+
+def forward(distance):
+    walk(0, number(distance, "distance"))
+
+
+def back(distance):
+    walk(180, number(distance, "distance"))
+
+
+def left(distance):
+    walk(-90, number(distance, "distance"))
+
+
+def right(distance):
+    walk(90, number(distance, "distance"))
+
+
+def pen_up():
+    T.down = False
+
+
+def pen_down():
+    T.down = True
+
+
+def set_pen_colour(value):
+    value = number(value, "pen colour")
+    if not 0 <= value <= 15:
+        raise LogoError(f"Colour out of range: {fmt(value)}")
+    T.colour = value
+
+
+def angle(value):
+    value = number(value, "angle")
+    if not 0 <= value <= 360:
+        raise LogoError(f"Angle out of range: {fmt(value)}")
+    return value
+
+
+def turn(value):
+    T.heading = T.heading + angle(value)
+
+
+def set_heading(value):
+    T.heading = angle(value)
+
+
+def set_x(value):
+    T.x = number(value, "coordinate")
+
+
+def set_y(value):
+    T.y = number(value, "coordinate")
+
+
+def label(value):
+    # Turtle graphics can't rotate text, so labels always run to the right
+    PEN.penup()
+    PEN.goto(screen(T.x, T.y))
+    PEN.pencolor(pen_colour())
+    PEN.write(text(value), font=(T.font, max(1, round(T.label_height)), "normal"))
+
+
+def set_label_height(value):
+    value = number(value, "label height")
+    if not (value > 0 and math.isfinite(value)):
+        raise LogoError(f"Label height must be positive: {fmt(value)}")
+    T.label_height = value
+
+
+def set_font(value):
+    T.font = word_of(value, "font")
+
+
+def print_value(value):
+    sys.stdout.write(printed(value) + "\n")
+
+
+def show(value):
+    sys.stdout.write(text(value) + "\n")
+
+
+def type_value(value):
+    sys.stdout.write(printed(value))
+    sys.stdout.flush()
+
+
+def make(name, value):
+    V[word_of(name, "variable name")] = value
+
+
+def increment(name, value):
+    name = word_of(name, "variable name")
+    if name not in V:
+        raise LogoError(f"Variable not found: {name}")
+    V[name] = number(V[name], "increment target") + number(value, "incrementing a float")
+
+
+def put_property(name, prop, value):
+    name = name_of(name, "property list name")
+    PROPERTIES.setdefault(name, {})[name_of(prop, "property name")] = value
+
+
+def remove_property(name, prop):
+    name = name_of(name, "property list name")
+    properties = PROPERTIES.get(name, {})
+    properties.pop(name_of(prop, "property name"), None)
+    if not properties:
+        PROPERTIES.pop(name, None)
+
+
+def define(name, procedure):
+    PROCEDURES[name] = procedure
+
+
+def fetch_procedure(name):
+    if name not in PROCEDURES:
+        raise LogoError("Unsuccessful operation: fetching a procedure")
+    return PROCEDURES[name]
+
+
+def apply(name, arguments):
+    name = word_of(name, "procedure name")
+    if not isinstance(arguments, list):
+        raise LogoError(f"Invalid type for arguments: {type_name(arguments)}")
+    procedure = fetch_procedure(name)
+    inputs = procedure.__code__.co_argcount
+    if len(arguments) != inputs:
+        raise LogoError(f"{name} takes {inputs} input(s), but was given {len(arguments)}")
+    procedure(*arguments)
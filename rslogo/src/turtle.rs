@@ -1,34 +1,75 @@
-use crate::errors::TurtleError;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    canvas::{Label, Line},
+    errors::TurtleError,
+    space::{self, Segment, Vec3},
+};
 
 /// The turtle is a construct in Logo which is responsible for generating the resulting image.
 /// A Logo program is effectively a set of instructions on manipulating the turtle to produce the
 /// desired image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Turtle {
-    x: f32,
-    y: f32,
-    heading: f32,
+    x: f64,
+    y: f64,
+    heading: f64,
     pen_state: PenState,
-    pen_color: f32,
+    pen_color: f64,
+    label_height: f64,
+    font: String,
+
+    /// How far the turtle is in front of the canvas, when it moves in 3D
+    #[serde(default)]
+    z: f64,
+
+    /// The direction the turtle faces when it moves in 3D, which is up the canvas to begin with
+    #[serde(default = "Turtle::level_forward")]
+    forward: Vec3,
+
+    /// The direction the top of the turtle's shell faces when it moves in 3D, which is out of the
+    /// canvas to begin with
+    #[serde(default = "Turtle::level_up")]
+    up: Vec3,
+}
+
+impl Default for Turtle {
+    fn default() -> Self {
+        Turtle::new()
+    }
 }
 
 impl Turtle {
     /// Returns a new instance of Turtle with default values
     pub fn new() -> Self {
         Turtle {
-            x: 0_f32,
-            y: 0_f32,
-            heading: 0_f32,
+            x: 0_f64,
+            y: 0_f64,
+            heading: 0_f64,
             pen_state: PenState::Up,
-            pen_color: 0_f32,
+            pen_color: 0_f64,
+            label_height: 12_f64,
+            font: String::from("sans-serif"),
+            z: 0_f64,
+            forward: Turtle::level_forward(),
+            up: Turtle::level_up(),
         }
     }
 
+    fn level_forward() -> Vec3 {
+        [0_f64, -1_f64, 0_f64]
+    }
+
+    fn level_up() -> Vec3 {
+        [0_f64, 0_f64, 1_f64]
+    }
+
     /// Validates and sets the value for the pen colour for the turtle
     /// Returns the current pen colour when successful, and a ColourOutOfRange
     /// error otherwise.
-    pub fn set_pen_colour(&mut self, value: f32) -> Result<f32, TurtleError> {
+    pub fn set_pen_colour(&mut self, value: f64) -> Result<f64, TurtleError> {
         match value {
-            0_f32..=15_f32 => {
+            0_f64..=15_f64 => {
                 self.pen_color = value;
                 Ok(self.pen_color)
             }
@@ -37,11 +78,14 @@ impl Turtle {
     }
 
     /// Set the heading of the turtle to the given value. Returns the
-    /// current heading of the turtle when successful, and an error otherwise
-    pub fn set_heading(&mut self, angle: f32) -> Result<f32, TurtleError> {
+    /// current heading of the turtle when successful, and an error otherwise.
+    /// In 3D, the turtle is also levelled out, so it faces along the canvas with its shell up.
+    pub fn set_heading(&mut self, angle: f64) -> Result<f64, TurtleError> {
         match angle {
-            0_f32..=360_f32 => {
+            0_f64..=360_f64 => {
                 self.heading = angle;
+                (self.forward, self.up) = (Turtle::level_forward(), Turtle::level_up());
+                self.forward = space::rotate(self.forward, self.right(), angle).0;
                 Ok(self.heading)
             }
             _ => Err(TurtleError::AngleOutOfRange(angle)),
@@ -50,9 +94,9 @@ impl Turtle {
 
     /// Increments the heading of the turtle with the given value. Returns the
     /// current heading of the turtle when successful, and an error otherwise
-    pub fn turn(&mut self, angle: f32) -> Result<f32, TurtleError> {
+    pub fn turn(&mut self, angle: f64) -> Result<f64, TurtleError> {
         match angle {
-            0_f32..=360_f32 => {
+            0_f64..=360_f64 => {
                 self.heading += angle;
                 Ok(self.heading)
             }
@@ -60,11 +104,61 @@ impl Turtle {
         }
     }
 
+    /// Turns the turtle to its right by the given angle, about the top of its shell, when it
+    /// moves in 3D. Returns the heading it faces across the canvas.
+    pub fn yaw(&mut self, angle: f64) -> Result<f64, TurtleError> {
+        let angle: f64 = Turtle::check_angle(angle)?;
+        self.forward = space::rotate(self.forward, self.right(), angle).0;
+        Ok(self.orient())
+    }
+
+    /// Tilts the turtle's nose up by the given angle, when it moves in 3D. Returns the heading it
+    /// faces across the canvas.
+    pub fn pitch(&mut self, angle: f64) -> Result<f64, TurtleError> {
+        let angle: f64 = Turtle::check_angle(angle)?;
+        (self.forward, self.up) = space::rotate(self.forward, self.up, angle);
+        Ok(self.orient())
+    }
+
+    /// Rolls the turtle onto its right side by the given angle, when it moves in 3D. Returns the
+    /// heading it faces across the canvas.
+    pub fn roll(&mut self, angle: f64) -> Result<f64, TurtleError> {
+        let angle: f64 = Turtle::check_angle(angle)?;
+        self.up = space::rotate(self.up, self.right(), angle).0;
+        Ok(self.orient())
+    }
+
+    /// Angles in 3D are given the same way as to `turn`. Turning the other way, such as with
+    /// `DOWN`, is the same as turning the rest of the way round.
+    fn check_angle(angle: f64) -> Result<f64, TurtleError> {
+        match angle {
+            0_f64..=360_f64 => Ok(angle),
+            _ => Err(TurtleError::AngleOutOfRange(angle)),
+        }
+    }
+
+    /// The direction to the turtle's right
+    fn right(&self) -> Vec3 {
+        space::cross(self.up, self.forward)
+    }
+
+    /// Keep the turtle's directions at right angles to each other after it rotates, so rounding
+    /// errors don't build up, and work out the heading it faces across the canvas
+    fn orient(&mut self) -> f64 {
+        self.forward = space::normalise(self.forward);
+        self.up = space::normalise(space::cross(self.forward, self.right()));
+        self.heading = self.forward[0]
+            .atan2(-self.forward[1])
+            .to_degrees()
+            .rem_euclid(360_f64);
+        self.heading
+    }
+
     pub fn set_coordinates(
         &mut self,
-        x: Option<f32>,
-        y: Option<f32>,
-    ) -> Result<(f32, f32), TurtleError> {
+        x: Option<f64>,
+        y: Option<f64>,
+    ) -> Result<(f64, f64), TurtleError> {
         match (x, y) {
             (None, None) => Ok((self.x, self.y)),
             (None, Some(y)) => {
@@ -85,9 +179,9 @@ impl Turtle {
 
     pub fn move_turtle(
         &mut self,
-        x: Option<f32>,
-        y: Option<f32>,
-    ) -> Result<(f32, f32), TurtleError> {
+        x: Option<f64>,
+        y: Option<f64>,
+    ) -> Result<(f64, f64), TurtleError> {
         match (x, y) {
             (None, None) => Ok((self.x, self.y)),
             (None, Some(y)) => {
@@ -106,6 +200,91 @@ impl Turtle {
         }
     }
 
+    /// Moves the turtle the given distance, in a direction relative to its heading (so `90` is to
+    /// its right). Returns the line it travelled along, whether or not the pen is down.
+    pub fn walk(&mut self, angle: f64, distance: f64) -> Line {
+        let start: (f64, f64) = (self.x, self.y);
+        let heading: f64 = self.heading + angle;
+        let (sin, cos) = Turtle::sin_cos(heading);
+        let (x, y) = (self.x + sin * distance, self.y - cos * distance);
+        self.x = x;
+        self.y = y;
+
+        Line {
+            start,
+            end: (x, y),
+            heading,
+            length: distance,
+            colour: self.pen_color as usize,
+        }
+    }
+
+    /// The sine and cosine of a heading, which is cut down to whole degrees as lines are drawn at
+    /// whole degrees in images. Headings along the axes are exact, so moving along them doesn't
+    /// drift.
+    fn sin_cos(heading: f64) -> (f64, f64) {
+        match (heading as i32).rem_euclid(360) {
+            0 => (0.0, 1.0),
+            90 => (1.0, 0.0),
+            180 => (0.0, -1.0),
+            270 => (-1.0, 0.0),
+            degrees => f64::from(degrees).to_radians().sin_cos(),
+        }
+    }
+
+    /// Moves the turtle the given distance in 3D, in a direction relative to the way it faces (so
+    /// `90` is to its right). Returns the segment it travelled along, whether or not the pen is
+    /// down.
+    pub fn walk_3d(&mut self, angle: f64, distance: f64) -> Segment {
+        let start: Vec3 = [self.x, self.y, self.z];
+        let direction: Vec3 = space::rotate(self.forward, self.right(), angle).0;
+        let [x, y, z] = space::add(start, space::scale(direction, distance));
+        (self.x, self.y, self.z) = (x, y, z);
+
+        Segment {
+            start,
+            end: [x, y, z],
+            colour: self.pen_color as usize,
+        }
+    }
+
+    pub fn set_z(&mut self, z: f64) -> f64 {
+        self.z = z;
+        self.z
+    }
+
+    pub fn get_z(&self) -> f64 {
+        self.z
+    }
+
+    /// Sets the height of the text written by `label`. Returns the new height when successful,
+    /// and a LabelHeightOutOfRange error otherwise.
+    pub fn set_label_height(&mut self, height: f64) -> Result<f64, TurtleError> {
+        if height > 0_f64 && height.is_finite() {
+            self.label_height = height;
+            Ok(self.label_height)
+        } else {
+            Err(TurtleError::LabelHeightOutOfRange(height))
+        }
+    }
+
+    /// Sets the font family of the text written by `label`
+    pub fn set_font(&mut self, font: String) {
+        self.font = font;
+    }
+
+    /// Writes text at the turtle's position, running along its heading. The turtle doesn't move.
+    pub fn label(&self, text: String) -> Label {
+        Label {
+            position: (self.x, self.y),
+            heading: self.heading,
+            text,
+            height: self.label_height,
+            font: self.font.clone(),
+            colour: self.pen_color as usize,
+        }
+    }
+
     pub fn set_pen_state(&mut self, state: PenState) -> &PenState {
         self.pen_state = state;
         &self.pen_state
@@ -115,20 +294,29 @@ impl Turtle {
         &self.pen_state
     }
 
-    pub(crate) fn get_turtle_coords(&self) -> (f32, f32) {
+    pub fn get_turtle_coords(&self) -> (f64, f64) {
         (self.x, self.y)
     }
 
-    pub fn get_pen_colour(&self) -> f32 {
+    pub fn get_pen_colour(&self) -> f64 {
         self.pen_color
     }
 
-    pub fn get_heading(&self) -> f32 {
+    pub fn get_heading(&self) -> f64 {
         self.heading
     }
+
+    pub fn get_label_height(&self) -> f64 {
+        self.label_height
+    }
+
+    pub fn get_font(&self) -> &str {
+        &self.font
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum PenState {
     Up,
     Down,
@@ -0,0 +1,169 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+    time::Duration,
+};
+
+use notify::{
+    event::{AccessKind, AccessMode, ModifyKind},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+
+use rslogo::loader::Loader;
+
+use crate::batch::{render_file, RenderOptions};
+
+/// How long to wait for an editor to finish saving, after the first change is seen
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// An error which stops a program being watched for changes
+#[derive(thiserror::Error, Debug, miette::Diagnostic)]
+pub enum WatchError {
+    #[error("Failed to find {}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to watch for changes")]
+    Notify(#[from] notify::Error),
+
+    #[error("Stopped watching for changes")]
+    Stopped,
+}
+
+/// Renders a program again whenever it, or a file it loads, is saved.
+///
+/// The directories of the files are watched, rather than the files themselves, so files which
+/// editors save by replacing them are still noticed. The files to watch are found again on every
+/// render, so adding a `LOAD` starts watching the file it names.
+pub struct Watch {
+    source: PathBuf,
+    image: PathBuf,
+    options: RenderOptions,
+
+    /// Stops watching when dropped
+    watcher: RecommendedWatcher,
+
+    events: Receiver<notify::Result<Event>>,
+
+    /// The directories being watched
+    watched: HashSet<PathBuf>,
+
+    /// The files read by the last render, which are the only ones worth rendering again for
+    files: HashSet<PathBuf>,
+}
+
+impl Watch {
+    pub fn new(source: &Path, image: &Path, options: RenderOptions) -> Result<Self, WatchError> {
+        let (sender, events) = channel();
+        Ok(Watch {
+            source: source.to_path_buf(),
+            image: image.to_path_buf(),
+            options,
+            watcher: notify::recommended_watcher(sender)?,
+            events,
+            watched: HashSet::new(),
+            files: HashSet::new(),
+        })
+    }
+
+    /// Render the program, then start watching every file it read. The image is only replaced if
+    /// the program succeeds, so the last good drawing is kept while a program is broken.
+    pub fn render(&mut self) -> Result<miette::Result<()>, WatchError> {
+        let mut loader = Loader::new(self.options.include.clone());
+        let result: miette::Result<()> =
+            render_file(&mut loader, &self.source, &self.image, &self.options);
+
+        // The source is watched even if it couldn't be read, in case it is created later
+        let source: PathBuf = self
+            .source
+            .canonicalize()
+            .or_else(|_| std::path::absolute(&self.source))
+            .map_err(|e| WatchError::Io {
+                path: self.source.clone(),
+                source: e,
+            })?;
+        self.files = loader.dependencies().into_iter().collect();
+        self.files.insert(source);
+
+        let dirs: HashSet<PathBuf> = self
+            .files
+            .iter()
+            .filter_map(|x: &PathBuf| x.parent())
+            .map(Path::to_path_buf)
+            .collect();
+        for dir in dirs.difference(&self.watched) {
+            self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+        for dir in self.watched.difference(&dirs) {
+            // The directory may have been removed, which stops it being watched anyway
+            let _ = self.watcher.unwatch(dir);
+        }
+        self.watched = dirs;
+        Ok(result)
+    }
+
+    /// Wait until one of the files read by the last render is changed. Editors often change a
+    /// file several times while saving it, so this waits for them to finish.
+    pub fn wait(&mut self) -> Result<(), WatchError> {
+        loop {
+            let event: Event = self.events.recv().map_err(|_| WatchError::Stopped)??;
+            if is_change(&event.kind) && event.paths.iter().any(|x| self.files.contains(x)) {
+                break;
+            }
+        }
+        while self.events.recv_timeout(SETTLE_TIME).is_ok() {}
+        Ok(())
+    }
+
+    /// The files read by the last render
+    pub fn files(&self) -> &HashSet<PathBuf> {
+        &self.files
+    }
+}
+
+/// Whether an event changes what a file contains, rather than only reading it. Rendering reads
+/// every file, so reads must be ignored to avoid rendering forever.
+fn is_change(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Create(_) | EventKind::Remove(_) => true,
+        EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        EventKind::Modify(_) => true,
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        EventKind::Access(_) | EventKind::Any | EventKind::Other => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_again_when_a_loaded_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let library = dir.path().join("shapes.lg");
+        let main = dir.path().join("main.lg");
+        let image = out.path().join("main.svg");
+        std::fs::write(&library, "TO Line\n  FORWARD \"10\nEND\n").unwrap();
+        std::fs::write(&main, "LOAD \"shapes.lg\nPENDOWN\nLine\n").unwrap();
+
+        let mut watch = Watch::new(&main, &image, RenderOptions::default()).unwrap();
+        watch.render().unwrap().unwrap();
+        assert_eq!(watch.files().len(), 2);
+        let drawn = std::fs::read_to_string(&image).unwrap();
+
+        // A broken library is reported, and the last good image is kept
+        std::fs::write(&library, "TO Line\n  FORWARD\nEND\n").unwrap();
+        watch.wait().unwrap();
+        assert!(watch.render().unwrap().is_err());
+        assert_eq!(std::fs::read_to_string(&image).unwrap(), drawn);
+
+        std::fs::write(&library, "TO Line\n  FORWARD \"20\nEND\n").unwrap();
+        watch.wait().unwrap();
+        watch.render().unwrap().unwrap();
+        assert_ne!(std::fs::read_to_string(&image).unwrap(), drawn);
+    }
+}